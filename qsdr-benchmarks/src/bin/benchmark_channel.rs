@@ -9,6 +9,7 @@ use kanal::{
     Receiver as KanalReceiver, Sender as KanalSender,
 };
 use qsdr::channel::{
+    WaitStrategy,
     mpsc::{
         futex::{Receiver as QsdrMpscFutexReceiver, Sender as QsdrMpscFutexSender},
         futures::{Receiver as QsdrMpscFuturesReceiver, Sender as QsdrMpscFuturesSender},
@@ -32,12 +33,46 @@ struct Args {
     /// Channel size
     #[arg(long, default_value_t = 64)]
     channel_size: usize,
+    /// Number of items forwarded at once (only batched for qsdr channels)
+    #[arg(long, default_value_t = 1)]
+    batch_size: usize,
     /// Measurement iterations
     #[arg(long, default_value_t = 10000000)]
     measurement_iters: usize,
     /// Channel type
     #[arg(long)]
     channel: ChannelType,
+    /// Wait strategy of the receivers (only for qsdr channels)
+    #[arg(long)]
+    wait_strategy: Option<WaitStrategyType>,
+    /// Number of spins of the wait strategy (maximum number for adaptive)
+    #[arg(long, default_value_t = 1 << 13)]
+    spins: u32,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
+enum WaitStrategyType {
+    /// Spin without ever sleeping
+    BusySpin,
+    /// Spin and then yield
+    SpinYield,
+    /// Spin and then sleep
+    SpinSleep,
+    /// Spin an adaptive number of times and then sleep
+    Adaptive,
+}
+
+impl Args {
+    fn wait_strategy(&self, default: WaitStrategy) -> WaitStrategy {
+        let spins = self.spins;
+        match self.wait_strategy {
+            None => default,
+            Some(WaitStrategyType::BusySpin) => WaitStrategy::BusySpin,
+            Some(WaitStrategyType::SpinYield) => WaitStrategy::SpinYield { spins },
+            Some(WaitStrategyType::SpinSleep) => WaitStrategy::SpinSleep { spins },
+            Some(WaitStrategyType::Adaptive) => WaitStrategy::Adaptive { max_spins: spins },
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, ValueEnum)]
@@ -75,15 +110,29 @@ const ITEM: [u64; ITEMSIZE] = [0; ITEMSIZE];
 trait Channel<T> {
     type Sender: Sender<T>;
     type Receiver: Receiver<T>;
-    fn bounded(size: usize) -> (Self::Sender, Self::Receiver);
+    fn bounded(size: usize, args: &Args) -> (Self::Sender, Self::Receiver);
 }
 
 trait Sender<T>: Send + 'static {
     fn send(&mut self, value: T);
+
+    // Sends all the items in the buffer, leaving it empty.
+    fn send_batch(&mut self, buf: &mut Vec<T>) {
+        for item in buf.drain(..) {
+            self.send(item);
+        }
+    }
 }
 
 trait Receiver<T>: Send + 'static {
     fn recv(&mut self) -> T;
+
+    // Receives up to max items, appending them to the buffer, and returns
+    // the number of items received.
+    fn recv_batch(&mut self, buf: &mut Vec<T>, _max: usize) -> usize {
+        buf.push(self.recv());
+        1
+    }
 }
 
 macro_rules! impl_channel {
@@ -93,7 +142,7 @@ macro_rules! impl_channel {
         impl<T: Send + 'static> Channel<T> for $alias<T> {
             type Sender = $sender<T>;
             type Receiver = $receiver<T>;
-            fn bounded(size: usize) -> (Self::Sender, Self::Receiver) {
+            fn bounded(size: usize, _args: &Args) -> (Self::Sender, Self::Receiver) {
                 $bounded(size)
             }
         }
@@ -117,8 +166,11 @@ type QsdrSpscFutex<T> = (QsdrSpscFutexSender<T>, QsdrSpscFutexReceiver<T>);
 impl<T: Send + 'static> Channel<T> for QsdrSpscFutex<T> {
     type Sender = QsdrSpscFutexSender<T>;
     type Receiver = QsdrSpscFutexReceiver<T>;
-    fn bounded(size: usize) -> (Self::Sender, Self::Receiver) {
-        qsdr::channel::spsc::futex::channel(size)
+    fn bounded(size: usize, args: &Args) -> (Self::Sender, Self::Receiver) {
+        qsdr::channel::spsc::futex::channel_with_wait_strategy(
+            size,
+            args.wait_strategy(WaitStrategy::FUTEX_DEFAULT),
+        )
     }
 }
 
@@ -126,12 +178,20 @@ impl<T: Send + 'static> Sender<T> for QsdrSpscFutexSender<T> {
     fn send(&mut self, value: T) {
         QsdrSpscFutexSender::send(self, value)
     }
+
+    fn send_batch(&mut self, buf: &mut Vec<T>) {
+        QsdrSpscFutexSender::send_batch(self, buf.drain(..))
+    }
 }
 
 impl<T: Send + 'static> Receiver<T> for QsdrSpscFutexReceiver<T> {
     fn recv(&mut self) -> T {
         QsdrSpscFutexReceiver::recv(self).unwrap()
     }
+
+    fn recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> usize {
+        QsdrSpscFutexReceiver::recv_batch(self, buf, max)
+    }
 }
 
 type QsdrMpscFutex<T> = (QsdrMpscFutexSender<T>, QsdrMpscFutexReceiver<T>);
@@ -139,8 +199,11 @@ type QsdrMpscFutex<T> = (QsdrMpscFutexSender<T>, QsdrMpscFutexReceiver<T>);
 impl<T: Send + 'static> Channel<T> for QsdrMpscFutex<T> {
     type Sender = QsdrMpscFutexSender<T>;
     type Receiver = QsdrMpscFutexReceiver<T>;
-    fn bounded(size: usize) -> (Self::Sender, Self::Receiver) {
-        qsdr::channel::mpsc::futex::channel(size)
+    fn bounded(size: usize, args: &Args) -> (Self::Sender, Self::Receiver) {
+        qsdr::channel::mpsc::futex::channel_with_wait_strategy(
+            size,
+            args.wait_strategy(WaitStrategy::FUTEX_DEFAULT),
+        )
     }
 }
 
@@ -148,12 +211,20 @@ impl<T: Send + 'static> Sender<T> for QsdrMpscFutexSender<T> {
     fn send(&mut self, value: T) {
         QsdrMpscFutexSender::send(self, value)
     }
+
+    fn send_batch(&mut self, buf: &mut Vec<T>) {
+        QsdrMpscFutexSender::send_batch(self, buf.drain(..))
+    }
 }
 
 impl<T: Send + 'static> Receiver<T> for QsdrMpscFutexReceiver<T> {
     fn recv(&mut self) -> T {
         QsdrMpscFutexReceiver::recv(self).unwrap()
     }
+
+    fn recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> usize {
+        QsdrMpscFutexReceiver::recv_batch(self, buf, max)
+    }
 }
 
 impl_channel!(
@@ -174,11 +245,18 @@ impl_channel!(
 trait AsyncChannel<T> {
     type Sender: Sender<T>;
     type Receiver: AsyncReceiver<T>;
-    fn bounded(size: usize) -> (Self::Sender, Self::Receiver);
+    fn bounded(size: usize, args: &Args) -> (Self::Sender, Self::Receiver);
 }
 
 trait AsyncReceiver<T>: Send + 'static {
     async fn recv(&mut self) -> T;
+
+    // Receives up to max items, appending them to the buffer, and returns
+    // the number of items received.
+    async fn recv_batch(&mut self, buf: &mut Vec<T>, _max: usize) -> usize {
+        buf.push(self.recv().await);
+        1
+    }
 }
 
 macro_rules! impl_async_channel {
@@ -188,7 +266,7 @@ macro_rules! impl_async_channel {
         impl<T: Send + 'static> AsyncChannel<T> for $alias<T> {
             type Sender = $sender<T>;
             type Receiver = $receiver<T>;
-            fn bounded(size: usize) -> (Self::Sender, Self::Receiver) {
+            fn bounded(size: usize, _args: &Args) -> (Self::Sender, Self::Receiver) {
                 $bounded(size)
             }
         }
@@ -231,7 +309,7 @@ type FlumeBoundedAsync<T> = (FlumeSender<T>, FlumeReceiver<T>);
 impl<T: Send + 'static> AsyncChannel<T> for FlumeBoundedAsync<T> {
     type Sender = FlumeSender<T>;
     type Receiver = FlumeReceiver<T>;
-    fn bounded(size: usize) -> (Self::Sender, Self::Receiver) {
+    fn bounded(size: usize, _args: &Args) -> (Self::Sender, Self::Receiver) {
         flume::bounded(size)
     }
 }
@@ -247,8 +325,11 @@ type QsdrSpscFutures<T> = (QsdrSpscFuturesSender<T>, QsdrSpscFuturesReceiver<T>)
 impl<T: Send + 'static> AsyncChannel<T> for QsdrSpscFutures<T> {
     type Sender = QsdrSpscFuturesSender<T>;
     type Receiver = QsdrSpscFuturesReceiver<T>;
-    fn bounded(size: usize) -> (Self::Sender, Self::Receiver) {
-        qsdr::channel::spsc::futures::channel(size)
+    fn bounded(size: usize, args: &Args) -> (Self::Sender, Self::Receiver) {
+        qsdr::channel::spsc::futures::channel_with_wait_strategy(
+            size,
+            args.wait_strategy(WaitStrategy::FUTURES_DEFAULT),
+        )
     }
 }
 
@@ -256,12 +337,20 @@ impl<T: Send + 'static> Sender<T> for QsdrSpscFuturesSender<T> {
     fn send(&mut self, value: T) {
         QsdrSpscFuturesSender::send(self, value)
     }
+
+    fn send_batch(&mut self, buf: &mut Vec<T>) {
+        QsdrSpscFuturesSender::send_batch(self, buf.drain(..))
+    }
 }
 
 impl<T: Send + 'static> AsyncReceiver<T> for QsdrSpscFuturesReceiver<T> {
     async fn recv(&mut self) -> T {
         QsdrSpscFuturesReceiver::recv(self).await.unwrap()
     }
+
+    async fn recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> usize {
+        QsdrSpscFuturesReceiver::recv_batch(self, buf, max).await
+    }
 }
 
 type QsdrMpscFutures<T> = (QsdrMpscFuturesSender<T>, QsdrMpscFuturesReceiver<T>);
@@ -269,8 +358,11 @@ type QsdrMpscFutures<T> = (QsdrMpscFuturesSender<T>, QsdrMpscFuturesReceiver<T>)
 impl<T: Send + 'static> AsyncChannel<T> for QsdrMpscFutures<T> {
     type Sender = QsdrMpscFuturesSender<T>;
     type Receiver = QsdrMpscFuturesReceiver<T>;
-    fn bounded(size: usize) -> (Self::Sender, Self::Receiver) {
-        qsdr::channel::mpsc::futures::channel(size)
+    fn bounded(size: usize, args: &Args) -> (Self::Sender, Self::Receiver) {
+        qsdr::channel::mpsc::futures::channel_with_wait_strategy(
+            size,
+            args.wait_strategy(WaitStrategy::FUTURES_DEFAULT),
+        )
     }
 }
 
@@ -278,12 +370,20 @@ impl<T: Send + 'static> Sender<T> for QsdrMpscFuturesSender<T> {
     fn send(&mut self, value: T) {
         QsdrMpscFuturesSender::send(self, value)
     }
+
+    fn send_batch(&mut self, buf: &mut Vec<T>) {
+        QsdrMpscFuturesSender::send_batch(self, buf.drain(..))
+    }
 }
 
 impl<T: Send + 'static> AsyncReceiver<T> for QsdrMpscFuturesReceiver<T> {
     async fn recv(&mut self) -> T {
         QsdrMpscFuturesReceiver::recv(self).await.unwrap()
     }
+
+    async fn recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> usize {
+        QsdrMpscFuturesReceiver::recv_batch(self, buf, max).await
+    }
 }
 
 fn setup_core_ids() -> Result<(CoreId, CoreId)> {
@@ -298,27 +398,63 @@ fn setup_core_ids() -> Result<(CoreId, CoreId)> {
     Ok((core0, core1))
 }
 
+// Forwards up to batch_size items from rx to tx and returns the number of items
+// forwarded.
+fn forward<T>(
+    rx: &mut impl Receiver<T>,
+    tx: &mut impl Sender<T>,
+    buf: &mut Vec<T>,
+    batch_size: usize,
+) -> usize {
+    if batch_size == 1 {
+        tx.send(rx.recv());
+        1
+    } else {
+        let items = rx.recv_batch(buf, batch_size);
+        tx.send_batch(buf);
+        items
+    }
+}
+
+async fn forward_async<T>(
+    rx: &mut impl AsyncReceiver<T>,
+    tx: &mut impl Sender<T>,
+    buf: &mut Vec<T>,
+    batch_size: usize,
+) -> usize {
+    if batch_size == 1 {
+        tx.send(rx.recv().await);
+        1
+    } else {
+        let items = rx.recv_batch(buf, batch_size).await;
+        tx.send_batch(buf);
+        items
+    }
+}
+
 fn benchmark_channel<C: Channel<Item>>(args: &Args) -> Result<()> {
     let (core0, core1) = setup_core_ids()?;
-    let (mut tx0, mut rx1) = C::bounded(args.channel_size);
-    let (mut tx1, mut rx0) = C::bounded(args.channel_size);
+    let (mut tx0, mut rx1) = C::bounded(args.channel_size, args);
+    let (mut tx1, mut rx0) = C::bounded(args.channel_size, args);
 
     for _ in 0..args.channel_size {
         tx1.send(ITEM);
     }
 
     let measurement_iters = args.measurement_iters;
+    let batch_size = args.batch_size;
     let measurement_thread = thread::spawn(move || {
         core_affinity::set_for_current(core1);
+        let mut buf = Vec::with_capacity(batch_size);
         let mut time = Instant::now();
         loop {
-            for _ in 0..measurement_iters {
-                let item = rx1.recv();
-                tx1.send(item);
+            let mut items = 0;
+            while items < measurement_iters {
+                items += forward(&mut rx1, &mut tx1, &mut buf, batch_size);
             }
             let now = Instant::now();
             let elapsed = now - time;
-            let items_per_sec = measurement_iters as f64 / elapsed.as_secs_f64();
+            let items_per_sec = items as f64 / elapsed.as_secs_f64();
             println!("items/s = {items_per_sec:.3e}");
             time = now;
         }
@@ -326,9 +462,9 @@ fn benchmark_channel<C: Channel<Item>>(args: &Args) -> Result<()> {
 
     thread::spawn(move || {
         core_affinity::set_for_current(core0);
+        let mut buf = Vec::with_capacity(batch_size);
         loop {
-            let item = rx0.recv();
-            tx0.send(item);
+            forward(&mut rx0, &mut tx0, &mut buf, batch_size);
         }
     });
 
@@ -339,26 +475,28 @@ fn benchmark_channel<C: Channel<Item>>(args: &Args) -> Result<()> {
 
 fn benchmark_async_channel<C: AsyncChannel<Item>>(args: &Args) -> Result<()> {
     let (core0, core1) = setup_core_ids()?;
-    let (mut tx0, mut rx1) = C::bounded(args.channel_size);
-    let (mut tx1, mut rx0) = C::bounded(args.channel_size);
+    let (mut tx0, mut rx1) = C::bounded(args.channel_size, args);
+    let (mut tx1, mut rx0) = C::bounded(args.channel_size, args);
 
     for _ in 0..args.channel_size {
         tx1.send(ITEM);
     }
 
     let measurement_iters = args.measurement_iters;
+    let batch_size = args.batch_size;
     let measurement_thread = thread::spawn(move || {
         core_affinity::set_for_current(core1);
         block_on(async move {
+            let mut buf = Vec::with_capacity(batch_size);
             let mut time = Instant::now();
             loop {
-                for _ in 0..measurement_iters {
-                    let item = rx1.recv().await;
-                    tx1.send(item);
+                let mut items = 0;
+                while items < measurement_iters {
+                    items += forward_async(&mut rx1, &mut tx1, &mut buf, batch_size).await;
                 }
                 let now = Instant::now();
                 let elapsed = now - time;
-                let items_per_sec = measurement_iters as f64 / elapsed.as_secs_f64();
+                let items_per_sec = items as f64 / elapsed.as_secs_f64();
                 println!("items/s = {items_per_sec:.3e}");
                 time = now;
             }
@@ -368,9 +506,9 @@ fn benchmark_async_channel<C: AsyncChannel<Item>>(args: &Args) -> Result<()> {
     thread::spawn(move || {
         core_affinity::set_for_current(core0);
         block_on(async move {
+            let mut buf = Vec::with_capacity(batch_size);
             loop {
-                let item = rx0.recv().await;
-                tx0.send(item);
+                forward_async(&mut rx0, &mut tx0, &mut buf, batch_size).await;
             }
         })
     });
@@ -382,6 +520,7 @@ fn benchmark_async_channel<C: AsyncChannel<Item>>(args: &Args) -> Result<()> {
 
 fn main() -> Result<()> {
     let args = Args::parse();
+    anyhow::ensure!(args.batch_size != 0, "the batch size must be nonzero");
     match args.channel {
         ChannelType::AsyncChannelBounded => {
             benchmark_async_channel::<AsyncChannelBounded<Item>>(&args)?
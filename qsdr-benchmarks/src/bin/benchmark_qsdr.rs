@@ -1,5 +1,6 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use futures::stream::FusedStream;
 use qsdr::{
     Block, BlockWorkStatus, Buffer, Channel, Flowgraph, Quantum, Receiver, Run, WorkInPlace,
     WorkSink, WorkStatus,
@@ -8,14 +9,17 @@ use qsdr::{
     channels::{Spsc, SpscRef},
     kernels,
     ports::{PortInQ, PortOut, PortOutQ, PortRefInQ, PortSource},
-    scheduler::{run, sequence2, sequence3, sequence4, sequence5},
+    scheduler::{
+        CpuGroups, run, sequence_vec, sequence2, sequence3, sequence4, sequence5, sequence6,
+        sequence7, sequence8,
+    },
 };
 use qsdr_benchmarks::{
     affinity::{get_core_ids, pin_cpu},
     futures::executor::block_on,
 };
 use rand::prelude::*;
-use std::{thread, time::Instant};
+use std::{pin::Pin, thread, time::Instant};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -32,10 +36,13 @@ struct Args {
 
 #[derive(Subcommand, Debug)]
 enum Command {
-    /// Single kernel on a single core.
-    SingleCore,
+    /// Kernels on a single core.
+    SingleCore(SingleCore),
     /// Multiple kernels spread over multiple cores.
     MultiKernel(MultiKernel),
+    /// Multiple kernels spread over multiple cores using the qsdr multi-core
+    /// scheduler.
+    MultiKernelBuiltin(MultiKernel),
     /// Multiple kernels spread over multiple cores using the Tokio runtime.
     MultiKernelTokio(MultiKernel),
     /// Multiple kernels spread over multiple cores using async-executor.
@@ -50,11 +57,23 @@ enum Command {
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[allow(clippy::enum_variant_names)]
 enum Executor {
+    Builtin,
     TokioRuntime,
     AsyncExecutor,
     ThreadPerBlock,
 }
 
+#[derive(Parser, Debug)]
+struct SingleCore {
+    /// Number of kernels (at most 6 without --sequence-vec).
+    #[arg(long, default_value_t = 1)]
+    num_kernels: usize,
+    /// Use the dynamically sized sequence scheduler instead of the fixed-arity
+    /// ones.
+    #[arg(long)]
+    sequence_vec: bool,
+}
+
 #[derive(Parser, Debug)]
 struct MultiKernel {
     /// Number of kernels.
@@ -220,42 +239,6 @@ where
 
 type Buff = CacheAlignedBuffer<f32>;
 
-fn single_core(args: &Args) -> Result<()> {
-    pin_cpu()?;
-    let mut rng = rand::rng();
-    let dummy_source = DummySource::<Quantum<Buff>>::new();
-    let saxpy = Saxpy::<Buff, Spsc, SpscRef>::new(rng.random(), rng.random());
-    let benchmark_sink = BenchmarkSink::new();
-
-    let buf_len = args.buffer_size / std::mem::size_of::<<Buff as Buffer>::Item>();
-    let buffers = std::iter::repeat_with(|| Quantum::new(Buff::from_fn(buf_len, |_| rng.random())))
-        .take(args.num_buffers);
-
-    let mut fg = Flowgraph::new();
-    let dummy_source = fg.add_block(dummy_source);
-    let saxpy = fg.add_block(saxpy);
-    let benchmark_sink = fg.add_block(benchmark_sink);
-
-    let mut circuit = fg.new_circuit(buffers);
-    fg.connect(&mut circuit, dummy_source.output(), saxpy.input())?;
-    fg.connect_with_return(
-        &mut circuit,
-        saxpy.output(),
-        benchmark_sink.input(),
-        dummy_source.input(),
-    )?;
-    let mut fg = fg.validate()?;
-    let dummy_source = fg.extract_block(dummy_source)?;
-    let saxpy = fg.extract_block(saxpy)?;
-    let benchmark_sink = fg.extract_block(benchmark_sink)?;
-
-    block_on(run(sequence3(
-        dummy_source.into_stream(),
-        saxpy.into_stream(),
-        benchmark_sink.into_stream(),
-    )))
-}
-
 macro_rules! sequence {
     ($stream0:expr) => {
         $stream0
@@ -272,6 +255,130 @@ macro_rules! sequence {
     ($stream0:expr, $stream1:expr, $stream2:expr, $stream3:expr, $stream4:expr) => {
         sequence5($stream0, $stream1, $stream2, $stream3, $stream4)
     };
+    ($stream0:expr, $stream1:expr, $stream2:expr, $stream3:expr, $stream4:expr, $stream5:expr) => {
+        sequence6($stream0, $stream1, $stream2, $stream3, $stream4, $stream5)
+    };
+    ($stream0:expr, $stream1:expr, $stream2:expr, $stream3:expr, $stream4:expr, $stream5:expr,
+     $stream6:expr) => {
+        sequence7(
+            $stream0, $stream1, $stream2, $stream3, $stream4, $stream5, $stream6,
+        )
+    };
+    ($stream0:expr, $stream1:expr, $stream2:expr, $stream3:expr, $stream4:expr, $stream5:expr,
+     $stream6:expr, $stream7:expr) => {
+        sequence8(
+            $stream0, $stream1, $stream2, $stream3, $stream4, $stream5, $stream6, $stream7,
+        )
+    };
+}
+
+fn single_core(args: &Args, sub: &SingleCore) -> Result<()> {
+    anyhow::ensure!(sub.num_kernels >= 1, "at least one kernel is required");
+    pin_cpu()?;
+    let mut rng = rand::rng();
+
+    let mut fg = Flowgraph::new();
+    let dummy_source = fg.add_block(DummySource::<Quantum<Buff>>::new());
+    let saxpys = (0..sub.num_kernels - 1)
+        .map(|_| fg.add_block(Saxpy::<Buff, Spsc, Spsc>::new(rng.random(), rng.random())))
+        .collect::<Vec<_>>();
+    let last_saxpy = fg.add_block(Saxpy::<Buff, Spsc, SpscRef>::new(
+        rng.random(),
+        rng.random(),
+    ));
+    let benchmark_sink = fg.add_block(BenchmarkSink::new());
+
+    let buf_len = args.buffer_size / std::mem::size_of::<<Buff as Buffer>::Item>();
+    let buffers = std::iter::repeat_with(|| Quantum::new(Buff::from_fn(buf_len, |_| rng.random())))
+        .take(args.num_buffers);
+    let mut circuit = fg.new_circuit(buffers);
+    if let Some(first_saxpy) = saxpys.first() {
+        fg.connect(&mut circuit, dummy_source.output(), first_saxpy.input())?;
+        for (saxpy0, saxpy1) in saxpys.iter().zip(saxpys.iter().skip(1)) {
+            fg.connect(&mut circuit, saxpy0.output(), saxpy1.input())?;
+        }
+        fg.connect(
+            &mut circuit,
+            saxpys.last().unwrap().output(),
+            last_saxpy.input(),
+        )?;
+    } else {
+        fg.connect(&mut circuit, dummy_source.output(), last_saxpy.input())?;
+    }
+    fg.connect_with_return(
+        &mut circuit,
+        last_saxpy.output(),
+        benchmark_sink.input(),
+        dummy_source.input(),
+    )?;
+    let mut fg = fg.validate()?;
+    let dummy_source = fg.extract_block(dummy_source)?.into_stream();
+    let saxpys = saxpys
+        .into_iter()
+        .map(|saxpy| Ok(fg.extract_block(saxpy)?.into_stream()))
+        .collect::<Result<Vec<_>>>()?;
+    let last_saxpy = fg.extract_block(last_saxpy)?.into_stream();
+    let benchmark_sink = fg.extract_block(benchmark_sink)?.into_stream();
+
+    if sub.sequence_vec {
+        let mut streams: Vec<Pin<Box<dyn FusedStream<Item = Result<()>>>>> =
+            vec![Box::pin(dummy_source)];
+        streams.extend(
+            saxpys
+                .into_iter()
+                .map(|saxpy| Box::pin(saxpy) as Pin<Box<dyn FusedStream<Item = Result<()>>>>),
+        );
+        streams.push(Box::pin(last_saxpy));
+        streams.push(Box::pin(benchmark_sink));
+        return block_on(run(sequence_vec(streams)));
+    }
+
+    let mut saxpys = saxpys.into_iter();
+    let mut saxpy = || saxpys.next().unwrap();
+    match sub.num_kernels {
+        1 => block_on(run(sequence!(dummy_source, last_saxpy, benchmark_sink))),
+        2 => block_on(run(sequence!(
+            dummy_source,
+            saxpy(),
+            last_saxpy,
+            benchmark_sink
+        ))),
+        3 => block_on(run(sequence!(
+            dummy_source,
+            saxpy(),
+            saxpy(),
+            last_saxpy,
+            benchmark_sink
+        ))),
+        4 => block_on(run(sequence!(
+            dummy_source,
+            saxpy(),
+            saxpy(),
+            saxpy(),
+            last_saxpy,
+            benchmark_sink
+        ))),
+        5 => block_on(run(sequence!(
+            dummy_source,
+            saxpy(),
+            saxpy(),
+            saxpy(),
+            saxpy(),
+            last_saxpy,
+            benchmark_sink
+        ))),
+        6 => block_on(run(sequence!(
+            dummy_source,
+            saxpy(),
+            saxpy(),
+            saxpy(),
+            saxpy(),
+            saxpy(),
+            last_saxpy,
+            benchmark_sink
+        ))),
+        _ => anyhow::bail!("more than 6 kernels require --sequence-vec"),
+    }
 }
 
 fn multi_kernel(args: &Args, sub: &MultiKernel) -> Result<()> {
@@ -722,16 +829,40 @@ fn multi_kernel_executor(args: &Args, sub: &MultiKernel, executor: Executor) ->
         dummy_source.input(),
     )?;
     let mut fg = fg.validate()?;
-    let dummy_source = fg.extract_block(dummy_source)?;
-    let mut saxpy_blocks = Vec::with_capacity(saxpys.len());
-    for saxpy in saxpys.into_iter() {
-        saxpy_blocks.push(fg.extract_block(saxpy)?);
+
+    // the flowgraph is run by the builtin scheduler, and the blocks are
+    // extracted to be run by the other executors
+    macro_rules! extract_blocks {
+        () => {{
+            let dummy_source = fg.extract_block(dummy_source)?;
+            let saxpy_blocks = saxpys
+                .into_iter()
+                .map(|saxpy| fg.extract_block(saxpy))
+                .collect::<Result<Vec<_>>>()?;
+            let last_saxpy = fg.extract_block(last_saxpy)?;
+            let benchmark_sink = fg.extract_block(benchmark_sink)?;
+            (dummy_source, saxpy_blocks, last_saxpy, benchmark_sink)
+        }};
     }
-    let last_saxpy = fg.extract_block(last_saxpy)?;
-    let benchmark_sink = fg.extract_block(benchmark_sink)?;
 
     match executor {
+        Executor::Builtin => {
+            // spread the kernels evenly over the CPUs, with the source running
+            // on the CPU of the first kernel and the sink on the CPU of the
+            // last
+            let kernel_cpu = |n: usize| core_ids[n * sub.num_cpus / sub.num_kernels].id;
+            let mut groups = CpuGroups::new();
+            groups.add(kernel_cpu(0), &dummy_source);
+            for (n, saxpy) in saxpys.iter().enumerate() {
+                groups.add(kernel_cpu(n), saxpy);
+            }
+            groups
+                .add(kernel_cpu(sub.num_kernels - 1), &last_saxpy)
+                .add(kernel_cpu(sub.num_kernels - 1), &benchmark_sink);
+            fg.run_multi_core(&groups)?;
+        }
         Executor::TokioRuntime => {
+            let (dummy_source, saxpy_blocks, last_saxpy, benchmark_sink) = extract_blocks!();
             let rt = tokio::runtime::Builder::new_multi_thread()
                 .worker_threads(sub.num_cpus)
                 .on_thread_start(move || {
@@ -755,6 +886,7 @@ fn multi_kernel_executor(args: &Args, sub: &MultiKernel, executor: Executor) ->
             rt.block_on(all_handles).0??;
         }
         Executor::AsyncExecutor => {
+            let (dummy_source, saxpy_blocks, last_saxpy, benchmark_sink) = extract_blocks!();
             let ex = async_executor::Executor::new();
             let mut tasks = Vec::new();
             tasks.push(ex.spawn(run(dummy_source.into_stream())));
@@ -776,6 +908,7 @@ fn multi_kernel_executor(args: &Args, sub: &MultiKernel, executor: Executor) ->
             })?;
         }
         Executor::ThreadPerBlock => {
+            let (dummy_source, saxpy_blocks, last_saxpy, benchmark_sink) = extract_blocks!();
             let mut handles = Vec::new();
             handles.push(thread::spawn(move || {
                 block_on(run(dummy_source.into_stream()))
@@ -848,8 +981,9 @@ fn benchmark_ref(args: &Args) -> Result<()> {
 fn main() -> Result<()> {
     let args = Args::parse();
     match &args.command {
-        Command::SingleCore => single_core(&args)?,
+        Command::SingleCore(sub) => single_core(&args, sub)?,
         Command::MultiKernel(sub) => multi_kernel(&args, sub)?,
+        Command::MultiKernelBuiltin(sub) => multi_kernel_executor(&args, sub, Executor::Builtin)?,
         Command::MultiKernelTokio(sub) => {
             multi_kernel_executor(&args, sub, Executor::TokioRuntime)?
         }
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use qsdr::{channel::spsc, kernels::saxpy::Saxpy};
+use qsdr::{buffers::HugePageBuffer, channel::spsc, kernels::saxpy::Saxpy};
 use qsdr_benchmarks::{
     Buffer,
     affinity::{get_core_ids, pin_cpu},
@@ -8,7 +8,7 @@ use qsdr_benchmarks::{
     futures::executor::block_on,
 };
 use rand::prelude::*;
-use std::{thread, time::Instant};
+use std::{ops::DerefMut, thread, time::Instant};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -39,6 +39,8 @@ enum Command {
     MultiKernelAsync(MultiKernel),
     /// Test several buffer sizes in a single-core benchmark.
     ScanBufferSize(ScanBufferSize),
+    /// Compare normal and huge page buffers in a single-core benchmark.
+    CompareHugePages(CompareHugePages),
 }
 
 #[derive(Parser, Debug)]
@@ -96,6 +98,22 @@ struct ScanBufferSize {
     measurement_time: f64,
 }
 
+#[derive(Parser, Debug)]
+struct CompareHugePages {
+    /// Buffer size (bytes).
+    #[arg(long, default_value_t = 1 << 24)]
+    buffer_size: usize,
+    /// Number of buffers.
+    #[arg(long, default_value_t = 4)]
+    num_buffers: usize,
+    /// Measurement time for each buffer type (seconds).
+    #[arg(long, default_value_t = 10.0)]
+    measurement_time: f64,
+    /// Lock the huge page buffers in memory.
+    #[arg(long)]
+    mlock: bool,
+}
+
 #[inline(always)]
 fn begin_measurement(cpu_num: usize) -> Result<(Instant, u64)> {
     Ok((Instant::now(), get_cpu_cycles(cpu_num)?))
@@ -458,6 +476,72 @@ fn scan_buffer_size(args: &Args, args_sub: &ScanBufferSize) -> Result<()> {
     Ok(())
 }
 
+fn compare_huge_pages(args: &Args, args_sub: &CompareHugePages) -> Result<()> {
+    check_buffer_args!(args_sub);
+    let cpu_num = pin_cpu()?;
+    let mut rng = rand::rng();
+    let saxpy = Saxpy::new(rng.random(), rng.random());
+    let buf_len = args_sub.buffer_size / std::mem::size_of::<f32>();
+    let samples_per_iter = buf_len * args_sub.num_buffers;
+    let measurement_iters = (args.clock_frequency * args_sub.measurement_time
+        / (Saxpy::CLOCKS_PER_SAMPLE * samples_per_iter as f64))
+        .ceil() as usize;
+    let samples_per_measurement = measurement_iters * samples_per_iter;
+
+    fn measure<B: DerefMut<Target = [f32]>>(
+        saxpy: &Saxpy,
+        buffers: &mut [B],
+        measurement_iters: usize,
+        samples_per_measurement: usize,
+        cpu_num: usize,
+    ) -> Result<()> {
+        let (time, cycles) = begin_measurement(cpu_num)?;
+        for _ in 0..measurement_iters {
+            for buf in buffers.iter_mut() {
+                saxpy.run_best(&mut buf[..]);
+            }
+        }
+        make_measurement(time, cycles, samples_per_measurement, cpu_num)?;
+        Ok(())
+    }
+
+    let mut buffers = std::iter::repeat_with(|| Buffer::<f32>::from_fn(buf_len, |_| rng.random()))
+        .take(args_sub.num_buffers)
+        .collect::<Vec<_>>();
+    print!("CacheAlignedBuffer: ");
+    measure(
+        &saxpy,
+        &mut buffers,
+        measurement_iters,
+        samples_per_measurement,
+        cpu_num,
+    )?;
+    drop(buffers);
+
+    let mut buffers =
+        std::iter::repeat_with(|| HugePageBuffer::<f32>::from_fn(buf_len, |_| rng.random()))
+            .take(args_sub.num_buffers)
+            .collect::<Result<Vec<_>>>()?;
+    if args_sub.mlock {
+        for buf in buffers.iter_mut() {
+            buf.lock()?;
+        }
+    }
+    let pages = if buffers[0].huge_tlb() {
+        "hugetlbfs"
+    } else {
+        "transparent"
+    };
+    print!("HugePageBuffer ({pages}): ");
+    measure(
+        &saxpy,
+        &mut buffers,
+        measurement_iters,
+        samples_per_measurement,
+        cpu_num,
+    )
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
     match &args.command {
@@ -471,6 +555,7 @@ fn main() -> Result<()> {
         Command::MultiKernel(sub) => multi_kernel(&args, sub)?,
         Command::MultiKernelAsync(sub) => multi_kernel_async(&args, sub)?,
         Command::ScanBufferSize(sub) => scan_buffer_size(&args, sub)?,
+        Command::CompareHugePages(sub) => compare_huge_pages(&args, sub)?,
     }
 
     Ok(())
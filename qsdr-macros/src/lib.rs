@@ -93,11 +93,17 @@ pub fn block_derive(input: TokenStream) -> TokenStream {
         }
     };
 
+    let block_generic_types = block_generics.iter().map(|ty| &ty.ident);
+    let block_generic_types = quote! {
+        #(#block_generic_types),*
+    };
+
     let mut channels = Vec::new();
     let mut channel_idents = Vec::new();
     let mut seeds = Vec::new();
-    let mut seeds_defaults = Vec::new();
     let mut port_ids = Vec::new();
+    let mut port_infos = Vec::new();
+    let mut erased_endpoints = Vec::new();
     for (port_id, port) in ports.iter().enumerate() {
         let ident = port.ident.as_ref().expect("port should have ident");
         channel_idents.push(ident);
@@ -106,31 +112,57 @@ pub fn block_derive(input: TokenStream) -> TokenStream {
             #ident: <#ty as #qsdr::__private::Port>::Channel
         });
         seeds.push(quote! {
-            #ident: ::std::cell::RefCell<<#ty as #qsdr::__private::Port>::Seed>
+            #ident: ::std::sync::Mutex<<#ty as #qsdr::__private::Port>::Seed>
         });
-        seeds_defaults.push(quote! {
-            #ident: ::std::cell::RefCell::new(Default::default())
+        port_infos.push(quote! {
+            #qsdr::__private::PortInfo {
+                name: stringify!(#ident),
+                kind: <#ty as #qsdr::__private::Port>::KIND,
+                blocking: <#ty as #qsdr::__private::Port>::BLOCKING,
+            }
         });
         let port_id = u32::try_from(port_id).unwrap();
+        let port_name = ident.to_string();
+        erased_endpoints.push(quote! {
+            #port_name => {
+                let port = #qsdr::__private::PortId::from(#port_id);
+                let Ok(seed) = #qsdr::__private::FlowgraphNode::state(self)
+                    .seeds()
+                    .#ident
+                    .try_lock()
+                else {
+                    anyhow::bail!(concat!("port ", stringify!(#ident), " is already in use"));
+                };
+                Ok(#qsdr::ports::ErasedEndpoint::new::<#ty>(
+                    self.flowgraph_id,
+                    self.node_id,
+                    port,
+                    stringify!(#ident),
+                    seed,
+                ))
+            }
+        });
         port_ids.push(quote! {
             #vis fn #ident(&self) -> #qsdr::ports::Endpoint<'_, #ty> {
                 // Use this to remove a "field is never read" warning. With
                 // this, the warning will typically show iff this function is
                 // never called.
-                let _ = &self.as_ref().#ident;
+                let _ = |block: &#block_ident<#block_generic_types>| {
+                    let _ = &block.#ident;
+                };
                 let port = #qsdr::__private::PortId::from(#port_id);
-                let seed = self.seeds.#ident.borrow_mut();
-                #qsdr::ports::Endpoint::new(self.flowgraph_id, self.node_id, port, seed)
+                let seed = #qsdr::__private::FlowgraphNode::state(self)
+                    .seeds()
+                    .#ident
+                    .try_lock()
+                    .expect(concat!("port ", stringify!(#ident), " is already in use"));
+                #qsdr::ports::Endpoint::new(self.flowgraph_id, self.node_id, port, stringify!(#ident), seed)
             }
         });
     }
 
     let block_channels_ident = format_ident!("__{block_ident}BlockChannels");
     let block_seeds_ident = format_ident!("__{block_ident}BlockSeeds");
-    let block_generic_types = block_generics.iter().map(|ty| &ty.ident);
-    let block_generic_types = quote! {
-        #(#block_generic_types),*
-    };
     let block_generic_list = quote! {
         #(#block_generics),*
     };
@@ -156,7 +188,15 @@ pub fn block_derive(input: TokenStream) -> TokenStream {
 
             fn try_from(value: #block_seeds_ident<#block_generic_types>) -> anyhow::Result<Self> {
                 Ok(Self {
-                    #(#channel_idents: value.#channel_idents.into_inner().try_into()?),*,
+                    #(
+                        #channel_idents: value.#channel_idents
+                            .into_inner()
+                            .unwrap_or_else(::std::sync::PoisonError::into_inner)
+                            .try_into()
+                            .map_err(
+                            |err: anyhow::Error| err.context(concat!("port ", stringify!(#channel_idents)))
+                        )?
+                    ),*,
                     __qsdr__phantom: ::std::marker::PhantomData,
                 })
             }
@@ -195,6 +235,23 @@ pub fn block_derive(input: TokenStream) -> TokenStream {
             }
         }
 
+        impl<#block_generic_list> #qsdr::__private::TakeSeeds for #block_seeds_ident<#block_generic_types>
+            #block_where
+        {
+            fn take(&self) -> Self {
+                Self {
+                    #(
+                        #channel_idents: ::std::sync::Mutex::new(::std::mem::take(
+                            &mut *self.#channel_idents
+                                .lock()
+                                .unwrap_or_else(::std::sync::PoisonError::into_inner)
+                        ))
+                    ),*,
+                    __qsdr__phantom: ::std::marker::PhantomData,
+                }
+            }
+        }
+
         impl<#block_generic_list> ::std::fmt::Debug for #block_seeds_ident<#block_generic_types>
             #block_where
         {
@@ -217,8 +274,7 @@ pub fn block_derive(input: TokenStream) -> TokenStream {
         {
             flowgraph_id: #qsdr::__private::FlowgraphId,
             node_id: #qsdr::__private::NodeId,
-            block: #block_ident<#block_generic_types>,
-            seeds: #block_seeds_ident<#block_generic_types>,
+            state: ::std::sync::Arc<#qsdr::__private::NodeState<#block_ident<#block_generic_types>>>,
         }
 
         impl<#block_generic_list> #qsdr::__private::FlowgraphNode for #flowgraph_node_ident<#block_generic_types>
@@ -226,6 +282,8 @@ pub fn block_derive(input: TokenStream) -> TokenStream {
         {
             type B = #block_ident<#block_generic_types>;
 
+            const PORTS: &'static [#qsdr::__private::PortInfo] = &[#(#port_infos),*];
+
             fn flowgraph_id(&self) -> #qsdr::__private::FlowgraphId {
                 self.flowgraph_id
             }
@@ -234,32 +292,29 @@ pub fn block_derive(input: TokenStream) -> TokenStream {
                 self.node_id
             }
 
-            fn wrap_block(flowgraph_id: #qsdr::__private::FlowgraphId,
-                          node_id: #qsdr::__private::NodeId, block: Self::B) -> Self {
-                Self { flowgraph_id, node_id, block, seeds: Default::default() }
+            fn wrap_state(
+                flowgraph_id: #qsdr::__private::FlowgraphId,
+                node_id: #qsdr::__private::NodeId,
+                state: ::std::sync::Arc<#qsdr::__private::NodeState<Self::B>>,
+            ) -> Self {
+                Self { flowgraph_id, node_id, state }
             }
 
-            fn try_into_object(self, _fg: &mut #qsdr::ValidatedFlowgraph) ->
-                Result<#qsdr::BlockObject<#block_ident<#block_generic_types>>, anyhow::Error> {
-                    Ok(#qsdr::BlockObject::new(self.block, self.seeds.try_into()?))
-                }
-        }
-
-        impl<#block_generic_list> ::std::convert::AsRef<#block_ident<#block_generic_types>>
-            for #flowgraph_node_ident<#block_generic_types>
-            #block_where
-        {
-            fn as_ref(&self) -> &#block_ident<#block_generic_types> {
-                &self.block
+            fn state(&self) -> &::std::sync::Arc<#qsdr::__private::NodeState<Self::B>> {
+                &self.state
             }
-        }
 
-        impl<#block_generic_list> ::std::convert::AsMut<#block_ident<#block_generic_types>>
-            for #flowgraph_node_ident<#block_generic_types>
-            #block_where
-        {
-            fn as_mut(&mut self) -> &mut #block_ident<#block_generic_types> {
-                &mut self.block
+            fn erased_endpoint(
+                &self,
+                port: &str,
+            ) -> anyhow::Result<#qsdr::ports::ErasedEndpoint<'_>>
+            where
+                Self: 'static,
+            {
+                match port {
+                    #(#erased_endpoints)*
+                    _ => anyhow::bail!("block does not have a port named {port}"),
+                }
             }
         }
     };
@@ -282,6 +337,21 @@ pub fn block_derive(input: TokenStream) -> TokenStream {
         impl<#block_generic_list> #flowgraph_node_ident<#block_generic_types>
             #block_where
         {
+            /// Calls a function with a mutable reference to the block.
+            ///
+            /// This can be used to configure the block after it has been
+            /// added to the flowgraph.
+            ///
+            /// # Panics
+            ///
+            /// Panics if the block has been extracted from the flowgraph.
+            pub fn with_block<R>(
+                &self,
+                f: impl FnOnce(&mut #block_ident<#block_generic_types>) -> R,
+            ) -> R {
+                #qsdr::__private::FlowgraphNode::state(self).with_block(f)
+            }
+
             #(#port_ids)*
         }
     };
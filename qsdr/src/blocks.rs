@@ -7,6 +7,8 @@ pub mod basic {
     pub use null_source::NullSource;
     mod passthrough;
     pub use passthrough::Passthrough;
+    mod rechunk;
+    pub use rechunk::Rechunk;
     mod ref_clone;
     pub use ref_clone::RefClone;
     mod round_robin;
@@ -0,0 +1,245 @@
+use crate::{
+    prelude::*,
+    runtime::channel::{RefReceiver, Sender},
+};
+use std::borrow::Borrow;
+
+/// Copies the samples of a circuit into the quanta of another circuit with a
+/// different buffer length.
+///
+/// The block has the same ports as [`RefClone`](super::RefClone), but the
+/// samples of each input quantum are packed into or split across as many
+/// output quanta as needed, preserving their order. Each output quantum is
+/// sent once it is full, except at the end of the stream, where the last
+/// output quantum is sent with its text shrunk to the samples it contains.
+///
+/// The text of the output quanta taken from the source port spans their whole
+/// buffer. If the input quanta have [metadata](crate::Metadata), each output
+/// quantum gets the metadata of the input quantum where it starts, together
+/// with the tags of all its samples.
+#[derive(Block, Debug)]
+#[qsdr_crate = "crate"]
+#[work(WorkCustom)]
+pub struct Rechunk<
+    Bin: Buffer,
+    Bout: Buffer<Item = Bin::Item> = Bin,
+    Cin: Channel = SpscRef,
+    Cout: Channel = Spsc,
+    Csource: Channel = Spsc,
+> where
+    Bin::Item: Clone,
+    Csource::Receiver<Quantum<Bout>>: Receiver<Quantum<Bout>>,
+{
+    #[port]
+    input: PortRefInQ<Bin, Cin>,
+    #[port]
+    source: PortSourceQ<Bout, Csource>,
+    #[port]
+    output: PortOutQ<Bout, Cout>,
+    // output quantum that is being filled and number of samples in it
+    current: Option<(Quantum<Bout>, usize)>,
+}
+
+impl<Bin, Bout, Cin, Cout, Csource> Rechunk<Bin, Bout, Cin, Cout, Csource>
+where
+    Bin: Buffer,
+    Bout: Buffer<Item = Bin::Item>,
+    Cin: Channel,
+    Cout: Channel,
+    Csource: Channel,
+    Bin::Item: Clone,
+    Csource::Receiver<Quantum<Bout>>: Receiver<Quantum<Bout>>,
+{
+    pub fn new() -> Self {
+        Self {
+            input: Default::default(),
+            source: Default::default(),
+            output: Default::default(),
+            current: None,
+        }
+    }
+}
+
+impl<Bin, Bout, Cin, Cout, Csource> Default for Rechunk<Bin, Bout, Cin, Cout, Csource>
+where
+    Bin: Buffer,
+    Bout: Buffer<Item = Bin::Item>,
+    Cin: Channel,
+    Cout: Channel,
+    Csource: Channel,
+    Bin::Item: Clone,
+    Csource::Receiver<Quantum<Bout>>: Receiver<Quantum<Bout>>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Bin, Bout, Cin, Cout, Csource> WorkCustom for Rechunk<Bin, Bout, Cin, Cout, Csource>
+where
+    Bin: Buffer,
+    Bout: Buffer<Item = Bin::Item>,
+    Cin: Channel,
+    Cout: Channel,
+    Csource: Channel,
+    Bin::Item: Clone,
+    Csource::Receiver<Quantum<Bout>>: Receiver<Quantum<Bout>>,
+{
+    async fn work_custom(&mut self, channels: &mut Self::Channels) -> Result<BlockWorkStatus> {
+        let Some(item_in) = channels.input.ref_recv().await else {
+            if let Some((mut quantum, filled)) = self.current.take() {
+                quantum.shrink_right(quantum.len() - filled);
+                channels.output.send(quantum);
+            }
+            return Ok(BlockWorkStatus::Done);
+        };
+        let item_in: &Quantum<Bin> = item_in.borrow();
+        let slice_in = item_in.as_slice();
+        let mut position = 0;
+        while position < slice_in.len() {
+            let (quantum, filled) = match &mut self.current {
+                Some(current) => current,
+                None => {
+                    let Some(mut quantum) = channels.source.recv().await else {
+                        return Ok(BlockWorkStatus::Done);
+                    };
+                    quantum.set_margins(0, 0);
+                    assert!(!quantum.is_empty(), "output quantum is empty");
+                    quantum.set_metadata(None);
+                    self.current.insert((quantum, 0))
+                }
+            };
+            let len = (slice_in.len() - position).min(quantum.len() - *filled);
+            quantum.as_mut_slice()[*filled..*filled + len]
+                .clone_from_slice(&slice_in[position..position + len]);
+            if let Some(metadata_in) = item_in.metadata() {
+                if *filled == 0 {
+                    let mut metadata = metadata_in.clone();
+                    metadata.advance(position as isize);
+                    metadata.truncate(len);
+                    quantum.set_metadata(Some(metadata));
+                } else if let Some(metadata) = quantum.metadata_mut() {
+                    metadata.tags.extend(
+                        metadata_in
+                            .tags
+                            .iter()
+                            .filter(|tag| (position..position + len).contains(&tag.offset))
+                            .map(|tag| {
+                                let mut tag = tag.clone();
+                                tag.offset = tag.offset - position + *filled;
+                                tag
+                            }),
+                    );
+                }
+            }
+            *filled += len;
+            position += len;
+            if *filled == quantum.len() {
+                let (quantum, _) = self.current.take().unwrap();
+                channels.output.send(quantum);
+            }
+        }
+        Ok(BlockWorkStatus::Run)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        Metadata, QuantumSnapshot, TagValue,
+        blocks::basic::{SnapshotSink, SnapshotSource, SourceIterator},
+        buffers::CacheAlignedBuffer,
+        scheduler::{run, sequence3},
+    };
+    use futures::executor::block_on;
+
+    fn rechunk(len_in: usize, len_out: usize, num_elements: usize) {
+        type B = CacheAlignedBuffer<u32>;
+        let num_buffers = 4;
+        let make_buffers = |len| {
+            std::iter::repeat_with(move || Quantum::new(B::new(len)))
+                .take(num_buffers)
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+        let elements = (0..num_elements).map(move |n| {
+            let mut metadata = Metadata::new((n * len_in) as u64);
+            metadata.add_tag(1, "tag", TagValue::U64(n as u64));
+            QuantumSnapshot::from(
+                (n * len_in..(n + 1) * len_in)
+                    .map(|x| x as u32)
+                    .collect::<Vec<_>>(),
+            )
+            .with_metadata(metadata)
+        });
+
+        let mut fg = Flowgraph::new();
+        let source = fg.add_block(SnapshotSource::<B, _, Spsc, SpscRef>::new(SourceIterator(
+            elements,
+        )));
+        let rechunk = fg.add_block(Rechunk::<B, B, SpscRef, SpscRef>::new());
+        let (tx, rx) = std::sync::mpsc::channel();
+        let sink = fg.add_block(SnapshotSink::<B, _>::new(tx));
+        let mut circ0 = fg.new_circuit(make_buffers(len_in));
+        fg.connect_with_return(&mut circ0, source.output(), rechunk.input(), source.input())
+            .unwrap();
+        let mut circ1 = fg.new_circuit(make_buffers(len_out));
+        fg.connect_with_return(&mut circ1, rechunk.output(), sink.input(), rechunk.source())
+            .unwrap();
+        let mut fg = fg.validate().unwrap();
+        let source = fg.extract_block(source).unwrap();
+        let rechunk = fg.extract_block(rechunk).unwrap();
+        let sink = fg.extract_block(sink).unwrap();
+
+        block_on(run(sequence3(
+            source.into_stream(),
+            rechunk.into_stream(),
+            sink.into_stream(),
+        )))
+        .unwrap();
+
+        let received = rx.into_iter().collect::<Vec<_>>();
+        let total = num_elements * len_in;
+        assert_eq!(received.len(), total.div_ceil(len_out));
+        for (n, snapshot) in received.iter().enumerate() {
+            let start = n * len_out;
+            let expected = (start..(start + len_out).min(total))
+                .map(|x| x as u32)
+                .collect::<Vec<_>>();
+            assert_eq!(snapshot.as_slice(), expected, "quantum {n} mismatch");
+            let metadata = snapshot.metadata().unwrap();
+            assert_eq!(metadata.sample_offset, start as u64);
+            let expected_tags = (start..start + expected.len())
+                .filter(|x| x % len_in == 1)
+                .map(|x| (x - start, x / len_in))
+                .collect::<Vec<_>>();
+            let tags = metadata
+                .tags
+                .iter()
+                .map(|tag| {
+                    let TagValue::U64(value) = tag.value else {
+                        panic!("unexpected tag value");
+                    };
+                    (tag.offset, value as usize)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(tags, expected_tags, "quantum {n} tags mismatch");
+        }
+    }
+
+    #[test]
+    fn pack() {
+        rechunk(1024, 4096, 100);
+    }
+
+    #[test]
+    fn split() {
+        rechunk(4096, 1024, 25);
+    }
+
+    #[test]
+    fn uneven() {
+        rechunk(1000, 768, 100);
+    }
+}
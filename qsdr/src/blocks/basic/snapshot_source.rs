@@ -52,11 +52,12 @@ where
     Cout: Channel,
 {
     async fn work_in_place(&mut self, quantum: &mut Quantum<B>) -> Result<WorkStatus> {
-        let Some(snapshot) = self.source.recv().await? else {
+        let Some(mut snapshot) = self.source.recv().await? else {
             return Ok(DoneWithoutOutput);
         };
         assert_eq!(quantum.as_slice().len(), snapshot.as_slice().len());
         quantum.as_mut_slice().clone_from_slice(snapshot.as_slice());
+        quantum.set_metadata(snapshot.take_metadata());
         Ok(Run)
     }
 }
@@ -77,6 +78,7 @@ where
 mod test {
     use super::*;
     use crate::{
+        Metadata, TagValue,
         blocks::basic::SnapshotSink,
         buffers::CacheAlignedBuffer,
         scheduler::{run, sequence2},
@@ -97,13 +99,17 @@ mod test {
                 .take(num_buffers)
                 .collect::<Vec<_>>()
                 .into_iter();
-        let elements = std::iter::repeat_with(|| {
-            let mut v = vec![0; buffer_size];
-            Fill::fill(&mut v[..], &mut rng);
-            v.into()
-        })
-        .take(num_elements)
-        .collect::<Vec<_>>();
+        let elements = (0..num_elements)
+            .map(|n| {
+                let mut v = vec![0; buffer_size];
+                Fill::fill(&mut v[..], &mut rng);
+                let mut metadata = Metadata::new((n * buffer_size) as u64);
+                if n % 7 == 0 {
+                    metadata.add_tag(n % buffer_size, "tag", TagValue::U64(n as u64));
+                }
+                QuantumSnapshot::from(v).with_metadata(metadata)
+            })
+            .collect::<Vec<_>>();
 
         let mut fg = Flowgraph::new();
         let source = fg.add_block(SnapshotSource::<B, _, Spsc, SpscRef>::new(SourceIterator(
@@ -1,2 +1,54 @@
+use std::fmt;
+
+mod futex;
+pub mod mpmc;
 pub mod mpsc;
 pub mod spsc;
+pub(crate) mod stats;
+mod wait;
+#[cfg(feature = "stats")]
+pub use stats::{ChannelStats, StatsHandle};
+pub use wait::{
+    Adaptive, BusySpin, DefaultWait, FutexDefaultWait, SpinSleep, SpinYield, Wait, WaitStrategy,
+};
+
+/// Error returned by `try_recv`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum TryRecvError {
+    /// The channel is empty, but the senders are still alive.
+    Empty,
+    /// The channel is empty and all the senders have been dropped.
+    Disconnected,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "receiving on an empty channel"),
+            TryRecvError::Disconnected => write!(f, "receiving on a closed channel"),
+        }
+    }
+}
+
+impl std::error::Error for TryRecvError {}
+
+/// Error returned by `recv_timeout` and `recv_deadline`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RecvTimeoutError {
+    /// No item was received before the timeout, but the senders are still
+    /// alive.
+    Timeout,
+    /// The channel is empty and all the senders have been dropped.
+    Disconnected,
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => write!(f, "timed out waiting on a channel"),
+            RecvTimeoutError::Disconnected => write!(f, "receiving on a closed channel"),
+        }
+    }
+}
+
+impl std::error::Error for RecvTimeoutError {}
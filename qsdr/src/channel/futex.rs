@@ -0,0 +1,59 @@
+use libc::{FUTEX_PRIVATE_FLAG, FUTEX_WAIT, FUTEX_WAKE, SYS_futex, syscall};
+use std::{sync::atomic::AtomicU32, time::Instant};
+
+// Waits on the futex while it contains the expected value, until woken up or
+// until the deadline (if any). Returns false without waiting if the deadline
+// has already passed. Spurious wake-ups are possible, so the caller should
+// check the value of the futex again.
+pub fn wait(futex: &AtomicU32, expected: u32, deadline: Option<Instant>) -> bool {
+    wait_op(futex, expected, deadline, FUTEX_WAIT | FUTEX_PRIVATE_FLAG)
+}
+
+// Same as wait, but for a futex in memory that can be shared with other
+// processes.
+pub fn wait_shared(futex: &AtomicU32, expected: u32, deadline: Option<Instant>) -> bool {
+    wait_op(futex, expected, deadline, FUTEX_WAIT)
+}
+
+fn wait_op(futex: &AtomicU32, expected: u32, deadline: Option<Instant>, op: i32) -> bool {
+    let timeout = match deadline {
+        None => None,
+        Some(deadline) => {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            Some(libc::timespec {
+                tv_sec: remaining.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+                tv_nsec: remaining.subsec_nanos().into(),
+            })
+        }
+    };
+    unsafe {
+        syscall(
+            SYS_futex,
+            futex.as_ptr().cast_const(),
+            op,
+            expected,
+            timeout
+                .as_ref()
+                .map_or(std::ptr::null(), |timeout| &raw const *timeout),
+        )
+    };
+    true
+}
+
+// Wakes up at most count threads waiting on the futex.
+pub fn wake(futex: &AtomicU32, count: i32) {
+    wake_op(futex, count, FUTEX_WAKE | FUTEX_PRIVATE_FLAG);
+}
+
+// Same as wake, but for a futex in memory that can be shared with other
+// processes.
+pub fn wake_shared(futex: &AtomicU32, count: i32) {
+    wake_op(futex, count, FUTEX_WAKE);
+}
+
+fn wake_op(futex: &AtomicU32, count: i32, op: i32) {
+    unsafe { syscall(SYS_futex, futex.as_ptr().cast_const(), op, count) };
+}
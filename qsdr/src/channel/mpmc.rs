@@ -0,0 +1,3 @@
+mod chan;
+pub mod futex;
+pub mod futures;
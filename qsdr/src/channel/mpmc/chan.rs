@@ -0,0 +1,309 @@
+// Bounded multi-producer multi-consumer queue.
+//
+// The queue is a circular buffer of slots, each of which has a sequence that
+// indicates whether the slot is ready to be written by a sender or read by a
+// receiver in the current lap around the buffer. Senders and receivers claim
+// slots by advancing the shared write and read positions with a CAS.
+
+use crate::channel::{
+    TryRecvError,
+    stats::Stats,
+    wait::{WaitStrategy, Waiter},
+};
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    ops::Deref,
+    sync::{
+        Arc,
+        atomic::{
+            AtomicBool, AtomicU32, AtomicUsize,
+            Ordering::{AcqRel, Acquire, Relaxed, Release, SeqCst},
+            fence,
+        },
+    },
+};
+
+#[derive(Debug)]
+pub struct Sender<T, W: Waker> {
+    pub shared: Arc<Shared<T, W>>,
+}
+
+impl<T, W: Waker> Clone for Sender<T, W> {
+    fn clone(&self) -> Sender<T, W> {
+        self.shared.num_senders.fetch_add(1, Relaxed);
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Receiver<T, W: Waker> {
+    pub shared: Arc<Shared<T, W>>,
+    // identifies the receiver in the waker of the futures receivers
+    pub id: usize,
+    pub waiter: Waiter,
+}
+
+impl<T, W: Waker> Clone for Receiver<T, W> {
+    fn clone(&self) -> Receiver<T, W> {
+        Receiver {
+            shared: Arc::clone(&self.shared),
+            id: self.shared.next_receiver_id.fetch_add(1, Relaxed),
+            waiter: Waiter::new(self.waiter.strategy()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Shared<T, W> {
+    write_pos: CacheLine<AtomicU32>,
+    read_pos: CacheLine<AtomicU32>,
+    // Incremented each time that the sleeping receivers are notified, so that
+    // the receivers can detect notifications that happen while they are going
+    // to sleep.
+    pub event: CacheLine<AtomicU32>,
+    // Number of receivers that are sleeping or about to sleep.
+    pub sleepers: AtomicU32,
+    num_senders: AtomicU32,
+    disconnected: AtomicBool,
+    next_receiver_id: AtomicUsize,
+    mask: u32,
+    slots: Box<[Slot<T>]>,
+    pub waker: W,
+    pub stats: Stats,
+}
+
+unsafe impl<T: Send, W: Send> Send for Shared<T, W> {}
+unsafe impl<T: Send, W: Sync> Sync for Shared<T, W> {}
+
+#[derive(Debug)]
+struct Slot<T> {
+    sequence: AtomicU32,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+#[derive(Debug, Default)]
+#[repr(align(64))]
+pub struct CacheLine<T>(T);
+
+impl<T> Deref for CacheLine<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+pub trait Waker: Sized + Send + Sync + Default {
+    // Wakes up one of the sleeping receivers.
+    fn wake_one<T>(shared: &Shared<T, Self>);
+
+    // Wakes up all the sleeping receivers.
+    fn wake_all<T>(shared: &Shared<T, Self>);
+}
+
+pub fn channel<T, W: Waker>(
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> (Sender<T, W>, Receiver<T, W>) {
+    // A capacity of at least 2 is needed so that a slot that has been written
+    // is distinguishable from a slot that is free in the next lap.
+    let size = size.max(2).next_power_of_two();
+    // The positions are compared with wrapping arithmetic, so the size needs
+    // to be much smaller than the range of a u32.
+    assert!(u32::try_from(size).unwrap() <= 1 << 30);
+    let shared = Arc::new(Shared {
+        write_pos: Default::default(),
+        read_pos: Default::default(),
+        event: Default::default(),
+        sleepers: AtomicU32::new(0),
+        num_senders: AtomicU32::new(1),
+        disconnected: AtomicBool::new(false),
+        next_receiver_id: AtomicUsize::new(1),
+        mask: u32::try_from(size - 1).unwrap(),
+        slots: (0..size)
+            .map(|n| Slot {
+                sequence: AtomicU32::new(u32::try_from(n).unwrap()),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect(),
+        waker: W::default(),
+        stats: Stats::new(),
+    });
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver {
+            shared,
+            id: 0,
+            waiter: Waiter::new(wait_strategy),
+        },
+    )
+}
+
+impl<T, W: Waker> Sender<T, W> {
+    pub fn send(&self, value: T) {
+        assert!(
+            self.try_send(value).is_ok(),
+            "send() called on a full channel"
+        );
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        let shared = &*self.shared;
+        let mut pos = shared.write_pos.load(Relaxed);
+        loop {
+            let slot = shared.slot(pos);
+            // Acquire because the read of the previous value in the slot by a
+            // receiver needs to happen before the write.
+            let sequence = slot.sequence.load(Acquire);
+            let diff = sequence.wrapping_sub(pos).cast_signed();
+            if diff == 0 {
+                match shared.write_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Relaxed,
+                    Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.sequence.store(pos.wrapping_add(1), Release);
+                        break;
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                // the slot still holds the item of the previous lap
+                return Err(value);
+            } else {
+                // another sender has claimed this slot
+                pos = shared.write_pos.load(Relaxed);
+            }
+        }
+        shared.stats.sent();
+        // the receivers might have already received items that other senders
+        // have sent after this one, so the difference can be negative
+        let waiting = pos
+            .wrapping_add(1)
+            .wrapping_sub(shared.read_pos.load(Relaxed))
+            .cast_signed();
+        shared.stats.waiting(waiting.max(0).cast_unsigned());
+        // The SeqCst fence orders the write of the slot before the read of
+        // the sleepers. The receivers order the increment of the sleepers
+        // before checking the slot in the same way, so either the receiver
+        // sees the item or the sender sees the receiver.
+        fence(SeqCst);
+        if shared.sleepers.load(Relaxed) != 0 {
+            shared.event.fetch_add(1, Release);
+            shared.stats.woke();
+            W::wake_one(shared);
+        }
+        Ok(())
+    }
+}
+
+impl<T, W: Waker> Drop for Sender<T, W> {
+    fn drop(&mut self) {
+        if self.shared.num_senders.fetch_sub(1, AcqRel) == 1 {
+            // this is the last sender
+            self.shared.disconnected.store(true, Release);
+            self.shared.event.fetch_add(1, Release);
+            fence(SeqCst);
+            if self.shared.sleepers.load(Relaxed) != 0 {
+                self.shared.stats.woke();
+                W::wake_all(&self.shared);
+            }
+        }
+    }
+}
+
+impl<T, W: Waker> Receiver<T, W> {
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        if let Some(value) = self.shared.pop() {
+            return Ok(value);
+        }
+        if !self.shared.disconnected.load(Acquire) {
+            return Err(TryRecvError::Empty);
+        }
+        // The senders might have sent more items before being dropped.
+        self.shared.pop().ok_or(TryRecvError::Disconnected)
+    }
+
+    // Number of items that have been claimed by the senders and not claimed
+    // by the receivers yet. Some of them might not have been written yet.
+    pub fn len(&self) -> u32 {
+        self.shared.len()
+    }
+}
+
+impl<T, W> Shared<T, W> {
+    fn slot(&self, pos: u32) -> &Slot<T> {
+        &self.slots[(pos & self.mask) as usize]
+    }
+
+    fn pop(&self) -> Option<T> {
+        let mut pos = self.read_pos.load(Relaxed);
+        loop {
+            let slot = self.slot(pos);
+            // Acquire because the write of the value by the sender needs to
+            // happen before the read.
+            let sequence = slot.sequence.load(Acquire);
+            let diff = sequence.wrapping_sub(pos.wrapping_add(1)).cast_signed();
+            if diff == 0 {
+                match self.read_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Relaxed,
+                    Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.sequence
+                            .store(pos.wrapping_add(self.mask).wrapping_add(1), Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                // the slot has not been written in this lap yet
+                return None;
+            } else {
+                // another receiver has claimed this slot
+                pos = self.read_pos.load(Relaxed);
+            }
+        }
+    }
+
+    // Returns true if there is an item that can be received or if all the
+    // senders have been dropped.
+    pub fn is_ready(&self) -> bool {
+        let pos = self.read_pos.load(Relaxed);
+        self.slot(pos).sequence.load(Relaxed) == pos.wrapping_add(1)
+            || self.disconnected.load(Relaxed)
+    }
+
+    pub fn len(&self) -> u32 {
+        let read_pos = self.read_pos.load(Relaxed);
+        let write_pos = self.write_pos.load(Relaxed);
+        // the positions are loaded non-atomically, so the difference can be
+        // out of range while other threads access the channel
+        write_pos
+            .wrapping_sub(read_pos)
+            .cast_signed()
+            .clamp(0, self.capacity().cast_signed())
+            .cast_unsigned()
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.mask + 1
+    }
+}
+
+impl<T, W> Drop for Shared<T, W> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
@@ -0,0 +1,277 @@
+use super::chan::{self, Shared, Waker};
+use crate::channel::{
+    RecvTimeoutError, TryRecvError, futex,
+    wait::{Fallback, WaitStrategy, Waiter},
+};
+use std::{
+    sync::atomic::{
+        Ordering::{Acquire, Relaxed, SeqCst},
+        fence,
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+pub struct Sender<T>(chan::Sender<T, FutexWaker>);
+
+impl<T> Sender<T> {
+    pub fn send(&self, value: T) {
+        self.0.send(value)
+    }
+
+    /// Sends a value if the channel is not full.
+    ///
+    /// If the channel is full, the value is given back in the `Err`.
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        self.0.try_send(value)
+    }
+
+    /// Returns the number of items in the channel.
+    pub fn len(&self) -> usize {
+        self.0.shared.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
+
+    /// Returns the number of slots of the channel.
+    ///
+    /// This is at least the size with which the channel was created.
+    pub fn capacity(&self) -> usize {
+        self.0.shared.capacity() as usize
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        Sender(self.0.clone())
+    }
+}
+
+/// Receiver of a multi-consumer channel.
+///
+/// The receiver can be cloned to obtain more consumers. Each item is received
+/// by only one of the consumers.
+#[derive(Debug)]
+pub struct Receiver<T>(chan::Receiver<T, FutexWaker>);
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Receiver<T> {
+        Receiver(self.0.clone())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct FutexWaker {}
+
+impl Waker for FutexWaker {
+    fn wake_one<T>(shared: &Shared<T, FutexWaker>) {
+        futex::wake(&shared.event, 1);
+    }
+
+    fn wake_all<T>(shared: &Shared<T, FutexWaker>) {
+        futex::wake(&shared.event, i32::MAX);
+    }
+}
+
+pub fn channel<T>(size: usize) -> (Sender<T>, Receiver<T>) {
+    channel_with_wait_strategy(size, WaitStrategy::FUTEX_DEFAULT)
+}
+
+pub fn channel_with_wait_strategy<T>(
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = chan::channel(size, wait_strategy);
+    (Sender(tx), Receiver(rx))
+}
+
+impl<T> Receiver<T> {
+    pub fn recv(&mut self) -> Option<T> {
+        self.0.recv_futex_waker(None).ok()
+    }
+
+    /// Receives an item, waiting at most for the given timeout.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        // A timeout so large that the deadline overflows is the same as no
+        // timeout.
+        self.0.recv_futex_waker(Instant::now().checked_add(timeout))
+    }
+
+    /// Receives an item, waiting at most until the given deadline.
+    pub fn recv_deadline(&mut self, deadline: Instant) -> Result<T, RecvTimeoutError> {
+        self.0.recv_futex_waker(Some(deadline))
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    /// Returns the number of items in the channel.
+    ///
+    /// Some of them might be received by other consumers.
+    pub fn len(&self) -> usize {
+        self.0.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.0.waiter.strategy()
+    }
+
+    pub fn set_wait_strategy(&mut self, wait_strategy: WaitStrategy) {
+        self.0.waiter = Waiter::new(wait_strategy);
+    }
+
+    /// Returns a handle to the statistics of the channel.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> crate::channel::StatsHandle {
+        self.0.shared.stats.handle()
+    }
+}
+
+impl<T> chan::Receiver<T, FutexWaker> {
+    fn recv_futex_waker(&mut self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let shared = &*self.shared;
+        self.waiter.spin(|| shared.is_ready());
+        loop {
+            match self.try_recv() {
+                Ok(value) => return Ok(value),
+                Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                Err(TryRecvError::Empty) => (),
+            }
+            match self.waiter.fallback() {
+                fallback @ (Fallback::Spin | Fallback::Yield) => {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                    if fallback == Fallback::Spin {
+                        std::hint::spin_loop();
+                    } else {
+                        std::thread::yield_now();
+                    }
+                }
+                Fallback::Sleep => {
+                    let shared = &*self.shared;
+                    let event = shared.event.load(Acquire);
+                    shared.sleepers.fetch_add(1, Relaxed);
+                    // See the comment in chan::Sender::try_send.
+                    fence(SeqCst);
+                    if shared.is_ready() {
+                        shared.sleepers.fetch_sub(1, Relaxed);
+                        continue;
+                    }
+                    shared.stats.slept();
+                    let waited = futex::wait(&shared.event, event, deadline);
+                    shared.sleepers.fetch_sub(1, Relaxed);
+                    if !waited {
+                        return Err(RecvTimeoutError::Timeout);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::channel::{RecvTimeoutError, TryRecvError};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn multiple_senders_multiple_receivers() {
+        let cap = 4096;
+        let senders = 4;
+        let receivers = 4;
+        let (tx, rx) = super::channel(cap);
+        let sender_threads = (0..senders)
+            .map(|thread_num| {
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    for n in (0..cap).skip(thread_num).step_by(senders) {
+                        tx.send(n);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(tx);
+        let receiver_threads = (0..receivers)
+            .map(|_| {
+                let mut rx = rx.clone();
+                std::thread::spawn(move || std::iter::from_fn(|| rx.recv()).collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>();
+        drop(rx);
+        for thread in sender_threads.into_iter() {
+            thread.join().unwrap();
+        }
+        let mut received = receiver_threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect::<Vec<_>>();
+        received.sort_unstable();
+        let expected = (0..cap).collect::<Vec<_>>();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn try_send_try_recv() {
+        let (tx, mut rx) = super::channel(4);
+        assert_eq!(tx.capacity(), 4);
+        assert!(tx.is_empty());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        let mut sent = 0;
+        while tx.try_send(sent).is_ok() {
+            sent += 1;
+        }
+        assert_eq!(sent, tx.capacity());
+        assert!(tx.is_full());
+        assert_eq!(tx.try_send(sent), Err(sent));
+        let mut rx2 = rx.clone();
+        assert_eq!(rx2.len(), sent);
+        for n in 0..sent {
+            let rx = if n % 2 == 0 { &mut rx } else { &mut rx2 };
+            assert_eq!(rx.try_recv(), Ok(n));
+        }
+        assert!(rx.is_empty());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert!(!tx.is_full());
+        tx.try_send(sent).unwrap();
+        drop(tx);
+        assert_eq!(rx2.try_recv(), Ok(sent));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(rx2.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn recv_timeout() {
+        let (tx, mut rx) = super::channel(16);
+        let timeout = Duration::from_millis(10);
+        let start = Instant::now();
+        assert_eq!(rx.recv_timeout(timeout), Err(RecvTimeoutError::Timeout));
+        assert!(start.elapsed() >= timeout);
+        assert_eq!(
+            rx.recv_deadline(Instant::now()),
+            Err(RecvTimeoutError::Timeout)
+        );
+        let sender_thread = std::thread::spawn(move || {
+            let tx = tx;
+            std::thread::sleep(Duration::from_millis(20));
+            tx.send(42);
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok(42));
+        sender_thread.join().unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::MAX),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use super::chan::{self, Shared, Waker};
+use crate::channel::{
+    TryRecvError,
+    wait::{Fallback, WaitStrategy, Waiter},
+};
+use futures::stream::Stream;
+use std::{
+    collections::VecDeque,
+    future::poll_fn,
+    pin::Pin,
+    sync::{
+        Mutex,
+        atomic::{
+            AtomicU32,
+            Ordering::{Relaxed, SeqCst},
+            fence,
+        },
+    },
+    task::{self, Context, Poll},
+};
+
+#[derive(Debug)]
+pub struct Sender<T>(chan::Sender<T, TaskWakers>);
+
+impl<T> Sender<T> {
+    pub fn send(&self, value: T) {
+        self.0.send(value)
+    }
+
+    /// Sends a value if the channel is not full.
+    ///
+    /// If the channel is full, the value is given back in the `Err`.
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        self.0.try_send(value)
+    }
+
+    /// Returns the number of items in the channel.
+    pub fn len(&self) -> usize {
+        self.0.shared.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
+
+    /// Returns the number of slots of the channel.
+    ///
+    /// This is at least the size with which the channel was created.
+    pub fn capacity(&self) -> usize {
+        self.0.shared.capacity() as usize
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        Sender(self.0.clone())
+    }
+}
+
+/// Receiver of a multi-consumer channel.
+///
+/// The receiver can be cloned to obtain more consumers. Each item is received
+/// by only one of the consumers.
+#[derive(Debug)]
+pub struct Receiver<T> {
+    rx: chan::Receiver<T, TaskWakers>,
+    // the waker of the task might be registered in the TaskWakers
+    registered: bool,
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Receiver<T> {
+        Receiver {
+            rx: self.rx.clone(),
+            registered: false,
+        }
+    }
+}
+
+// Wakers of the tasks of the sleeping receivers, together with the ids of the
+// receivers, in the order in which they went to sleep.
+#[derive(Debug, Default)]
+struct TaskWakers(Mutex<VecDeque<(usize, task::Waker)>>);
+
+impl TaskWakers {
+    fn register(&self, id: usize, waker: &task::Waker, sleepers: &AtomicU32) {
+        let mut wakers = self.0.lock().unwrap();
+        if let Some((_, registered)) = wakers.iter_mut().find(|(n, _)| *n == id) {
+            registered.clone_from(waker);
+        } else {
+            wakers.push_back((id, waker.clone()));
+        }
+        sleepers.store(wakers.len() as u32, Relaxed);
+    }
+
+    fn unregister(&self, id: usize, sleepers: &AtomicU32) {
+        let mut wakers = self.0.lock().unwrap();
+        wakers.retain(|(n, _)| *n != id);
+        sleepers.store(wakers.len() as u32, Relaxed);
+    }
+}
+
+impl Waker for TaskWakers {
+    fn wake_one<T>(shared: &Shared<T, TaskWakers>) {
+        let waker = {
+            let mut wakers = shared.waker.0.lock().unwrap();
+            let waker = wakers.pop_front();
+            shared.sleepers.store(wakers.len() as u32, Relaxed);
+            waker
+        };
+        if let Some((_, waker)) = waker {
+            waker.wake();
+        }
+    }
+
+    fn wake_all<T>(shared: &Shared<T, TaskWakers>) {
+        let wakers = {
+            let mut wakers = shared.waker.0.lock().unwrap();
+            shared.sleepers.store(0, Relaxed);
+            std::mem::take(&mut *wakers)
+        };
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+    }
+}
+
+pub fn channel<T>(size: usize) -> (Sender<T>, Receiver<T>) {
+    channel_with_wait_strategy(size, WaitStrategy::FUTURES_DEFAULT)
+}
+
+pub fn channel_with_wait_strategy<T>(
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = chan::channel(size, wait_strategy);
+    (
+        Sender(tx),
+        Receiver {
+            rx,
+            registered: false,
+        },
+    )
+}
+
+impl<T> Receiver<T> {
+    pub async fn recv(&mut self) -> Option<T> {
+        poll_fn(|cx| self.poll(cx)).await
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let result = self.rx.try_recv();
+        if !matches!(result, Err(TryRecvError::Empty)) {
+            self.unregister();
+        }
+        result
+    }
+
+    /// Returns the number of items in the channel.
+    ///
+    /// Some of them might be received by other consumers.
+    pub fn len(&self) -> usize {
+        self.rx.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.rx.waiter.strategy()
+    }
+
+    pub fn set_wait_strategy(&mut self, wait_strategy: WaitStrategy) {
+        self.rx.waiter = Waiter::new(wait_strategy);
+    }
+
+    /// Returns a handle to the statistics of the channel.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> crate::channel::StatsHandle {
+        self.rx.shared.stats.handle()
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let shared = &*self.rx.shared;
+        self.rx.waiter.spin(|| shared.is_ready());
+        loop {
+            match self.rx.try_recv() {
+                Ok(value) => {
+                    self.unregister();
+                    return Poll::Ready(Some(value));
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.unregister();
+                    return Poll::Ready(None);
+                }
+                Err(TryRecvError::Empty) => (),
+            }
+            if self.rx.waiter.fallback() != Fallback::Sleep {
+                // let the executor poll again
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let shared = &*self.rx.shared;
+            shared
+                .waker
+                .register(self.rx.id, cx.waker(), &shared.sleepers);
+            self.registered = true;
+            // See the comment in chan::Sender::try_send.
+            fence(SeqCst);
+            if !shared.is_ready() {
+                shared.stats.slept();
+                return Poll::Pending;
+            }
+            // an item has arrived in the meantime
+        }
+    }
+
+    // The receiver can be polled without having been woken up, and then its
+    // waker is still registered. It is unregistered when it receives an item,
+    // since otherwise wake_one could choose this receiver, which is busy,
+    // instead of one that is sleeping.
+    fn unregister(&mut self) {
+        if self.registered {
+            let shared = &*self.rx.shared;
+            shared.waker.unregister(self.rx.id, &shared.sleepers);
+            self.registered = false;
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let shared = &*self.rx.shared;
+        shared.waker.unregister(self.rx.id, &shared.sleepers);
+        // This receiver might have been woken up for an item that it will not
+        // receive, so another receiver is woken up instead.
+        if shared.is_ready() {
+            TaskWakers::wake_one(shared);
+        }
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.poll(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::channel::WaitStrategy;
+    use futures::task::{ArcWake, waker};
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    #[derive(Default)]
+    struct CountWakes(AtomicUsize);
+
+    impl ArcWake for CountWakes {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn multiple_receivers() {
+        let cap = 4096;
+        let receivers = 4;
+        for wait_strategy in [
+            WaitStrategy::FUTURES_DEFAULT,
+            WaitStrategy::SpinYield { spins: 16 },
+        ] {
+            let (tx, rx) = super::channel_with_wait_strategy(cap, wait_strategy);
+            let receiver_threads = (0..receivers)
+                .map(|_| {
+                    let mut rx = rx.clone();
+                    std::thread::spawn(move || {
+                        futures::executor::block_on(async move {
+                            let mut received = Vec::new();
+                            while let Some(n) = rx.recv().await {
+                                received.push(n);
+                            }
+                            received
+                        })
+                    })
+                })
+                .collect::<Vec<_>>();
+            drop(rx);
+            for n in 0..cap {
+                tx.send(n);
+            }
+            drop(tx);
+            let mut received = receiver_threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>();
+            received.sort_unstable();
+            let expected = (0..cap).collect::<Vec<_>>();
+            assert_eq!(received, expected);
+        }
+    }
+
+    #[test]
+    fn spurious_poll() {
+        let (tx, mut rx1) = super::channel(4);
+        let mut rx2 = rx1.clone();
+        let counts = [(); 2].map(|_| Arc::new(CountWakes::default()));
+        let wakers = counts.each_ref().map(|count| waker(Arc::clone(count)));
+        let mut cx = wakers.each_ref().map(Context::from_waker);
+        let woken = |n: usize| counts[n].0.load(Ordering::SeqCst);
+
+        assert!(rx2.poll(&mut cx[1]).is_pending());
+        assert!(rx1.poll(&mut cx[0]).is_pending());
+        // rx2 is woken up, but rx1 is polled before and receives the item
+        tx.send(0);
+        assert_eq!(woken(1), 1);
+        assert_eq!(rx1.poll(&mut cx[0]), Poll::Ready(Some(0)));
+        assert!(rx2.poll(&mut cx[1]).is_pending());
+
+        // rx1 is busy, so the next item wakes up rx2
+        tx.send(1);
+        assert_eq!(woken(0), 0);
+        assert_eq!(woken(1), 2);
+        assert_eq!(rx2.poll(&mut cx[1]), Poll::Ready(Some(1)));
+    }
+}
@@ -1,3 +1,4 @@
 mod chan;
 pub mod futex;
 pub mod futures;
+pub mod shm;
@@ -1,10 +1,22 @@
+use crate::{
+    channel::{
+        TryRecvError,
+        stats::Stats,
+        wait::{WaitStrategy, Waiter},
+    },
+    shm::{RawEndpoint, Segment},
+};
+use anyhow::Result;
 use std::{
     alloc::{Layout, alloc, dealloc, handle_alloc_error},
     marker::PhantomData,
     ptr::NonNull,
-    sync::atomic::{
-        AtomicU32,
-        Ordering::{AcqRel, Relaxed, Release},
+    sync::{
+        Arc,
+        atomic::{
+            AtomicU32,
+            Ordering::{self, AcqRel, Acquire, Relaxed, Release},
+        },
     },
 };
 
@@ -46,10 +58,29 @@ pub struct Receiver<T, W> {
     pub read_idx: u32,
     pub available: u32,
     pub clear_pending: u32,
+    pub waiter: Waiter,
+}
+
+pub fn channel<T, W: Waker>(
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> (Sender<T, W>, Receiver<T, W>) {
+    endpoints(Common::new(size), wait_strategy)
 }
 
-pub fn channel<T, W: Waker>(size: usize) -> (Sender<T, W>, Receiver<T, W>) {
-    let common = Common::new(size);
+// Creates a channel whose ring is allocated in a shared memory segment.
+pub fn channel_in<T, W: Waker>(
+    segment: Arc<Segment>,
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> Result<(Sender<T, W>, Receiver<T, W>)> {
+    Ok(endpoints(Common::new_in(segment, size)?, wait_strategy))
+}
+
+fn endpoints<T, W: Waker>(
+    common: Common<T, W>,
+    wait_strategy: WaitStrategy,
+) -> (Sender<T, W>, Receiver<T, W>) {
     (
         Sender {
             common: common.clone(),
@@ -59,11 +90,28 @@ pub fn channel<T, W: Waker>(size: usize) -> (Sender<T, W>, Receiver<T, W>) {
             read_idx: 0,
             available: 0,
             clear_pending: 0,
+            waiter: Waiter::new(wait_strategy),
         },
     )
 }
 
 impl<T, W: Waker> Sender<T, W> {
+    // Safety: raw must have been obtained from into_raw on a sender of a
+    // channel in the same segment.
+    pub unsafe fn from_raw(segment: Arc<Segment>, raw: RawEndpoint) -> Sender<T, W> {
+        Sender {
+            common: unsafe { Common::from_raw(segment, raw) },
+        }
+    }
+
+    // Returns the location of the ring of a channel allocated in a segment
+    // without dropping the sender.
+    pub fn into_raw(self) -> Option<RawEndpoint> {
+        let raw = self.common.raw(0)?;
+        std::mem::forget(self);
+        Some(raw)
+    }
+
     pub fn send(&self, value: T) {
         let shared = self
             .common
@@ -74,11 +122,96 @@ impl<T, W: Waker> Sender<T, W> {
             available != self.common.mask,
             "send() called on a full channel"
         );
+        unsafe { self.write_slot(shared >> WRITE_IDX_SHIFT, value) };
+        self.sent(shared, 1);
+    }
+
+    // Sends all the items of the iterator, claiming their slots with a single
+    // atomic operation. The channel must have free slots for all of them,
+    // which is checked before sending any item.
+    pub fn send_batch<I>(&self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        // The items are collected before claiming their slots, because the
+        // receiver would wait forever for a claimed slot that is not written
+        // if the iterator panics or yields fewer items than its length.
+        let iter = iter.into_iter();
+        let mut items = Vec::with_capacity(iter.len());
+        items.extend(iter);
+        if items.is_empty() {
+            return;
+        }
+        let count = u32::try_from(items.len()).unwrap_or(u32::MAX);
+        let ordering = if size_of::<T>() == 0 {
+            Relaxed
+        } else {
+            // Acquire because the read of the slots that are overwritten needs
+            // to happen before the writes.
+            Acquire
+        };
+        let mut shared = self.common.shared().load(Relaxed);
+        loop {
+            let available = (shared & ((1 << WRITE_IDX_SHIFT) - 1)) >> AVAILABLE_SHIFT;
+            assert!(
+                count <= self.common.mask - available,
+                "send_batch() called without room for the batch"
+            );
+            match self.common.shared().compare_exchange_weak(
+                shared,
+                shared.wrapping_add(count * ((1 << WRITE_IDX_SHIFT) | (1 << AVAILABLE_SHIFT))),
+                ordering,
+                Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => shared = current,
+            }
+        }
+        let mut write_idx = shared >> WRITE_IDX_SHIFT;
+        for value in items {
+            unsafe { self.write_slot(write_idx, value) };
+            write_idx = (write_idx.wrapping_add(1) << WRITE_IDX_SHIFT) >> WRITE_IDX_SHIFT;
+        }
+        self.sent(shared, count);
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        let ordering = if size_of::<T>() == 0 {
+            Relaxed
+        } else {
+            // Acquire because the read of the slot that is overwritten needs
+            // to happen before the write.
+            Acquire
+        };
+        let mut shared = self.common.shared().load(Relaxed);
+        loop {
+            if (shared & ((1 << WRITE_IDX_SHIFT) - 1)) >> AVAILABLE_SHIFT >= self.common.mask {
+                return Err(value);
+            }
+            match self.common.shared().compare_exchange_weak(
+                shared,
+                shared.wrapping_add((1 << WRITE_IDX_SHIFT) | (1 << AVAILABLE_SHIFT)),
+                ordering,
+                Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => shared = current,
+            }
+        }
+        unsafe { self.write_slot(shared >> WRITE_IDX_SHIFT, value) };
+        self.sent(shared, 1);
+        Ok(())
+    }
+
+    // Writes the value in the slot with the given write index, which has been
+    // claimed by adding to the shared atomic.
+    unsafe fn write_slot(&self, write_idx: u32, value: T) {
         unsafe {
             let slot = self
                 .common
                 .slot_buf()
-                .add(((shared >> WRITE_IDX_SHIFT) & self.common.mask) as usize)
+                .add((write_idx & self.common.mask) as usize)
                 .as_ptr();
             std::ptr::write(&raw mut (*slot).value, value);
             let ordering = if size_of::<T>() == 0 {
@@ -88,19 +221,236 @@ impl<T, W: Waker> Sender<T, W> {
             } else {
                 Release
             };
-            (*slot).sequence.store(shared >> WRITE_IDX_SHIFT, ordering);
+            (*slot).sequence.store(write_idx, ordering);
         };
+    }
+
+    // Records that count items have been written after adding to the shared
+    // atomic, whose previous value is shared, and wakes up the receiver if
+    // needed.
+    fn sent(&self, shared: u32, count: u32) {
+        self.common.stats.sent_batch(count);
         if shared & RECEIVER_SLEEPING != 0 {
             self.common.shared().fetch_and(!RECEIVER_SLEEPING, Relaxed);
+            self.common.stats.woke();
             W::wake(&self.common);
         }
     }
 }
 
+impl<T, W> Receiver<T, W> {
+    // Safety: raw must have been obtained from into_raw on a receiver of a
+    // channel in the same segment.
+    pub unsafe fn from_raw(
+        segment: Arc<Segment>,
+        raw: RawEndpoint,
+        wait_strategy: WaitStrategy,
+    ) -> Receiver<T, W> {
+        Receiver {
+            common: unsafe { Common::from_raw(segment, raw) },
+            read_idx: raw.idx,
+            available: 0,
+            clear_pending: 0,
+            waiter: Waiter::new(wait_strategy),
+        }
+    }
+
+    // Returns the location of the ring of a channel allocated in a segment
+    // without dropping the receiver.
+    pub fn into_raw(mut self) -> Option<RawEndpoint> {
+        let raw = self.common.raw(self.read_idx)?;
+        self.clear();
+        std::mem::forget(self);
+        Some(raw)
+    }
+
+    // Frees the slots of the items that have been received.
+    fn clear(&mut self) {
+        let ordering = if size_of::<T>() == 0 {
+            // if T is ZST, reads and writes are a noop, so synchronization
+            // is not needed
+            Relaxed
+        } else {
+            // Release because the read of these items from the buffer needs
+            // to happen before an overwrite of the same slot by the sender
+            // (the sender can still attempt to send even if the receiver is
+            // dropped).
+            Release
+        };
+        self.common
+            .shared()
+            .fetch_sub(self.clear_pending << AVAILABLE_SHIFT, ordering);
+        self.available = 0;
+        self.clear_pending = 0;
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        if self.available >> AVAILABLE_SHIFT == 0 {
+            self.load_available()?;
+        }
+        // A sender might have claimed the slot but not written the value yet.
+        // In this case, the value is treated as not available instead of
+        // spinning until it is written.
+        if !self.is_written() {
+            return Err(TryRecvError::Empty);
+        }
+        Ok(unsafe { self.read_available() })
+    }
+
+    // Receives up to max items that are available without waiting, appending
+    // them to buf. The slots of the items are freed with at most one atomic
+    // operation.
+    pub fn try_recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> Result<usize, TryRecvError> {
+        if max == 0 {
+            return Ok(0);
+        }
+        if self.available >> AVAILABLE_SHIFT == 0 {
+            self.load_available()?;
+        }
+        let max = (self.available >> AVAILABLE_SHIFT).min(u32::try_from(max).unwrap_or(u32::MAX));
+        buf.reserve(max as usize);
+        let mut count = 0;
+        // The items are received until reaching a slot that has been claimed
+        // by a sender but not written yet.
+        while count < max && self.is_written() {
+            let value = unsafe {
+                let slot = self
+                    .common
+                    .slot_buf()
+                    .add((self.read_idx & self.common.mask) as usize)
+                    .as_ptr();
+                std::ptr::read(&raw const (*slot).value)
+            };
+            buf.push(value);
+            self.read_idx = (self.read_idx.wrapping_add(1) << WRITE_IDX_SHIFT) >> WRITE_IDX_SHIFT;
+            count += 1;
+        }
+        if count == 0 {
+            return Err(TryRecvError::Empty);
+        }
+        self.consume(count);
+        Ok(count as usize)
+    }
+
+    // Updates self.available with the slots that have been claimed by the
+    // senders.
+    fn load_available(&mut self) -> Result<(), TryRecvError> {
+        let shared = self.common.shared().load(Relaxed);
+        let available = shared & ((1 << WRITE_IDX_SHIFT) - 1);
+        if available >> AVAILABLE_SHIFT == self.clear_pending {
+            return Err(if shared & TRANSMITTERS_DROPPED != 0 {
+                TryRecvError::Disconnected
+            } else {
+                TryRecvError::Empty
+            });
+        }
+        self.set_available(available - (self.clear_pending << AVAILABLE_SHIFT));
+        Ok(())
+    }
+
+    // Returns true if the value in the next slot has been written by the
+    // sender. If this returns true, the value can be read, since this
+    // synchronizes with the write.
+    fn is_written(&self) -> bool {
+        let ordering = if size_of::<T>() == 0 {
+            // if T is ZST, the read is a noop, so it doesn't need to be
+            // synchronized
+            Relaxed
+        } else {
+            // Acquire because the write of the value in the slot by the sender
+            // needs to happen before the read
+            Acquire
+        };
+        let slot = unsafe {
+            self.common
+                .slot_buf()
+                .add((self.read_idx & self.common.mask) as usize)
+                .as_ref()
+        };
+        slot.sequence.load(ordering) == self.read_idx
+    }
+
+    // Reads the next item from the buffer, spinning until the sender has
+    // written it.
+    //
+    // Safety: self.available must indicate that there is at least one item
+    // available.
+    pub unsafe fn read_available(&mut self) -> T {
+        let ordering = if size_of::<T>() == 0 {
+            // if T is ZST, the read below is a noop, so it doesn't need to be
+            // synchronized
+            Relaxed
+        } else {
+            // Acquire because the write of the value in the slot by the sender
+            // needs to happen before the read
+            Acquire
+        };
+        let value = unsafe {
+            let slot = self
+                .common
+                .slot_buf()
+                .add((self.read_idx & self.common.mask) as usize)
+                .as_ptr();
+            // spin until the sequence on the item to read matches the expected value
+            while (*slot).sequence.load(ordering) != self.read_idx {}
+            std::ptr::read(&raw const (*slot).value)
+        };
+        self.read_idx = (self.read_idx.wrapping_add(1) << WRITE_IDX_SHIFT) >> WRITE_IDX_SHIFT;
+        self.consume(1);
+        value
+    }
+
+    // Accounts for count items that have been read, freeing their slots if
+    // there are enough pending.
+    fn consume(&mut self, count: u32) {
+        self.clear_pending += count;
+        if self.clear_pending >= MAX_PENDING_SLOTS {
+            let ordering = if size_of::<T>() == 0 {
+                // if T is ZST, reads and writes are a noop, so synchronization
+                // is not needed
+                Relaxed
+            } else {
+                // Release because the read of these items from the buffer
+                // needs to happen before an overwrite of the same slots by the
+                // senders.
+                Release
+            };
+            let old_shared = self
+                .common
+                .shared()
+                .fetch_sub(self.clear_pending << AVAILABLE_SHIFT, ordering);
+            self.set_available(
+                (old_shared - (self.clear_pending << AVAILABLE_SHIFT))
+                    & ((1 << WRITE_IDX_SHIFT) - 1),
+            );
+            self.clear_pending = 0;
+        } else {
+            self.available -= count << AVAILABLE_SHIFT;
+        }
+    }
+
+    // Sets the slots that can be received, given as the available bits of the
+    // shared atomic without the pending slots, and records them in the
+    // statistics.
+    pub fn set_available(&mut self, available: u32) {
+        self.available = available;
+        self.common.stats.waiting(available >> AVAILABLE_SHIFT);
+    }
+
+    // Number of items that have been claimed by the senders and not received
+    // yet. Some of them might not have been written yet.
+    pub fn len(&self) -> u32 {
+        self.common.occupied(Relaxed) - self.clear_pending
+    }
+}
+
 #[derive(Debug)]
 pub struct Common<T, W> {
     shared: NonNull<AtomicU32>,
     pub mask: u32,
+    pub stats: Stats,
+    // segment in which the ring is allocated, if it is not in the heap
+    segment: Option<Arc<Segment>>,
     _phantom: PhantomData<(*mut T, *mut W)>,
 }
 
@@ -119,6 +469,8 @@ impl<T, W> Clone for Common<T, W> {
         Common {
             shared: self.shared,
             mask: self.mask,
+            stats: self.stats.clone(),
+            segment: self.segment.clone(),
             _phantom: PhantomData,
         }
     }
@@ -126,6 +478,23 @@ impl<T, W> Clone for Common<T, W> {
 
 impl<T, W: Default> Common<T, W> {
     fn new(size: usize) -> Common<T, W> {
+        let size = Self::ring_size(size);
+        let layout = Self::shared_layout(size);
+        let ptr = unsafe { alloc(layout) };
+        let Some(ptr) = NonNull::new(ptr.cast::<AtomicU32>()) else {
+            handle_alloc_error(layout);
+        };
+        unsafe { Self::init(ptr, size, None) }
+    }
+
+    fn new_in(segment: Arc<Segment>, size: usize) -> Result<Common<T, W>> {
+        let size = Self::ring_size(size);
+        let offset = segment.alloc(Self::shared_layout(size))?;
+        let ptr = segment.ptr(offset).cast::<AtomicU32>();
+        Ok(unsafe { Self::init(ptr, size, Some(segment)) })
+    }
+
+    fn ring_size(size: usize) -> usize {
         // The size is amended to size + MAX_PENDING_SLOTS, because at least
         // MAX_PENDING_SLOTS - 1 additional slots can be not claimed to be free
         // by the receiver, and one additional slot is needed for the assertion
@@ -141,44 +510,48 @@ impl<T, W: Default> Common<T, W> {
         // the slots get different sequences in consecutive laps around the
         // circular buffer.
         assert!(u32::try_from(size).unwrap() < 1 << (32 - WRITE_IDX_SHIFT));
-        let shared = {
-            let layout = Self::shared_layout(size);
-            let ptr = unsafe { alloc(layout) };
-            let Some(ptr) = NonNull::new(ptr.cast::<AtomicU32>()) else {
-                handle_alloc_error(layout);
-            };
-            unsafe {
-                ptr.write(AtomicU32::new(0));
-                ptr.offset(NUM_SENDERS_OFFSET).write(AtomicU32::new(1));
-                ptr.offset(ENDPOINT_DROPPED_OFFSET).write(AtomicU32::new(0));
-                // READ_IDX_OFFSET does not really need to be accessed atomically,
-                // because it is synchronized by ENDPOINT_DROPPED_OFFSET, but we
-                // still use atomic accesses for homogeneity
-                ptr.offset(READ_IDX_OFFSET).write(AtomicU32::new(0));
-                ptr.byte_add(Self::WAKER_BYTE_OFFSET)
-                    .cast::<W>()
-                    .write(W::default());
-
-                for j in 0..size {
-                    // initialize sequences to all 1's so that they do not
-                    // contain a valid sequence value
-                    std::ptr::write(
-                        &raw mut (*ptr
-                            .byte_add(Self::SLOT_BUF_BYTE_OFFSET)
-                            .cast::<Slot<T>>()
-                            .add(j)
-                            .as_ptr())
-                        .sequence,
-                        AtomicU32::new(!0),
-                    );
-                }
+        size
+    }
+
+    // Safety: ptr must point to an allocation with the layout given by
+    // shared_layout(size).
+    unsafe fn init(
+        ptr: NonNull<AtomicU32>,
+        size: usize,
+        segment: Option<Arc<Segment>>,
+    ) -> Common<T, W> {
+        unsafe {
+            ptr.write(AtomicU32::new(0));
+            ptr.offset(NUM_SENDERS_OFFSET).write(AtomicU32::new(1));
+            ptr.offset(ENDPOINT_DROPPED_OFFSET).write(AtomicU32::new(0));
+            // READ_IDX_OFFSET does not really need to be accessed atomically,
+            // because it is synchronized by ENDPOINT_DROPPED_OFFSET, but we
+            // still use atomic accesses for homogeneity
+            ptr.offset(READ_IDX_OFFSET).write(AtomicU32::new(0));
+            ptr.byte_add(Self::WAKER_BYTE_OFFSET)
+                .cast::<W>()
+                .write(W::default());
+
+            for j in 0..size {
+                // initialize sequences to all 1's so that they do not
+                // contain a valid sequence value
+                std::ptr::write(
+                    &raw mut (*ptr
+                        .byte_add(Self::SLOT_BUF_BYTE_OFFSET)
+                        .cast::<Slot<T>>()
+                        .add(j)
+                        .as_ptr())
+                    .sequence,
+                    AtomicU32::new(!0),
+                );
             }
-            ptr
-        };
+        }
         let mask = u32::try_from(size - 1).unwrap();
         Common {
-            shared,
+            shared: ptr,
             mask,
+            stats: Stats::new(),
+            segment,
             _phantom: PhantomData,
         }
     }
@@ -222,8 +595,32 @@ impl<T, W> Common<T, W> {
                 .cast::<W>()
                 .drop_in_place()
         };
-        let size = usize::try_from(self.mask).unwrap() + 1;
-        unsafe { dealloc(self.shared.as_ptr().cast::<u8>(), Self::shared_layout(size)) };
+        if self.segment.is_none() {
+            // the memory of a segment is not reclaimed
+            let size = usize::try_from(self.mask).unwrap() + 1;
+            unsafe { dealloc(self.shared.as_ptr().cast::<u8>(), Self::shared_layout(size)) };
+        }
+    }
+
+    // Safety: raw must have been obtained from Common::raw on a ring in the
+    // same segment.
+    unsafe fn from_raw(segment: Arc<Segment>, raw: RawEndpoint) -> Common<T, W> {
+        Common {
+            shared: segment.ptr(raw.offset).cast::<AtomicU32>(),
+            mask: raw.size - 1,
+            stats: Stats::new(),
+            segment: Some(segment),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn raw(&self, idx: u32) -> Option<RawEndpoint> {
+        let segment = self.segment.as_ref()?;
+        Some(RawEndpoint {
+            offset: segment.offset(self.shared.cast::<u8>()),
+            size: self.mask + 1,
+            idx,
+        })
     }
 
     pub fn shared(&self) -> &AtomicU32 {
@@ -251,6 +648,12 @@ impl<T, W> Common<T, W> {
         }
     }
 
+    // Number of slots that are not free. This includes the slots that have
+    // been read by the receiver but not cleared yet.
+    pub fn occupied(&self, ordering: Ordering) -> u32 {
+        (self.shared().load(ordering) & ((1 << WRITE_IDX_SHIFT) - 1)) >> AVAILABLE_SHIFT
+    }
+
     pub fn slot_buf(&self) -> NonNull<Slot<T>> {
         unsafe {
             self.shared
@@ -271,6 +674,7 @@ impl<T, W: Waker> Drop for Sender<T, W> {
         let old_shared = self.common.shared().fetch_or(TRANSMITTERS_DROPPED, Relaxed);
         // wake the receiver if it is sleeping
         if old_shared & RECEIVER_SLEEPING != 0 {
+            self.common.stats.woke();
             W::wake(&self.common);
         }
 
@@ -292,20 +696,7 @@ impl<T, W: Waker> Drop for Sender<T, W> {
 impl<T, W> Drop for Receiver<T, W> {
     fn drop(&mut self) {
         self.common.read_idx().store(self.read_idx, Relaxed);
-        let ordering = if size_of::<T>() == 0 {
-            // if T is ZST, reads and writes are a noop, so synchronization
-            // is not needed
-            Relaxed
-        } else {
-            // Release because the read of these items from the buffer needs
-            // to happen before an overwrite of the same slot by the sender
-            // (the sender can still attempt to send even if the receiver is
-            // dropped).
-            Release
-        };
-        self.common
-            .shared()
-            .fetch_sub(self.clear_pending << AVAILABLE_SHIFT, ordering);
+        self.clear();
         // Release ordering used here to establish a happens-before relationship
         // between storing at read_idX() and loading it on deallocate when run
         // by Sender's drop. Acquire ordering used here to establish a
@@ -1,9 +1,14 @@
 use super::chan::{
-    self, AVAILABLE_SHIFT, Common, MAX_PENDING_SLOTS, RECEIVER_SLEEPING, TRANSMITTERS_DROPPED,
-    WRITE_IDX_SHIFT, Waker,
+    self, AVAILABLE_SHIFT, Common, RECEIVER_SLEEPING, TRANSMITTERS_DROPPED, WRITE_IDX_SHIFT, Waker,
+};
+use crate::channel::{
+    RecvTimeoutError, TryRecvError, futex,
+    wait::{Fallback, WaitStrategy, Waiter},
+};
+use std::{
+    sync::atomic::Ordering::Relaxed,
+    time::{Duration, Instant},
 };
-use libc::{FUTEX_PRIVATE_FLAG, FUTEX_WAIT, FUTEX_WAKE, SYS_futex, syscall};
-use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
 
 #[derive(Debug)]
 pub struct Sender<T>(chan::Sender<T, FutexWaker>);
@@ -12,6 +17,53 @@ impl<T> Sender<T> {
     pub fn send(&self, value: T) {
         self.0.send(value)
     }
+
+    /// Sends a value if the channel is not full.
+    ///
+    /// If the channel is full, the value is given back in the `Err`.
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        self.0.try_send(value)
+    }
+
+    /// Sends all the items of an iterator.
+    ///
+    /// The slots for the items are claimed with a single atomic operation,
+    /// which is faster than sending them one by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel does not have free slots for all the items. In
+    /// this case no item is sent.
+    pub fn send_batch<I>(&self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.0.send_batch(iter)
+    }
+
+    /// Returns the number of slots of the channel that are in use.
+    ///
+    /// This includes the items that have been received but whose slots have
+    /// not been freed yet by the receiver, which does this in batches.
+    pub fn len(&self) -> usize {
+        self.0.common.occupied(Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
+
+    /// Returns the number of slots of the channel.
+    ///
+    /// This is at least the size with which the channel was created.
+    pub fn capacity(&self) -> usize {
+        self.0.common.mask as usize
+    }
 }
 
 impl<T> Clone for Sender<T> {
@@ -28,47 +80,117 @@ unsafe impl<T: Send> Send for Receiver<T> {}
 // so it is not possible to have multiple consumers by sharing references
 unsafe impl<T: Send> Sync for Receiver<T> {}
 
+// The waker of the channels in shared memory segments uses futexes that are
+// not private to the process.
 #[derive(Debug, Clone, Default)]
-struct FutexWaker {}
-
-impl Waker for FutexWaker {
-    fn wake<T>(common: &Common<T, FutexWaker>) {
-        unsafe {
-            syscall(
-                SYS_futex,
-                common.shared().as_ptr().cast_const(),
-                FUTEX_WAKE | FUTEX_PRIVATE_FLAG,
-                1,
-            )
-        };
+pub(super) struct FutexWaker<const SHARED: bool = false> {}
+
+impl<const SHARED: bool> Waker for FutexWaker<SHARED> {
+    fn wake<T>(common: &Common<T, FutexWaker<SHARED>>) {
+        if SHARED {
+            futex::wake_shared(common.shared(), 1);
+        } else {
+            futex::wake(common.shared(), 1);
+        }
     }
 }
 
 pub fn channel<T>(size: usize) -> (Sender<T>, Receiver<T>) {
-    let (tx, rx) = chan::channel(size);
-    (Sender(tx), Receiver(rx))
+    channel_with_wait_strategy(size, WaitStrategy::FUTEX_DEFAULT)
 }
 
-const RECEIVER_SPINS: usize = 1 << 13;
+pub fn channel_with_wait_strategy<T>(
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = chan::channel(size, wait_strategy);
+    (Sender(tx), Receiver(rx))
+}
 
 impl<T> Receiver<T> {
     pub fn recv(&mut self) -> Option<T> {
-        self.0.recv_futex_waker()
+        self.0.recv_futex_waker(None).ok()
+    }
+
+    /// Receives up to `max` items, appending them to `buf`.
+    ///
+    /// This waits for an item and then receives the items that are available
+    /// without waiting. Returns the number of items received, which is zero
+    /// only if the channel is disconnected or `max` is zero.
+    pub fn recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+        let Some(value) = self.recv() else {
+            return 0;
+        };
+        buf.push(value);
+        1 + self.0.try_recv_batch(buf, max - 1).unwrap_or(0)
+    }
+
+    /// Receives an item, waiting at most for the given timeout.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        // A timeout so large that the deadline overflows is the same as no
+        // timeout.
+        self.0.recv_futex_waker(Instant::now().checked_add(timeout))
+    }
+
+    /// Receives an item, waiting at most until the given deadline.
+    pub fn recv_deadline(&mut self, deadline: Instant) -> Result<T, RecvTimeoutError> {
+        self.0.recv_futex_waker(Some(deadline))
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    /// Receives up to `max` items that are available without waiting,
+    /// appending them to `buf`.
+    ///
+    /// Returns the number of items received.
+    pub fn try_recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> Result<usize, TryRecvError> {
+        self.0.try_recv_batch(buf, max)
+    }
+
+    /// Returns the number of items that can be received.
+    pub fn len(&self) -> usize {
+        self.0.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.0.waiter.strategy()
+    }
+
+    pub fn set_wait_strategy(&mut self, wait_strategy: WaitStrategy) {
+        self.0.waiter = Waiter::new(wait_strategy);
+    }
+
+    /// Returns a handle to the statistics of the channel.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> crate::channel::StatsHandle {
+        self.0.common.stats.handle()
     }
 }
 
-impl<T> chan::Receiver<T, FutexWaker> {
-    fn recv_futex_waker(&mut self) -> Option<T> {
+impl<T, const SHARED: bool> chan::Receiver<T, FutexWaker<SHARED>> {
+    pub(super) fn recv_futex_waker(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<T, RecvTimeoutError> {
         let mut available = self.available;
         if available >> AVAILABLE_SHIFT == 0 {
-            for _ in 0..RECEIVER_SPINS {
-                available = self.common.shared().load(Relaxed) & ((1 << WRITE_IDX_SHIFT) - 1);
-                if available >> AVAILABLE_SHIFT != self.clear_pending {
-                    break;
-                }
-                std::hint::spin_loop();
-            }
-            if available >> AVAILABLE_SHIFT == self.clear_pending {
+            let common = &self.common;
+            let clear_pending = self.clear_pending;
+            let ready = self.waiter.spin(|| {
+                let shared = common.shared().load(Relaxed);
+                available = shared & ((1 << WRITE_IDX_SHIFT) - 1);
+                available >> AVAILABLE_SHIFT != clear_pending || shared & TRANSMITTERS_DROPPED != 0
+            });
+            if !ready || available >> AVAILABLE_SHIFT == self.clear_pending {
                 loop {
                     let shared = self.common.shared().load(Relaxed);
                     available = shared & ((1 << WRITE_IDX_SHIFT) - 1);
@@ -77,69 +199,52 @@ impl<T> chan::Receiver<T, FutexWaker> {
                     }
                     if shared & TRANSMITTERS_DROPPED != 0 {
                         // channel is empty and all senders were dropped
-                        return None;
+                        return Err(RecvTimeoutError::Disconnected);
+                    }
+                    match self.waiter.fallback() {
+                        fallback @ (Fallback::Spin | Fallback::Yield) => {
+                            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                                return Err(RecvTimeoutError::Timeout);
+                            }
+                            if fallback == Fallback::Spin {
+                                std::hint::spin_loop();
+                            } else {
+                                std::thread::yield_now();
+                            }
+                        }
+                        Fallback::Sleep => {
+                            self.common.shared().fetch_or(RECEIVER_SLEEPING, Relaxed);
+                            self.common.stats.slept();
+                            let wait = if SHARED {
+                                futex::wait_shared
+                            } else {
+                                futex::wait
+                            };
+                            if !wait(self.common.shared(), shared | RECEIVER_SLEEPING, deadline) {
+                                // the flag is only cleared by the sender
+                                // when it sends an item
+                                self.common.shared().fetch_and(!RECEIVER_SLEEPING, Relaxed);
+                                return Err(RecvTimeoutError::Timeout);
+                            }
+                        }
                     }
-                    self.common.shared().fetch_or(RECEIVER_SLEEPING, Relaxed);
-                    unsafe {
-                        syscall(
-                            SYS_futex,
-                            self.common.shared().as_ptr().cast_const(),
-                            FUTEX_WAIT | FUTEX_PRIVATE_FLAG,
-                            shared | RECEIVER_SLEEPING,
-                            std::ptr::null::<libc::timespec>(),
-                        )
-                    };
                 }
             }
-            self.available = available - (self.clear_pending << AVAILABLE_SHIFT);
+            self.set_available(available - (self.clear_pending << AVAILABLE_SHIFT));
         }
-        let ordering = if size_of::<T>() == 0 {
-            // if T is ZST, the read below is a noop, so it doesn't need to be
-            // synchronized
-            Relaxed
-        } else {
-            // Acquire because the write of the value in the slot by the sender
-            // needs to happen before the read
-            Acquire
-        };
-        let value = unsafe {
-            let slot = self
-                .common
-                .slot_buf()
-                .add((self.read_idx & self.common.mask) as usize)
-                .as_ptr();
-            // spin until the sequence on the item to read matches the expected value
-            while (*slot).sequence.load(ordering) != self.read_idx {}
-            std::ptr::read(&raw const (*slot).value)
-        };
-        self.clear_pending += 1;
-        if self.clear_pending == MAX_PENDING_SLOTS {
-            let ordering = if size_of::<T>() == 0 {
-                // if T is ZST, reads and writes are a noop, so synchronization
-                // is not needed
-                Relaxed
-            } else {
-                // Release because the read of this item from the buffer needs
-                // to happen before an overwrite of the same slot by the sender.
-                Release
-            };
-            let old_shared = self
-                .common
-                .shared()
-                .fetch_sub(MAX_PENDING_SLOTS << AVAILABLE_SHIFT, ordering);
-            self.available = (old_shared - (MAX_PENDING_SLOTS << AVAILABLE_SHIFT))
-                & ((1 << WRITE_IDX_SHIFT) - 1);
-            self.clear_pending = 0;
-        } else {
-            self.available -= 1 << AVAILABLE_SHIFT;
-        }
-        self.read_idx = (self.read_idx.wrapping_add(1) << WRITE_IDX_SHIFT) >> WRITE_IDX_SHIFT;
-        Some(value)
+        Ok(unsafe { self.read_available() })
     }
 }
 
 #[cfg(test)]
 mod test {
+    use super::RECEIVER_SLEEPING;
+    use crate::channel::{RecvTimeoutError, TryRecvError};
+    use std::{
+        sync::atomic::Ordering::Relaxed,
+        time::{Duration, Instant},
+    };
+
     #[test]
     fn single_sender() {
         let cap = 4096;
@@ -187,4 +292,116 @@ mod test {
         }
         assert!(rx.recv().is_none());
     }
+
+    #[test]
+    fn try_send_try_recv() {
+        let (tx, mut rx) = super::channel(4);
+        assert!(tx.capacity() >= 4);
+        assert!(tx.is_empty());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        let mut sent = 0;
+        while tx.try_send(sent).is_ok() {
+            sent += 1;
+        }
+        assert_eq!(sent, tx.capacity());
+        assert!(tx.is_full());
+        assert_eq!(tx.try_send(sent), Err(sent));
+        assert_eq!(rx.len(), sent);
+        for n in 0..sent {
+            assert_eq!(rx.try_recv(), Ok(n));
+        }
+        assert!(rx.is_empty());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        // the slots are freed in batches, so some of them can be reused now
+        assert!(!tx.is_full());
+        tx.try_send(sent).unwrap();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(sent));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn recv_timeout() {
+        let (tx, mut rx) = super::channel(16);
+        let timeout = Duration::from_millis(10);
+        let start = Instant::now();
+        assert_eq!(rx.recv_timeout(timeout), Err(RecvTimeoutError::Timeout));
+        assert!(start.elapsed() >= timeout);
+        assert_eq!(rx.0.common.shared().load(Relaxed) & RECEIVER_SLEEPING, 0);
+        assert_eq!(
+            rx.recv_deadline(Instant::now()),
+            Err(RecvTimeoutError::Timeout)
+        );
+        let sender_thread = std::thread::spawn(move || {
+            let tx = tx;
+            std::thread::sleep(Duration::from_millis(20));
+            tx.send(42);
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok(42));
+        sender_thread.join().unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::MAX),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn batches() {
+        let num_items = 100_000;
+        let senders = 4;
+        let max_batch_size = 16;
+        let (tx, mut rx) = super::channel(64);
+        let sender_threads = (0..senders)
+            .map(|thread_num| {
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    let mut items = (0..num_items).skip(thread_num).step_by(senders);
+                    for batch_size in (1..=max_batch_size).cycle() {
+                        if items.len() == 0 {
+                            break;
+                        }
+                        // wait for room for the batches of all the senders
+                        while tx.capacity() - tx.len() < senders * max_batch_size {
+                            std::thread::yield_now();
+                        }
+                        let batch = items.by_ref().take(batch_size).collect::<Vec<_>>();
+                        tx.send_batch(batch);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(tx);
+        let mut received = Vec::new();
+        while rx.recv_batch(&mut received, 50) != 0 {}
+        received.sort_unstable();
+        let expected = (0..num_items).collect::<Vec<_>>();
+        assert_eq!(received, expected);
+        for thread in sender_threads.into_iter() {
+            thread.join().unwrap();
+        }
+        assert_eq!(
+            rx.try_recv_batch(&mut received, 1),
+            Err(TryRecvError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn batch_without_room() {
+        let (tx, mut rx) = super::channel(4);
+        let capacity = tx.capacity() as u32;
+        tx.send(0);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            tx.send_batch(1..capacity + 1)
+        }));
+        assert!(result.is_err());
+        // nothing has been sent
+        assert_eq!(tx.len(), 1);
+        tx.send_batch(1..capacity);
+        let mut received = Vec::new();
+        assert_eq!(
+            rx.try_recv_batch(&mut received, capacity as usize),
+            Ok(capacity as usize)
+        );
+        assert_eq!(received, (0..capacity).collect::<Vec<_>>());
+    }
 }
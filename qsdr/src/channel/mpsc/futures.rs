@@ -1,12 +1,15 @@
 use super::chan::{
-    self, AVAILABLE_SHIFT, Common, MAX_PENDING_SLOTS, RECEIVER_SLEEPING, TRANSMITTERS_DROPPED,
-    WRITE_IDX_SHIFT, Waker,
+    self, AVAILABLE_SHIFT, Common, RECEIVER_SLEEPING, TRANSMITTERS_DROPPED, WRITE_IDX_SHIFT, Waker,
+};
+use crate::channel::{
+    TryRecvError,
+    wait::{Fallback, WaitStrategy, Waiter},
 };
 use futures::stream::Stream;
 use std::{
     future::poll_fn,
     pin::Pin,
-    sync::atomic::Ordering::{Acquire, Relaxed, Release},
+    sync::atomic::Ordering::Relaxed,
     task::{Context, Poll},
 };
 
@@ -17,6 +20,53 @@ impl<T> Sender<T> {
     pub fn send(&self, value: T) {
         self.0.send(value)
     }
+
+    /// Sends a value if the channel is not full.
+    ///
+    /// If the channel is full, the value is given back in the `Err`.
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        self.0.try_send(value)
+    }
+
+    /// Sends all the items of an iterator.
+    ///
+    /// The slots for the items are claimed with a single atomic operation,
+    /// which is faster than sending them one by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel does not have free slots for all the items. In
+    /// this case no item is sent.
+    pub fn send_batch<I>(&self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.0.send_batch(iter)
+    }
+
+    /// Returns the number of slots of the channel that are in use.
+    ///
+    /// This includes the items that have been received but whose slots have
+    /// not been freed yet by the receiver, which does this in batches.
+    pub fn len(&self) -> usize {
+        self.0.common.occupied(Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
+
+    /// Returns the number of slots of the channel.
+    ///
+    /// This is at least the size with which the channel was created.
+    pub fn capacity(&self) -> usize {
+        self.0.common.mask as usize
+    }
 }
 
 impl<T> Clone for Sender<T> {
@@ -43,7 +93,14 @@ impl Waker for AtomicWaker {
 }
 
 pub fn channel<T>(size: usize) -> (Sender<T>, Receiver<T>) {
-    let (tx, rx) = chan::channel(size);
+    channel_with_wait_strategy(size, WaitStrategy::FUTURES_DEFAULT)
+}
+
+pub fn channel_with_wait_strategy<T>(
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = chan::channel(size, wait_strategy);
     (Sender(tx), Receiver(rx))
 }
 
@@ -52,13 +109,71 @@ impl<T> Receiver<T> {
         poll_fn(|cx| self.poll(cx)).await
     }
 
+    /// Receives up to `max` items, appending them to `buf`.
+    ///
+    /// This waits for an item and then receives the items that are available
+    /// without waiting. Returns the number of items received, which is zero
+    /// only if the channel is disconnected or `max` is zero.
+    pub async fn recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+        let Some(value) = self.recv().await else {
+            return 0;
+        };
+        buf.push(value);
+        1 + self.0.try_recv_batch(buf, max - 1).unwrap_or(0)
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    /// Receives up to `max` items that are available without waiting,
+    /// appending them to `buf`.
+    ///
+    /// Returns the number of items received.
+    pub fn try_recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> Result<usize, TryRecvError> {
+        self.0.try_recv_batch(buf, max)
+    }
+
+    /// Returns the number of items that can be received.
+    pub fn len(&self) -> usize {
+        self.0.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.0.waiter.strategy()
+    }
+
+    pub fn set_wait_strategy(&mut self, wait_strategy: WaitStrategy) {
+        self.0.waiter = Waiter::new(wait_strategy);
+    }
+
+    /// Returns a handle to the statistics of the channel.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> crate::channel::StatsHandle {
+        self.0.common.stats.handle()
+    }
+
     fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
         let mut available = self.0.available;
         if available >> AVAILABLE_SHIFT == 0 {
             // Normally this loop spins at most once, but it might spin multiple
             // times if the waker is waked before this returns Poll::Pending (in
             // which case the wake might have been lost).
-            let mut shared = self.0.common.shared().load(Relaxed);
+            let mut shared = 0;
+            let common = &self.0.common;
+            let clear_pending = self.0.clear_pending;
+            self.0.waiter.spin(|| {
+                shared = common.shared().load(Relaxed);
+                let available = shared & ((1 << WRITE_IDX_SHIFT) - 1);
+                available >> AVAILABLE_SHIFT != clear_pending || shared & TRANSMITTERS_DROPPED != 0
+            });
             loop {
                 available = shared & ((1 << WRITE_IDX_SHIFT) - 1);
                 if available >> AVAILABLE_SHIFT != self.0.clear_pending {
@@ -68,6 +183,11 @@ impl<T> Receiver<T> {
                     // channel is empty and all senders were dropped
                     return Poll::Ready(None);
                 }
+                if self.0.waiter.fallback() != Fallback::Sleep {
+                    // let the executor poll again
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
                 self.0.common.shared().fetch_or(RECEIVER_SLEEPING, Relaxed);
                 self.0.common.waker().0.register(cx.waker());
                 // Check that the shared atomic is still what we expect. This
@@ -78,55 +198,15 @@ impl<T> Receiver<T> {
                 let old_shared = shared;
                 shared = self.0.common.shared().load(Relaxed);
                 if shared == old_shared | RECEIVER_SLEEPING {
+                    self.0.common.stats.slept();
                     return Poll::Pending;
                 }
             }
-            self.0.available = available - (self.0.clear_pending << AVAILABLE_SHIFT);
+            let clear_pending = self.0.clear_pending;
+            self.0
+                .set_available(available - (clear_pending << AVAILABLE_SHIFT));
         }
-        let ordering = if size_of::<T>() == 0 {
-            // if T is ZST, the read below is a noop, so it doesn't need to be
-            // synchronized
-            Relaxed
-        } else {
-            // Acquire because the write of the value in the slot by the sender
-            // needs to happen before the read
-            Acquire
-        };
-        let value = unsafe {
-            let slot = self
-                .0
-                .common
-                .slot_buf()
-                .add((self.0.read_idx & self.0.common.mask) as usize)
-                .as_ptr();
-            // spin until the sequence on the item to read matches the expected value
-            while (*slot).sequence.load(ordering) != self.0.read_idx {}
-            std::ptr::read(&raw const (*slot).value)
-        };
-        self.0.clear_pending += 1;
-        if self.0.clear_pending == MAX_PENDING_SLOTS {
-            let ordering = if size_of::<T>() == 0 {
-                // if T is ZST, reads and writes are a noop, so synchronization
-                // is not needed
-                Relaxed
-            } else {
-                // Release because the read of this item from the buffer needs
-                // to happen before an overwrite of the same slot by the sender.
-                Release
-            };
-            let old_shared = self
-                .0
-                .common
-                .shared()
-                .fetch_sub(MAX_PENDING_SLOTS << AVAILABLE_SHIFT, ordering);
-            self.0.available = (old_shared - (MAX_PENDING_SLOTS << AVAILABLE_SHIFT))
-                & ((1 << WRITE_IDX_SHIFT) - 1);
-            self.0.clear_pending = 0;
-        } else {
-            self.0.available -= 1 << AVAILABLE_SHIFT;
-        }
-        self.0.read_idx = (self.0.read_idx.wrapping_add(1) << WRITE_IDX_SHIFT) >> WRITE_IDX_SHIFT;
-        Poll::Ready(Some(value))
+        Poll::Ready(Some(unsafe { self.0.read_available() }))
     }
 }
 
@@ -140,6 +220,8 @@ impl<T> Stream for Receiver<T> {
 
 #[cfg(test)]
 mod test {
+    use crate::channel::WaitStrategy;
+
     #[test]
     fn single_sender() {
         let cap = 4096;
@@ -193,4 +275,33 @@ mod test {
             assert!(rx.recv().await.is_none());
         });
     }
+
+    #[test]
+    fn wait_strategies() {
+        let cap = 4096;
+        for wait_strategy in [
+            WaitStrategy::BusySpin,
+            WaitStrategy::SpinYield { spins: 16 },
+            WaitStrategy::SpinSleep { spins: 16 },
+            WaitStrategy::Adaptive { max_spins: 1 << 10 },
+        ] {
+            let (tx, mut rx) = super::channel(cap);
+            rx.set_wait_strategy(wait_strategy);
+            let sender_thread = std::thread::spawn(move || {
+                for n in 0..cap {
+                    tx.send(n);
+                }
+            });
+            futures::executor::block_on(async {
+                let mut received = Vec::with_capacity(cap);
+                for _ in 0..cap {
+                    received.push(rx.recv().await.unwrap());
+                }
+                let expected = (0..cap).collect::<Vec<_>>();
+                assert_eq!(received, expected);
+                sender_thread.join().unwrap();
+                assert!(rx.recv().await.is_none());
+            });
+        }
+    }
 }
@@ -0,0 +1,256 @@
+//! Channels in shared memory segments.
+//!
+//! These channels behave as the [futex channels](super::futex), but they are
+//! allocated in a [`Segment`], so that each endpoint can be used by a
+//! different process. An endpoint is moved to another process by converting it
+//! into a [`RawEndpoint`] with `into_raw`, sending this to the other process,
+//! and recreating the endpoint there with `from_raw`.
+//!
+//! The items are copied bitwise between processes, so they should be plain
+//! data, such as the indices of the buffers of a circuit.
+
+use super::{chan, futex::FutexWaker};
+use crate::{
+    channel::{RecvTimeoutError, TryRecvError, wait::WaitStrategy, wait::Waiter},
+    shm::{RawEndpoint, Segment},
+};
+use anyhow::Result;
+use std::{
+    sync::{Arc, atomic::Ordering::Relaxed},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+pub struct Sender<T>(chan::Sender<T, FutexWaker<true>>);
+
+impl<T: Copy> Sender<T> {
+    /// Recreates a sender from its raw representation.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must have been obtained with [`Sender::into_raw`] from a sender
+    /// with the same item type in the same segment (possibly mapped by another
+    /// process), and it must be used only once. If the sender was created in
+    /// another process, the items must be valid in this process.
+    pub unsafe fn from_raw(segment: &Arc<Segment>, raw: RawEndpoint) -> Sender<T> {
+        Sender(unsafe { chan::Sender::from_raw(Arc::clone(segment), raw) })
+    }
+}
+
+impl<T> Sender<T> {
+    /// Converts the sender into its raw representation.
+    pub fn into_raw(self) -> RawEndpoint {
+        self.0.into_raw().unwrap()
+    }
+
+    pub fn send(&self, value: T) {
+        self.0.send(value)
+    }
+
+    /// Sends a value if the channel is not full.
+    ///
+    /// If the channel is full, the value is given back in the `Err`.
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        self.0.try_send(value)
+    }
+
+    /// Sends all the items of an iterator.
+    ///
+    /// The slots for the items are claimed with a single atomic operation,
+    /// which is faster than sending them one by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel does not have free slots for all the items. In
+    /// this case no item is sent.
+    pub fn send_batch<I>(&self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.0.send_batch(iter)
+    }
+
+    /// Returns the number of slots of the channel that are in use.
+    ///
+    /// This includes the items that have been received but whose slots have
+    /// not been freed yet by the receiver, which does this in batches.
+    pub fn len(&self) -> usize {
+        self.0.common.occupied(Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
+
+    /// Returns the number of slots of the channel.
+    ///
+    /// This is at least the size with which the channel was created.
+    pub fn capacity(&self) -> usize {
+        self.0.common.mask as usize
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        Sender(self.0.clone())
+    }
+}
+
+#[derive(Debug)]
+pub struct Receiver<T>(chan::Receiver<T, FutexWaker<true>>);
+
+unsafe impl<T: Send> Send for Receiver<T> {}
+// Receiver can be Sync, because the receive methods use &mut self,
+// so it is not possible to have multiple consumers by sharing references
+unsafe impl<T: Send> Sync for Receiver<T> {}
+
+/// Creates a channel in a shared memory segment.
+pub fn channel<T: Copy>(segment: &Arc<Segment>, size: usize) -> Result<(Sender<T>, Receiver<T>)> {
+    channel_with_wait_strategy(segment, size, WaitStrategy::FUTEX_DEFAULT)
+}
+
+pub fn channel_with_wait_strategy<T: Copy>(
+    segment: &Arc<Segment>,
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> Result<(Sender<T>, Receiver<T>)> {
+    let (tx, rx) = chan::channel_in(Arc::clone(segment), size, wait_strategy)?;
+    Ok((Sender(tx), Receiver(rx)))
+}
+
+impl<T: Copy> Receiver<T> {
+    /// Recreates a receiver from its raw representation.
+    ///
+    /// The receiver uses [`WaitStrategy::FUTEX_DEFAULT`].
+    ///
+    /// # Safety
+    ///
+    /// `raw` must have been obtained with [`Receiver::into_raw`] from a
+    /// receiver with the same item type in the same segment (possibly mapped
+    /// by another process), and it must be used only once. If the receiver was
+    /// created in another process, the items must be valid in this process.
+    pub unsafe fn from_raw(segment: &Arc<Segment>, raw: RawEndpoint) -> Receiver<T> {
+        Receiver(unsafe {
+            chan::Receiver::from_raw(Arc::clone(segment), raw, WaitStrategy::FUTEX_DEFAULT)
+        })
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Converts the receiver into its raw representation.
+    pub fn into_raw(self) -> RawEndpoint {
+        self.0.into_raw().unwrap()
+    }
+
+    pub fn recv(&mut self) -> Option<T> {
+        self.0.recv_futex_waker(None).ok()
+    }
+
+    /// Receives up to `max` items, appending them to `buf`.
+    ///
+    /// This waits for an item and then receives the items that are available
+    /// without waiting. Returns the number of items received, which is zero
+    /// only if the channel is disconnected or `max` is zero.
+    pub fn recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+        let Some(value) = self.recv() else {
+            return 0;
+        };
+        buf.push(value);
+        1 + self.0.try_recv_batch(buf, max - 1).unwrap_or(0)
+    }
+
+    /// Receives an item, waiting at most for the given timeout.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        // A timeout so large that the deadline overflows is the same as no
+        // timeout.
+        self.0.recv_futex_waker(Instant::now().checked_add(timeout))
+    }
+
+    /// Receives an item, waiting at most until the given deadline.
+    pub fn recv_deadline(&mut self, deadline: Instant) -> Result<T, RecvTimeoutError> {
+        self.0.recv_futex_waker(Some(deadline))
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    /// Receives up to `max` items that are available without waiting,
+    /// appending them to `buf`.
+    ///
+    /// Returns the number of items received.
+    pub fn try_recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> Result<usize, TryRecvError> {
+        self.0.try_recv_batch(buf, max)
+    }
+
+    /// Returns the number of items that can be received.
+    pub fn len(&self) -> usize {
+        self.0.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.0.waiter.strategy()
+    }
+
+    pub fn set_wait_strategy(&mut self, wait_strategy: WaitStrategy) {
+        self.0.waiter = Waiter::new(wait_strategy);
+    }
+
+    /// Returns a handle to the statistics of the channel in this process.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> crate::channel::StatsHandle {
+        self.0.common.stats.handle()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Receiver;
+    use crate::{channel::WaitStrategy, shm::Segment};
+    use std::{os::fd::AsFd, sync::Arc};
+
+    #[test]
+    fn multiple_senders_two_mappings() {
+        let cap = 4096;
+        let senders = 4;
+        let segment = Arc::new(Segment::new("test", 1 << 20).unwrap());
+        let other =
+            Arc::new(Segment::from_fd(segment.as_fd().try_clone_to_owned().unwrap()).unwrap());
+        let (tx, rx) = super::channel::<u32>(&segment, cap).unwrap();
+        let mut rx = unsafe { Receiver::<u32>::from_raw(&other, rx.into_raw()) };
+        rx.set_wait_strategy(WaitStrategy::SpinSleep { spins: 0 });
+        let sender_threads = (0..senders)
+            .map(|thread_num| {
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    for n in (0..cap).skip(thread_num).step_by(senders) {
+                        tx.send(n as u32);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(tx);
+        let mut received = std::iter::repeat_with(|| rx.recv().unwrap())
+            .take(cap)
+            .collect::<Vec<_>>();
+        received.sort_unstable();
+        let expected = (0..cap as u32).collect::<Vec<_>>();
+        assert_eq!(received, expected);
+        for thread in sender_threads.into_iter() {
+            thread.join().unwrap();
+        }
+        assert!(rx.recv().is_none());
+    }
+}
@@ -1,10 +1,23 @@
+use crate::{
+    channel::{
+        TryRecvError,
+        stats::Stats,
+        wait::{WaitStrategy, Waiter},
+    },
+    shm::{RawEndpoint, Segment},
+};
+use anyhow::Result;
 use std::{
     alloc::{Layout, alloc, dealloc, handle_alloc_error},
     marker::PhantomData,
     ptr::NonNull,
-    sync::atomic::{
-        AtomicU32,
-        Ordering::{AcqRel, Relaxed, Release},
+    sync::{
+        Arc,
+        atomic::{
+            AtomicU32,
+            Ordering::{self, AcqRel, Acquire, Relaxed, Release},
+            fence,
+        },
     },
 };
 
@@ -38,10 +51,29 @@ pub struct Receiver<T, W> {
     pub read_idx: u32,
     pub available: u32,
     pub clear_pending: u32,
+    pub waiter: Waiter,
+}
+
+pub fn channel<T, W: Waker>(
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> (Sender<T, W>, Receiver<T, W>) {
+    endpoints(Common::new(size), wait_strategy)
+}
+
+// Creates a channel whose ring is allocated in a shared memory segment.
+pub fn channel_in<T, W: Waker>(
+    segment: Arc<Segment>,
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> Result<(Sender<T, W>, Receiver<T, W>)> {
+    Ok(endpoints(Common::new_in(segment, size)?, wait_strategy))
 }
 
-pub fn channel<T, W: Waker>(size: usize) -> (Sender<T, W>, Receiver<T, W>) {
-    let common = Common::new(size);
+fn endpoints<T, W: Waker>(
+    common: Common<T, W>,
+    wait_strategy: WaitStrategy,
+) -> (Sender<T, W>, Receiver<T, W>) {
     (
         Sender {
             common: common.clone(),
@@ -52,20 +84,86 @@ pub fn channel<T, W: Waker>(size: usize) -> (Sender<T, W>, Receiver<T, W>) {
             read_idx: 0,
             available: 0,
             clear_pending: 0,
+            waiter: Waiter::new(wait_strategy),
         },
     )
 }
 
 impl<T, W: Waker> Sender<T, W> {
+    // Safety: raw must have been obtained from into_raw on a sender of a
+    // channel in the same segment.
+    pub unsafe fn from_raw(segment: Arc<Segment>, raw: RawEndpoint) -> Sender<T, W> {
+        Sender {
+            common: unsafe { Common::from_raw(segment, raw) },
+            write_idx: raw.idx,
+        }
+    }
+
+    // Returns the location of the ring of a channel allocated in a segment
+    // without dropping the sender.
+    pub fn into_raw(self) -> Option<RawEndpoint> {
+        let raw = self.common.raw(self.write_idx)?;
+        std::mem::forget(self);
+        Some(raw)
+    }
+
     pub fn send(&mut self, value: T) {
-        // these variables are used because otherwise the compiler loads the
-        // values several times
+        unsafe { self.write(0, value) };
+        self.publish(1);
+    }
+
+    // Sends all the items of the iterator, making them available to the
+    // receiver with a single atomic operation. The channel must have free
+    // slots for all of them, which is checked before sending any item.
+    pub fn send_batch<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let iter = iter.into_iter();
+        let count = iter.len();
+        if count == 0 {
+            return;
+        }
+        let ordering = if size_of::<T>() == 0 {
+            Relaxed
+        } else {
+            // Acquire because the read of the slots that are overwritten
+            // needs to happen before the writes.
+            Acquire
+        };
+        let free = self.common.mask - self.common.occupied(ordering);
+        assert!(
+            count <= free as usize,
+            "send_batch() called without room for the batch"
+        );
+        let mut written = 0;
+        for value in iter.take(count) {
+            unsafe { self.write(written, value) };
+            written += 1;
+        }
+        // If the iterator is shorter than its length, only the items that
+        // have been written are sent.
+        if written != 0 {
+            self.publish(written);
+        }
+    }
+
+    // Writes the value in the slot that is offset positions after the next
+    // slot to send.
+    //
+    // Safety: the slot must be free.
+    unsafe fn write(&mut self, offset: u32, value: T) {
         unsafe {
             self.common
                 .item_buf()
-                .add((self.write_idx & self.common.mask) as usize)
+                .add((self.write_idx.wrapping_add(offset) & self.common.mask) as usize)
                 .write(value)
         };
+    }
+
+    // Makes the next count slots available to the receiver.
+    fn publish(&mut self, count: u32) {
         let ordering = if size_of::<T>() == 0 {
             // if T is ZST, the write above has been a noop, so it doesn't need
             // to be synchronized
@@ -73,23 +171,195 @@ impl<T, W: Waker> Sender<T, W> {
         } else {
             // Acquire because the overwrite of buffer slots by future send()
             // calls needs to happen after the items in those slots have been
-            // read by the receiver. Release because the write of this item in
-            // the buffer needs to happen before it is read by the receiver.
+            // read by the receiver. Release because the write of these items
+            // in the buffer needs to happen before they are read by the
+            // receiver.
             AcqRel
         };
         let old_shared = self
             .common
             .shared()
-            .fetch_add(1 << AVAILABLE_SHIFT, ordering);
+            .fetch_add(count << AVAILABLE_SHIFT, ordering);
+        let occupied = (old_shared >> AVAILABLE_SHIFT) + count;
         assert!(
-            old_shared >> AVAILABLE_SHIFT != self.common.mask,
+            occupied <= self.common.mask,
             "send() called on a full channel"
         );
+        self.common.stats.sent_batch(count);
         if old_shared & RECEIVER_SLEEPING != 0 {
             self.common.shared().fetch_and(!RECEIVER_SLEEPING, Relaxed);
+            self.common.stats.woke();
             W::wake(&self.common);
         }
-        self.write_idx = self.write_idx.wrapping_add(1);
+        self.write_idx = self.write_idx.wrapping_add(count);
+    }
+
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        let ordering = if size_of::<T>() == 0 {
+            Relaxed
+        } else {
+            // Acquire because the read of the slot that send() will overwrite
+            // needs to happen before the write.
+            Acquire
+        };
+        if self.common.occupied(ordering) >= self.common.mask {
+            return Err(value);
+        }
+        self.send(value);
+        Ok(())
+    }
+}
+
+impl<T, W> Receiver<T, W> {
+    // Safety: raw must have been obtained from into_raw on a receiver of a
+    // channel in the same segment.
+    pub unsafe fn from_raw(
+        segment: Arc<Segment>,
+        raw: RawEndpoint,
+        wait_strategy: WaitStrategy,
+    ) -> Receiver<T, W> {
+        Receiver {
+            common: unsafe { Common::from_raw(segment, raw) },
+            read_idx: raw.idx,
+            available: 0,
+            clear_pending: 0,
+            waiter: Waiter::new(wait_strategy),
+        }
+    }
+
+    // Returns the location of the ring of a channel allocated in a segment
+    // without dropping the receiver.
+    pub fn into_raw(mut self) -> Option<RawEndpoint> {
+        let raw = self.common.raw(self.read_idx)?;
+        self.clear();
+        std::mem::forget(self);
+        Some(raw)
+    }
+
+    // Frees the slots of the items that have been received.
+    fn clear(&mut self) {
+        let ordering = if size_of::<T>() == 0 {
+            // if T is ZST, reads and writes are a noop, so synchronization
+            // is not needed
+            Relaxed
+        } else {
+            // Release because the read of these items from the buffer needs
+            // to happen before an overwrite of the same slot by the sender
+            // (the sender can still attempt to send even if the receiver is
+            // dropped).
+            Release
+        };
+        self.common
+            .shared()
+            .fetch_sub(self.clear_pending << AVAILABLE_SHIFT, ordering);
+        self.available = 0;
+        self.clear_pending = 0;
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        if self.available >> AVAILABLE_SHIFT == 0 {
+            self.load_available()?;
+        }
+        Ok(unsafe { self.read_available() })
+    }
+
+    // Receives up to max items that are available without waiting, appending
+    // them to buf. The slots of the items are freed with at most one atomic
+    // operation.
+    pub fn try_recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> Result<usize, TryRecvError> {
+        if max == 0 {
+            return Ok(0);
+        }
+        if self.available >> AVAILABLE_SHIFT == 0 {
+            self.load_available()?;
+        }
+        let count = (self.available >> AVAILABLE_SHIFT).min(u32::try_from(max).unwrap_or(u32::MAX));
+        buf.reserve(count as usize);
+        for _ in 0..count {
+            buf.push(unsafe {
+                self.common
+                    .item_buf()
+                    .add((self.read_idx & self.common.mask) as usize)
+                    .read()
+            });
+            self.read_idx = self.read_idx.wrapping_add(1);
+        }
+        self.consume(count);
+        Ok(count as usize)
+    }
+
+    // Updates self.available with the items that have been sent.
+    fn load_available(&mut self) -> Result<(), TryRecvError> {
+        let available = self.common.shared().load(Relaxed);
+        if available >> AVAILABLE_SHIFT == self.clear_pending {
+            return Err(if available & TRANSMITTER_DROPPED != 0 {
+                TryRecvError::Disconnected
+            } else {
+                TryRecvError::Empty
+            });
+        }
+        self.set_available(available - (self.clear_pending << AVAILABLE_SHIFT));
+        if size_of::<T>() != 0 {
+            // if T is ZST the read below is a noop, so it doesn't
+            // need to be synchronized
+            fence(Acquire);
+        }
+        Ok(())
+    }
+
+    // Reads the next item from the buffer.
+    //
+    // Safety: self.available must indicate that there is at least one item
+    // available, and the corresponding acquire fence must have been done.
+    pub unsafe fn read_available(&mut self) -> T {
+        let value = unsafe {
+            self.common
+                .item_buf()
+                .add((self.read_idx & self.common.mask) as usize)
+                .read()
+        };
+        self.read_idx = self.read_idx.wrapping_add(1);
+        self.consume(1);
+        value
+    }
+
+    // Accounts for count items that have been read, freeing their slots if
+    // there are enough pending.
+    fn consume(&mut self, count: u32) {
+        self.clear_pending += count;
+        if self.clear_pending >= MAX_PENDING_SLOTS {
+            let ordering = if size_of::<T>() == 0 {
+                // if T is ZST, reads and writes are a noop, so synchronization
+                // is not needed
+                Relaxed
+            } else {
+                // Acquire because writes into the buffer by the sender need to
+                // happen before reads by future recv() calls. Release because the
+                // read of these items from the buffer needs to happen before an
+                // overwrite of the same slots by the sender.
+                AcqRel
+            };
+            let old_shared = self
+                .common
+                .shared()
+                .fetch_sub(self.clear_pending << AVAILABLE_SHIFT, ordering);
+            self.set_available(old_shared - (self.clear_pending << AVAILABLE_SHIFT));
+            self.clear_pending = 0;
+        } else {
+            self.available -= count << AVAILABLE_SHIFT;
+        }
+    }
+
+    // Sets the items that can be received, given as the value of the shared
+    // atomic without the pending slots, and records them in the statistics.
+    pub fn set_available(&mut self, available: u32) {
+        self.available = available;
+        self.common.stats.waiting(available >> AVAILABLE_SHIFT);
+    }
+
+    // Number of items that can be received.
+    pub fn len(&self) -> u32 {
+        self.common.occupied(Relaxed) - self.clear_pending
     }
 }
 
@@ -98,6 +368,9 @@ pub struct Common<T, W> {
     shared: NonNull<AtomicU32>,
     pub mask: u32,
     pub write_idx: u32,
+    pub stats: Stats,
+    // segment in which the ring is allocated, if it is not in the heap
+    segment: Option<Arc<Segment>>,
     _phantom: PhantomData<(*mut T, *mut W)>,
 }
 
@@ -111,6 +384,8 @@ impl<T, W> Clone for Common<T, W> {
             shared: self.shared,
             mask: self.mask,
             write_idx: self.write_idx,
+            stats: self.stats.clone(),
+            segment: self.segment.clone(),
             _phantom: PhantomData,
         }
     }
@@ -118,6 +393,23 @@ impl<T, W> Clone for Common<T, W> {
 
 impl<T, W: Default> Common<T, W> {
     fn new(size: usize) -> Common<T, W> {
+        let size = Self::ring_size(size);
+        let layout = Self::shared_layout(size);
+        let ptr = unsafe { alloc(layout) };
+        let Some(ptr) = NonNull::new(ptr.cast::<AtomicU32>()) else {
+            handle_alloc_error(layout);
+        };
+        unsafe { Self::init(ptr, size, None) }
+    }
+
+    fn new_in(segment: Arc<Segment>, size: usize) -> Result<Common<T, W>> {
+        let size = Self::ring_size(size);
+        let offset = segment.alloc(Self::shared_layout(size))?;
+        let ptr = segment.ptr(offset).cast::<AtomicU32>();
+        Ok(unsafe { Self::init(ptr, size, Some(segment)) })
+    }
+
+    fn ring_size(size: usize) -> usize {
         // The size is amended to size + MAX_PENDING_SLOTS, because at least
         // MAX_PENDING_SLOTS - 1 additional slots can be not claimed to be free
         // by the receiver, and one additional slot is needed for the assertion
@@ -129,30 +421,34 @@ impl<T, W: Default> Common<T, W> {
             .next_power_of_two();
         assert!(size != 0); // this happens when next_power_of_two overflows in release mode
         assert!(size <= 1 << (32 - AVAILABLE_SHIFT));
-        let shared = {
-            let layout = Self::shared_layout(size);
-            let ptr = unsafe { alloc(layout) };
-            let Some(ptr) = NonNull::new(ptr.cast::<AtomicU32>()) else {
-                handle_alloc_error(layout);
-            };
-            unsafe {
-                ptr.write(AtomicU32::new(0));
-                ptr.offset(ENDPOINT_DROPPED_OFFSET).write(AtomicU32::new(0));
-                // READ_IDX_OFFSET does not really need to be accessed atomically,
-                // because it is synchronized by ENDPOINT_DROPPED_OFFSET, but we
-                // still use atomic accesses for homogeneity
-                ptr.offset(READ_IDX_OFFSET).write(AtomicU32::new(0));
-                ptr.byte_add(Self::WAKER_BYTE_OFFSET)
-                    .cast::<W>()
-                    .write(W::default());
-            }
-            ptr
-        };
+        size
+    }
+
+    // Safety: ptr must point to an allocation with the layout given by
+    // shared_layout(size).
+    unsafe fn init(
+        ptr: NonNull<AtomicU32>,
+        size: usize,
+        segment: Option<Arc<Segment>>,
+    ) -> Common<T, W> {
+        unsafe {
+            ptr.write(AtomicU32::new(0));
+            ptr.offset(ENDPOINT_DROPPED_OFFSET).write(AtomicU32::new(0));
+            // READ_IDX_OFFSET does not really need to be accessed atomically,
+            // because it is synchronized by ENDPOINT_DROPPED_OFFSET, but we
+            // still use atomic accesses for homogeneity
+            ptr.offset(READ_IDX_OFFSET).write(AtomicU32::new(0));
+            ptr.byte_add(Self::WAKER_BYTE_OFFSET)
+                .cast::<W>()
+                .write(W::default());
+        }
         let mask = u32::try_from(size - 1).unwrap();
         Common {
-            shared,
+            shared: ptr,
             mask,
             write_idx: 0,
+            stats: Stats::new(),
+            segment,
             _phantom: PhantomData,
         }
     }
@@ -196,8 +492,33 @@ impl<T, W> Common<T, W> {
                 .cast::<W>()
                 .drop_in_place()
         };
-        let size = usize::try_from(self.mask).unwrap() + 1;
-        unsafe { dealloc(self.shared.as_ptr().cast::<u8>(), Self::shared_layout(size)) };
+        if self.segment.is_none() {
+            // the memory of a segment is not reclaimed
+            let size = usize::try_from(self.mask).unwrap() + 1;
+            unsafe { dealloc(self.shared.as_ptr().cast::<u8>(), Self::shared_layout(size)) };
+        }
+    }
+
+    // Safety: raw must have been obtained from Common::raw on a ring in the
+    // same segment.
+    unsafe fn from_raw(segment: Arc<Segment>, raw: RawEndpoint) -> Common<T, W> {
+        Common {
+            shared: segment.ptr(raw.offset).cast::<AtomicU32>(),
+            mask: raw.size - 1,
+            write_idx: 0,
+            stats: Stats::new(),
+            segment: Some(segment),
+            _phantom: PhantomData,
+        }
+    }
+
+    fn raw(&self, idx: u32) -> Option<RawEndpoint> {
+        let segment = self.segment.as_ref()?;
+        Some(RawEndpoint {
+            offset: segment.offset(self.shared.cast::<u8>()),
+            size: self.mask + 1,
+            idx,
+        })
     }
 
     pub fn shared(&self) -> &AtomicU32 {
@@ -221,6 +542,12 @@ impl<T, W> Common<T, W> {
         }
     }
 
+    // Number of slots that are not free. This includes the slots that have
+    // been read by the receiver but not cleared yet.
+    pub fn occupied(&self, ordering: Ordering) -> u32 {
+        self.shared().load(ordering) >> AVAILABLE_SHIFT
+    }
+
     pub fn item_buf(&self) -> NonNull<T> {
         unsafe { self.shared.byte_add(Self::ITEM_BUF_BYTE_OFFSET).cast::<T>() }
     }
@@ -231,6 +558,7 @@ impl<T, W: Waker> Drop for Sender<T, W> {
         // wake the receiver if it is sleeping
         let old_shared = self.common.shared().fetch_or(TRANSMITTER_DROPPED, Relaxed);
         if old_shared & RECEIVER_SLEEPING != 0 {
+            self.common.stats.woke();
             W::wake(&self.common);
         }
         // Acquire ordering used here to establish a happens-before relationship
@@ -251,20 +579,7 @@ impl<T, W: Waker> Drop for Sender<T, W> {
 impl<T, W> Drop for Receiver<T, W> {
     fn drop(&mut self) {
         self.common.read_idx().store(self.read_idx, Relaxed);
-        let ordering = if size_of::<T>() == 0 {
-            // if T is ZST, reads and writes are a noop, so synchronization
-            // is not needed
-            Relaxed
-        } else {
-            // Release because the read of these items from the buffer needs
-            // to happen before an overwrite of the same slot by the sender
-            // (the sender can still attempt to send even if the receiver is
-            // dropped).
-            Release
-        };
-        self.common
-            .shared()
-            .fetch_sub(self.clear_pending << AVAILABLE_SHIFT, ordering);
+        self.clear();
         // Release ordering used here to establish a happens-before relationship
         // between storing at read_idx() (and the fetch_sub at shared()), and
         // loading it on deallocate when run by Sender's drop. Acquire ordering
@@ -1,10 +1,14 @@
-use super::chan::{
-    self, AVAILABLE_SHIFT, Common, MAX_PENDING_SLOTS, RECEIVER_SLEEPING, TRANSMITTER_DROPPED, Waker,
+use super::chan::{self, AVAILABLE_SHIFT, Common, RECEIVER_SLEEPING, TRANSMITTER_DROPPED, Waker};
+use crate::channel::{
+    RecvTimeoutError, TryRecvError, futex,
+    wait::{Fallback, WaitStrategy, Waiter},
 };
-use libc::{FUTEX_PRIVATE_FLAG, FUTEX_WAIT, FUTEX_WAKE, SYS_futex, syscall};
-use std::sync::atomic::{
-    Ordering::{AcqRel, Acquire, Relaxed},
-    fence,
+use std::{
+    sync::atomic::{
+        Ordering::{Acquire, Relaxed},
+        fence,
+    },
+    time::{Duration, Instant},
 };
 
 #[derive(Debug)]
@@ -14,6 +18,53 @@ impl<T> Sender<T> {
     pub fn send(&mut self, value: T) {
         self.0.send(value)
     }
+
+    /// Sends a value if the channel is not full.
+    ///
+    /// If the channel is full, the value is given back in the `Err`.
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        self.0.try_send(value)
+    }
+
+    /// Sends all the items of an iterator.
+    ///
+    /// The items are made available to the receiver with a single atomic
+    /// operation, which is faster than sending them one by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel does not have free slots for all the items. In
+    /// this case no item is sent.
+    pub fn send_batch<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.0.send_batch(iter)
+    }
+
+    /// Returns the number of slots of the channel that are in use.
+    ///
+    /// This includes the items that have been received but whose slots have
+    /// not been freed yet by the receiver, which does this in batches.
+    pub fn len(&self) -> usize {
+        self.0.common.occupied(Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
+
+    /// Returns the number of slots of the channel.
+    ///
+    /// This is at least the size with which the channel was created.
+    pub fn capacity(&self) -> usize {
+        self.0.common.mask as usize
+    }
 }
 
 #[derive(Debug)]
@@ -24,47 +75,117 @@ unsafe impl<T: Send> Send for Receiver<T> {}
 // so it is not possible to have multiple consumers by sharing references
 unsafe impl<T: Send> Sync for Receiver<T> {}
 
+// The waker of the channels in shared memory segments uses futexes that are
+// not private to the process.
 #[derive(Debug, Clone, Default)]
-struct FutexWaker {}
-
-impl Waker for FutexWaker {
-    fn wake<T>(common: &Common<T, FutexWaker>) {
-        unsafe {
-            syscall(
-                SYS_futex,
-                common.shared().as_ptr().cast_const(),
-                FUTEX_WAKE | FUTEX_PRIVATE_FLAG,
-                1,
-            )
-        };
+pub(super) struct FutexWaker<const SHARED: bool = false> {}
+
+impl<const SHARED: bool> Waker for FutexWaker<SHARED> {
+    fn wake<T>(common: &Common<T, FutexWaker<SHARED>>) {
+        if SHARED {
+            futex::wake_shared(common.shared(), 1);
+        } else {
+            futex::wake(common.shared(), 1);
+        }
     }
 }
 
 pub fn channel<T>(size: usize) -> (Sender<T>, Receiver<T>) {
-    let (tx, rx) = chan::channel(size);
-    (Sender(tx), Receiver(rx))
+    channel_with_wait_strategy(size, WaitStrategy::FUTEX_DEFAULT)
 }
 
-const RECEIVER_SPINS: usize = 1 << 13;
+pub fn channel_with_wait_strategy<T>(
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = chan::channel(size, wait_strategy);
+    (Sender(tx), Receiver(rx))
+}
 
 impl<T> Receiver<T> {
     pub fn recv(&mut self) -> Option<T> {
-        self.0.recv_futex_waker()
+        self.0.recv_futex_waker(None).ok()
+    }
+
+    /// Receives up to `max` items, appending them to `buf`.
+    ///
+    /// This waits for an item and then receives the items that are available
+    /// without waiting. Returns the number of items received, which is zero
+    /// only if the channel is disconnected or `max` is zero.
+    pub fn recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+        let Some(value) = self.recv() else {
+            return 0;
+        };
+        buf.push(value);
+        1 + self.0.try_recv_batch(buf, max - 1).unwrap_or(0)
+    }
+
+    /// Receives an item, waiting at most for the given timeout.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        // A timeout so large that the deadline overflows is the same as no
+        // timeout.
+        self.0.recv_futex_waker(Instant::now().checked_add(timeout))
+    }
+
+    /// Receives an item, waiting at most until the given deadline.
+    pub fn recv_deadline(&mut self, deadline: Instant) -> Result<T, RecvTimeoutError> {
+        self.0.recv_futex_waker(Some(deadline))
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    /// Receives up to `max` items that are available without waiting,
+    /// appending them to `buf`.
+    ///
+    /// Returns the number of items received.
+    pub fn try_recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> Result<usize, TryRecvError> {
+        self.0.try_recv_batch(buf, max)
+    }
+
+    /// Returns the number of items that can be received.
+    pub fn len(&self) -> usize {
+        self.0.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.0.waiter.strategy()
+    }
+
+    pub fn set_wait_strategy(&mut self, wait_strategy: WaitStrategy) {
+        self.0.waiter = Waiter::new(wait_strategy);
+    }
+
+    /// Returns a handle to the statistics of the channel.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> crate::channel::StatsHandle {
+        self.0.common.stats.handle()
     }
 }
 
-impl<T> chan::Receiver<T, FutexWaker> {
-    fn recv_futex_waker(&mut self) -> Option<T> {
+impl<T, const SHARED: bool> chan::Receiver<T, FutexWaker<SHARED>> {
+    pub(super) fn recv_futex_waker(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<T, RecvTimeoutError> {
         let mut available = self.available;
         if available >> AVAILABLE_SHIFT == 0 {
-            for _ in 0..RECEIVER_SPINS {
-                available = self.common.shared().load(Relaxed);
-                if available >> AVAILABLE_SHIFT != self.clear_pending {
-                    break;
-                }
-                std::hint::spin_loop();
-            }
-            if available >> AVAILABLE_SHIFT == self.clear_pending {
+            let common = &self.common;
+            let clear_pending = self.clear_pending;
+            let ready = self.waiter.spin(|| {
+                available = common.shared().load(Relaxed);
+                available >> AVAILABLE_SHIFT != clear_pending
+                    || available & TRANSMITTER_DROPPED != 0
+            });
+            if !ready || available >> AVAILABLE_SHIFT == self.clear_pending {
                 loop {
                     available = self.common.shared().load(Relaxed);
                     if available >> AVAILABLE_SHIFT != self.clear_pending {
@@ -72,62 +193,61 @@ impl<T> chan::Receiver<T, FutexWaker> {
                     }
                     if available & TRANSMITTER_DROPPED != 0 {
                         // channel is empty and sender was dropped
-                        return None;
+                        return Err(RecvTimeoutError::Disconnected);
+                    }
+                    match self.waiter.fallback() {
+                        fallback @ (Fallback::Spin | Fallback::Yield) => {
+                            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                                return Err(RecvTimeoutError::Timeout);
+                            }
+                            if fallback == Fallback::Spin {
+                                std::hint::spin_loop();
+                            } else {
+                                std::thread::yield_now();
+                            }
+                        }
+                        Fallback::Sleep => {
+                            self.common.shared().fetch_or(RECEIVER_SLEEPING, Relaxed);
+                            self.common.stats.slept();
+                            let wait = if SHARED {
+                                futex::wait_shared
+                            } else {
+                                futex::wait
+                            };
+                            if !wait(
+                                self.common.shared(),
+                                available | RECEIVER_SLEEPING,
+                                deadline,
+                            ) {
+                                // the flag is only cleared by the sender
+                                // when it sends an item
+                                self.common.shared().fetch_and(!RECEIVER_SLEEPING, Relaxed);
+                                return Err(RecvTimeoutError::Timeout);
+                            }
+                        }
                     }
-                    self.common.shared().fetch_or(RECEIVER_SLEEPING, Relaxed);
-                    unsafe {
-                        syscall(
-                            SYS_futex,
-                            self.common.shared().as_ptr().cast_const(),
-                            FUTEX_WAIT | FUTEX_PRIVATE_FLAG,
-                            available | RECEIVER_SLEEPING,
-                            std::ptr::null::<libc::timespec>(),
-                        )
-                    };
                 }
             }
-            self.available = available - (self.clear_pending << AVAILABLE_SHIFT);
+            self.set_available(available - (self.clear_pending << AVAILABLE_SHIFT));
             if size_of::<T>() != 0 {
                 // if T is ZST the read below is a noop, so it doesn't
                 // need to be synchronized
                 fence(Acquire);
             }
         }
-        let value = unsafe {
-            self.common
-                .item_buf()
-                .add((self.read_idx & self.common.mask) as usize)
-                .read()
-        };
-        self.clear_pending += 1;
-        if self.clear_pending == MAX_PENDING_SLOTS {
-            let ordering = if size_of::<T>() == 0 {
-                // if T is ZST, reads and writes are a noop, so synchronization
-                // is not needed
-                Relaxed
-            } else {
-                // Acquire because writes into the buffer by the sender need to
-                // happen before reads by future recv() calls. Release because the
-                // read of this item from the buffer needs to happen before an
-                // overwrite of the same slot by the sender.
-                AcqRel
-            };
-            let old_shared = self
-                .common
-                .shared()
-                .fetch_sub(MAX_PENDING_SLOTS << AVAILABLE_SHIFT, ordering);
-            self.available = old_shared - (MAX_PENDING_SLOTS << AVAILABLE_SHIFT);
-            self.clear_pending = 0;
-        } else {
-            self.available -= 1 << AVAILABLE_SHIFT;
-        }
-        self.read_idx = self.read_idx.wrapping_add(1);
-        Some(value)
+        Ok(unsafe { self.read_available() })
     }
 }
 
 #[cfg(test)]
 mod test {
+    use super::RECEIVER_SLEEPING;
+    use crate::channel::{RecvTimeoutError, TryRecvError, WaitStrategy};
+    use std::{
+        sync::atomic::Ordering::Relaxed,
+        time::{Duration, Instant},
+    };
+
     #[test]
     fn channel() {
         let cap = 4096;
@@ -145,4 +265,107 @@ mod test {
         sender_thread.join().unwrap();
         assert!(rx.recv().is_none());
     }
+
+    #[test]
+    fn recv_timeout() {
+        let (tx, mut rx) = super::channel(16);
+        let timeout = Duration::from_millis(10);
+        let start = Instant::now();
+        assert_eq!(rx.recv_timeout(timeout), Err(RecvTimeoutError::Timeout));
+        assert!(start.elapsed() >= timeout);
+        assert_eq!(rx.0.common.shared().load(Relaxed) & RECEIVER_SLEEPING, 0);
+        assert_eq!(
+            rx.recv_deadline(Instant::now()),
+            Err(RecvTimeoutError::Timeout)
+        );
+        let sender_thread = std::thread::spawn(move || {
+            let mut tx = tx;
+            std::thread::sleep(Duration::from_millis(20));
+            tx.send(42);
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Ok(42));
+        sender_thread.join().unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::MAX),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn wait_strategies() {
+        let cap = 4096;
+        for wait_strategy in [
+            WaitStrategy::BusySpin,
+            WaitStrategy::SpinYield { spins: 16 },
+            WaitStrategy::SpinSleep { spins: 0 },
+            WaitStrategy::Adaptive { max_spins: 1 << 10 },
+        ] {
+            let (mut tx, mut rx) = super::channel_with_wait_strategy(cap, wait_strategy);
+            assert_eq!(rx.wait_strategy(), wait_strategy);
+            let sender_thread = std::thread::spawn(move || {
+                for n in 0..cap {
+                    tx.send(n);
+                }
+            });
+            let received = std::iter::repeat_with(|| rx.recv().unwrap())
+                .take(cap)
+                .collect::<Vec<_>>();
+            let expected = (0..cap).collect::<Vec<_>>();
+            assert_eq!(received, expected);
+            sender_thread.join().unwrap();
+            assert!(rx.recv().is_none());
+            assert_eq!(
+                rx.recv_timeout(Duration::from_millis(1)),
+                Err(RecvTimeoutError::Disconnected)
+            );
+        }
+    }
+
+    #[test]
+    fn batches() {
+        let num_items = 100_000;
+        let (mut tx, mut rx) = super::channel(64);
+        let sender_thread = std::thread::spawn(move || {
+            let mut items = 0..num_items;
+            for batch_size in (1..=37).cycle() {
+                if items.is_empty() {
+                    break;
+                }
+                // wait for room, so that the ring wraps around many times
+                while tx.capacity() - tx.len() < batch_size {
+                    std::thread::yield_now();
+                }
+                tx.send_batch(items.by_ref().take(batch_size));
+            }
+        });
+        let mut received = Vec::new();
+        while rx.recv_batch(&mut received, 50) != 0 {}
+        let expected = (0..num_items).collect::<Vec<_>>();
+        assert_eq!(received, expected);
+        sender_thread.join().unwrap();
+        assert_eq!(
+            rx.try_recv_batch(&mut received, 1),
+            Err(TryRecvError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn batch_without_room() {
+        let (mut tx, mut rx) = super::channel(4);
+        let capacity = tx.capacity() as u32;
+        tx.send(0);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            tx.send_batch(1..capacity + 1)
+        }));
+        assert!(result.is_err());
+        // nothing has been sent
+        assert_eq!(tx.len(), 1);
+        tx.send_batch(1..capacity);
+        let mut received = Vec::new();
+        assert_eq!(
+            rx.try_recv_batch(&mut received, capacity as usize),
+            Ok(capacity as usize)
+        );
+        assert_eq!(received, (0..capacity).collect::<Vec<_>>());
+    }
 }
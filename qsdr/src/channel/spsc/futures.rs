@@ -1,12 +1,14 @@
-use super::chan::{
-    self, AVAILABLE_SHIFT, Common, MAX_PENDING_SLOTS, RECEIVER_SLEEPING, TRANSMITTER_DROPPED, Waker,
+use super::chan::{self, AVAILABLE_SHIFT, Common, RECEIVER_SLEEPING, TRANSMITTER_DROPPED, Waker};
+use crate::channel::{
+    TryRecvError,
+    wait::{Fallback, WaitStrategy, Waiter},
 };
 use futures::stream::Stream;
 use std::{
     future::poll_fn,
     pin::Pin,
     sync::atomic::{
-        Ordering::{AcqRel, Acquire, Relaxed},
+        Ordering::{Acquire, Relaxed},
         fence,
     },
     task::{Context, Poll},
@@ -19,6 +21,53 @@ impl<T> Sender<T> {
     pub fn send(&mut self, value: T) {
         self.0.send(value)
     }
+
+    /// Sends a value if the channel is not full.
+    ///
+    /// If the channel is full, the value is given back in the `Err`.
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        self.0.try_send(value)
+    }
+
+    /// Sends all the items of an iterator.
+    ///
+    /// The items are made available to the receiver with a single atomic
+    /// operation, which is faster than sending them one by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel does not have free slots for all the items. In
+    /// this case no item is sent.
+    pub fn send_batch<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.0.send_batch(iter)
+    }
+
+    /// Returns the number of slots of the channel that are in use.
+    ///
+    /// This includes the items that have been received but whose slots have
+    /// not been freed yet by the receiver, which does this in batches.
+    pub fn len(&self) -> usize {
+        self.0.common.occupied(Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
+
+    /// Returns the number of slots of the channel.
+    ///
+    /// This is at least the size with which the channel was created.
+    pub fn capacity(&self) -> usize {
+        self.0.common.mask as usize
+    }
 }
 
 #[derive(Debug)]
@@ -39,7 +88,14 @@ impl Waker for AtomicWaker {
 }
 
 pub fn channel<T>(size: usize) -> (Sender<T>, Receiver<T>) {
-    let (tx, rx) = chan::channel(size);
+    channel_with_wait_strategy(size, WaitStrategy::FUTURES_DEFAULT)
+}
+
+pub fn channel_with_wait_strategy<T>(
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> (Sender<T>, Receiver<T>) {
+    let (tx, rx) = chan::channel(size, wait_strategy);
     (Sender(tx), Receiver(rx))
 }
 
@@ -48,13 +104,70 @@ impl<T> Receiver<T> {
         poll_fn(|cx| self.poll(cx)).await
     }
 
+    /// Receives up to `max` items, appending them to `buf`.
+    ///
+    /// This waits for an item and then receives the items that are available
+    /// without waiting. Returns the number of items received, which is zero
+    /// only if the channel is disconnected or `max` is zero.
+    pub async fn recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+        let Some(value) = self.recv().await else {
+            return 0;
+        };
+        buf.push(value);
+        1 + self.0.try_recv_batch(buf, max - 1).unwrap_or(0)
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    /// Receives up to `max` items that are available without waiting,
+    /// appending them to `buf`.
+    ///
+    /// Returns the number of items received.
+    pub fn try_recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> Result<usize, TryRecvError> {
+        self.0.try_recv_batch(buf, max)
+    }
+
+    /// Returns the number of items that can be received.
+    pub fn len(&self) -> usize {
+        self.0.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.0.waiter.strategy()
+    }
+
+    pub fn set_wait_strategy(&mut self, wait_strategy: WaitStrategy) {
+        self.0.waiter = Waiter::new(wait_strategy);
+    }
+
+    /// Returns a handle to the statistics of the channel.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> crate::channel::StatsHandle {
+        self.0.common.stats.handle()
+    }
+
     fn poll(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
         let mut available = self.0.available;
         if available >> AVAILABLE_SHIFT == 0 {
             // Normally this loop only spins once, but it might spin multiple times
             // if the waker is waked before this returns Poll::Pending (in which
             // case the wake might have been lost).
-            available = self.0.common.shared().load(Relaxed);
+            let common = &self.0.common;
+            let clear_pending = self.0.clear_pending;
+            self.0.waiter.spin(|| {
+                available = common.shared().load(Relaxed);
+                available >> AVAILABLE_SHIFT != clear_pending
+                    || available & TRANSMITTER_DROPPED != 0
+            });
             loop {
                 if available >> AVAILABLE_SHIFT != self.0.clear_pending {
                     break;
@@ -63,6 +176,11 @@ impl<T> Receiver<T> {
                     // channel is empty and sender was dropped
                     return Poll::Ready(None);
                 }
+                if self.0.waiter.fallback() != Fallback::Sleep {
+                    // let the executor poll again
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
                 self.0.common.shared().fetch_or(RECEIVER_SLEEPING, Relaxed);
                 self.0.common.waker().0.register(cx.waker());
                 // Check that the shared atomic is still what we expect. This
@@ -73,48 +191,20 @@ impl<T> Receiver<T> {
                 let old_available = available;
                 available = self.0.common.shared().load(Relaxed);
                 if available == old_available | RECEIVER_SLEEPING {
+                    self.0.common.stats.slept();
                     return Poll::Pending;
                 }
             }
-            self.0.available = available - (self.0.clear_pending << AVAILABLE_SHIFT);
+            let clear_pending = self.0.clear_pending;
+            self.0
+                .set_available(available - (clear_pending << AVAILABLE_SHIFT));
             if size_of::<T>() != 0 {
                 // if T is ZST the read below is a noop, so it doesn't
                 // need to be synchronized
                 fence(Acquire);
             }
         }
-        let value = unsafe {
-            self.0
-                .common
-                .item_buf()
-                .add((self.0.read_idx & self.0.common.mask) as usize)
-                .read()
-        };
-        self.0.clear_pending += 1;
-        if self.0.clear_pending == MAX_PENDING_SLOTS {
-            let ordering = if size_of::<T>() == 0 {
-                // if T is ZST, reads and writes are a noop, so synchronization
-                // is not needed
-                Relaxed
-            } else {
-                // Acquire because writes into the buffer by the sender need to
-                // happen before reads by future recv() calls. Release because the
-                // read of this item from the buffer needs to happen before an
-                // overwrite of the same slot by the sender.
-                AcqRel
-            };
-            let old_shared = self
-                .0
-                .common
-                .shared()
-                .fetch_sub(MAX_PENDING_SLOTS << AVAILABLE_SHIFT, ordering);
-            self.0.available = old_shared - (MAX_PENDING_SLOTS << AVAILABLE_SHIFT);
-            self.0.clear_pending = 0;
-        } else {
-            self.0.available -= 1 << AVAILABLE_SHIFT;
-        }
-        self.0.read_idx = self.0.read_idx.wrapping_add(1);
-        Poll::Ready(Some(value))
+        Poll::Ready(Some(unsafe { self.0.read_available() }))
     }
 }
 
@@ -128,6 +218,8 @@ impl<T> Stream for Receiver<T> {
 
 #[cfg(test)]
 mod test {
+    use crate::channel::TryRecvError;
+
     #[test]
     fn channel() {
         let cap = 4096;
@@ -148,4 +240,31 @@ mod test {
             assert!(rx.recv().await.is_none());
         });
     }
+
+    #[test]
+    fn try_send_try_recv() {
+        let (mut tx, mut rx) = super::channel(4);
+        assert!(tx.capacity() >= 4);
+        assert!(tx.is_empty());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        let mut sent = 0;
+        while tx.try_send(sent).is_ok() {
+            sent += 1;
+        }
+        assert_eq!(sent, tx.capacity());
+        assert!(tx.is_full());
+        assert_eq!(tx.try_send(sent), Err(sent));
+        assert_eq!(rx.len(), sent);
+        for n in 0..sent {
+            assert_eq!(rx.try_recv(), Ok(n));
+        }
+        assert!(rx.is_empty());
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        // the slots are freed in batches, so some of them can be reused now
+        assert!(!tx.is_full());
+        tx.try_send(sent).unwrap();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(sent));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
 }
@@ -0,0 +1,273 @@
+//! Channels in shared memory segments.
+//!
+//! These channels behave as the [futex channels](super::futex), but they are
+//! allocated in a [`Segment`], so that each endpoint can be used by a
+//! different process. An endpoint is moved to another process by converting it
+//! into a [`RawEndpoint`] with `into_raw`, sending this to the other process,
+//! and recreating the endpoint there with `from_raw`.
+//!
+//! The items are copied bitwise between processes, so they should be plain
+//! data, such as the indices of the buffers of a circuit.
+
+use super::{chan, futex::FutexWaker};
+use crate::{
+    channel::{RecvTimeoutError, TryRecvError, wait::WaitStrategy, wait::Waiter},
+    shm::{RawEndpoint, Segment},
+};
+use anyhow::Result;
+use std::{
+    sync::{Arc, atomic::Ordering::Relaxed},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+pub struct Sender<T>(chan::Sender<T, FutexWaker<true>>);
+
+impl<T: Copy> Sender<T> {
+    /// Recreates a sender from its raw representation.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must have been obtained with [`Sender::into_raw`] from a sender
+    /// with the same item type in the same segment (possibly mapped by another
+    /// process), and it must be used only once. If the sender was created in
+    /// another process, the items must be valid in this process.
+    pub unsafe fn from_raw(segment: &Arc<Segment>, raw: RawEndpoint) -> Sender<T> {
+        Sender(unsafe { chan::Sender::from_raw(Arc::clone(segment), raw) })
+    }
+}
+
+impl<T> Sender<T> {
+    /// Converts the sender into its raw representation.
+    pub fn into_raw(self) -> RawEndpoint {
+        self.0.into_raw().unwrap()
+    }
+
+    pub fn send(&mut self, value: T) {
+        self.0.send(value)
+    }
+
+    /// Sends a value if the channel is not full.
+    ///
+    /// If the channel is full, the value is given back in the `Err`.
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        self.0.try_send(value)
+    }
+
+    /// Sends all the items of an iterator.
+    ///
+    /// The items are made available to the receiver with a single atomic
+    /// operation, which is faster than sending them one by one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the channel does not have free slots for all the items. In
+    /// this case no item is sent.
+    pub fn send_batch<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.0.send_batch(iter)
+    }
+
+    /// Returns the number of slots of the channel that are in use.
+    ///
+    /// This includes the items that have been received but whose slots have
+    /// not been freed yet by the receiver, which does this in batches.
+    pub fn len(&self) -> usize {
+        self.0.common.occupied(Relaxed) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
+
+    /// Returns the number of slots of the channel.
+    ///
+    /// This is at least the size with which the channel was created.
+    pub fn capacity(&self) -> usize {
+        self.0.common.mask as usize
+    }
+}
+
+#[derive(Debug)]
+pub struct Receiver<T>(chan::Receiver<T, FutexWaker<true>>);
+
+unsafe impl<T: Send> Send for Receiver<T> {}
+// Receiver can be Sync, because the receive methods use &mut self,
+// so it is not possible to have multiple consumers by sharing references
+unsafe impl<T: Send> Sync for Receiver<T> {}
+
+/// Creates a channel in a shared memory segment.
+pub fn channel<T: Copy>(segment: &Arc<Segment>, size: usize) -> Result<(Sender<T>, Receiver<T>)> {
+    channel_with_wait_strategy(segment, size, WaitStrategy::FUTEX_DEFAULT)
+}
+
+pub fn channel_with_wait_strategy<T: Copy>(
+    segment: &Arc<Segment>,
+    size: usize,
+    wait_strategy: WaitStrategy,
+) -> Result<(Sender<T>, Receiver<T>)> {
+    let (tx, rx) = chan::channel_in(Arc::clone(segment), size, wait_strategy)?;
+    Ok((Sender(tx), Receiver(rx)))
+}
+
+impl<T: Copy> Receiver<T> {
+    /// Recreates a receiver from its raw representation.
+    ///
+    /// The receiver uses [`WaitStrategy::FUTEX_DEFAULT`].
+    ///
+    /// # Safety
+    ///
+    /// `raw` must have been obtained with [`Receiver::into_raw`] from a
+    /// receiver with the same item type in the same segment (possibly mapped
+    /// by another process), and it must be used only once. If the receiver was
+    /// created in another process, the items must be valid in this process.
+    pub unsafe fn from_raw(segment: &Arc<Segment>, raw: RawEndpoint) -> Receiver<T> {
+        Receiver(unsafe {
+            chan::Receiver::from_raw(Arc::clone(segment), raw, WaitStrategy::FUTEX_DEFAULT)
+        })
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Converts the receiver into its raw representation.
+    pub fn into_raw(self) -> RawEndpoint {
+        self.0.into_raw().unwrap()
+    }
+
+    pub fn recv(&mut self) -> Option<T> {
+        self.0.recv_futex_waker(None).ok()
+    }
+
+    /// Receives up to `max` items, appending them to `buf`.
+    ///
+    /// This waits for an item and then receives the items that are available
+    /// without waiting. Returns the number of items received, which is zero
+    /// only if the channel is disconnected or `max` is zero.
+    pub fn recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+        let Some(value) = self.recv() else {
+            return 0;
+        };
+        buf.push(value);
+        1 + self.0.try_recv_batch(buf, max - 1).unwrap_or(0)
+    }
+
+    /// Receives an item, waiting at most for the given timeout.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        // A timeout so large that the deadline overflows is the same as no
+        // timeout.
+        self.0.recv_futex_waker(Instant::now().checked_add(timeout))
+    }
+
+    /// Receives an item, waiting at most until the given deadline.
+    pub fn recv_deadline(&mut self, deadline: Instant) -> Result<T, RecvTimeoutError> {
+        self.0.recv_futex_waker(Some(deadline))
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    /// Receives up to `max` items that are available without waiting,
+    /// appending them to `buf`.
+    ///
+    /// Returns the number of items received.
+    pub fn try_recv_batch(&mut self, buf: &mut Vec<T>, max: usize) -> Result<usize, TryRecvError> {
+        self.0.try_recv_batch(buf, max)
+    }
+
+    /// Returns the number of items that can be received.
+    pub fn len(&self) -> usize {
+        self.0.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.0.waiter.strategy()
+    }
+
+    pub fn set_wait_strategy(&mut self, wait_strategy: WaitStrategy) {
+        self.0.waiter = Waiter::new(wait_strategy);
+    }
+
+    /// Returns a handle to the statistics of the channel in this process.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> crate::channel::StatsHandle {
+        self.0.common.stats.handle()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Receiver, Sender};
+    use crate::{
+        channel::{RecvTimeoutError, WaitStrategy},
+        shm::Segment,
+    };
+    use std::{os::fd::AsFd, sync::Arc, time::Duration};
+
+    #[test]
+    fn two_mappings() {
+        let cap = 4096;
+        let segment = Arc::new(Segment::new("test", 1 << 20).unwrap());
+        // A second mapping of the segment stands for another process. The
+        // receiver sleeps on the futex through one mapping and is woken up
+        // through the other, so this only works with shared futexes.
+        let other =
+            Arc::new(Segment::from_fd(segment.as_fd().try_clone_to_owned().unwrap()).unwrap());
+        let (tx, rx) = super::channel_with_wait_strategy::<u32>(
+            &segment,
+            cap,
+            WaitStrategy::SpinSleep { spins: 0 },
+        )
+        .unwrap();
+        let rx = rx.into_raw();
+        let sender_thread = std::thread::spawn(move || {
+            let mut tx = tx;
+            for n in 0..cap {
+                tx.send(n as u32);
+                if n % 512 == 0 {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+        let mut rx = unsafe { Receiver::<u32>::from_raw(&other, rx) };
+        rx.set_wait_strategy(WaitStrategy::SpinSleep { spins: 0 });
+        for n in 0..cap / 2 {
+            assert_eq!(rx.recv(), Some(n as u32));
+        }
+        // move the receiver again half-way through
+        let mut rx = unsafe { Receiver::<u32>::from_raw(&segment, rx.into_raw()) };
+        for n in cap / 2..cap {
+            assert_eq!(rx.recv(), Some(n as u32));
+        }
+        sender_thread.join().unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(1)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn move_sender() {
+        let segment = Arc::new(Segment::new("test", 1 << 20).unwrap());
+        let (mut tx, mut rx) = super::channel::<u64>(&segment, 16).unwrap();
+        tx.send(1);
+        let mut tx = unsafe { Sender::<u64>::from_raw(&segment, tx.into_raw()) };
+        tx.send(2);
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+    }
+}
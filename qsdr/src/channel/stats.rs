@@ -0,0 +1,176 @@
+// Statistics of the channels.
+//
+// The statistics are only collected when the stats feature is enabled. When
+// it is disabled, Stats is a zero-sized type whose methods do nothing.
+
+#[cfg(feature = "stats")]
+use std::{
+    cell::RefCell,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, AtomicU64, Ordering::Relaxed},
+    },
+};
+
+/// Statistics of a channel.
+#[cfg(feature = "stats")]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct ChannelStats {
+    /// Maximum number of items that have been waiting in the channel at the
+    /// same time.
+    ///
+    /// In the channels with a single receiver, this is measured by the
+    /// receiver each time that it checks for new items.
+    pub high_water_mark: u32,
+    /// Number of times that the receiver has gone to sleep waiting for items.
+    pub sleeps: u64,
+    /// Number of times that a sender has woken up the receiver.
+    pub wakes: u64,
+    /// Number of items sent through the channel.
+    pub items: u64,
+}
+
+/// Handle to the statistics of a channel.
+///
+/// The handle can be kept and read while the channel is in use and after it
+/// has been dropped.
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone)]
+pub struct StatsHandle(Arc<Counters>);
+
+#[cfg(feature = "stats")]
+impl StatsHandle {
+    pub fn get(&self) -> ChannelStats {
+        let counters = &self.0;
+        ChannelStats {
+            high_water_mark: counters.receiver.high_water_mark.load(Relaxed),
+            sleeps: counters.receiver.sleeps.load(Relaxed),
+            wakes: counters.sender.wakes.load(Relaxed),
+            items: counters.sender.items.load(Relaxed),
+        }
+    }
+}
+
+// The counters updated by the senders and by the receiver are placed in
+// different cache lines to avoid false sharing.
+#[cfg(feature = "stats")]
+#[derive(Debug, Default)]
+struct Counters {
+    sender: SenderCounters,
+    receiver: ReceiverCounters,
+}
+
+#[cfg(feature = "stats")]
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct SenderCounters {
+    wakes: AtomicU64,
+    items: AtomicU64,
+}
+
+#[cfg(feature = "stats")]
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct ReceiverCounters {
+    // updated by the senders in channels with multiple receivers
+    high_water_mark: AtomicU32,
+    sleeps: AtomicU64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stats {
+    #[cfg(feature = "stats")]
+    counters: Arc<Counters>,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        #[cfg(feature = "stats")]
+        {
+            let stats = Stats {
+                counters: Default::default(),
+            };
+            COLLECTOR.with_borrow_mut(|collector| {
+                if let Some(collector) = collector {
+                    collector.push(stats.handle());
+                }
+            });
+            stats
+        }
+        #[cfg(not(feature = "stats"))]
+        Stats {}
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn handle(&self) -> StatsHandle {
+        StatsHandle(Arc::clone(&self.counters))
+    }
+
+    // Records an item sent.
+    #[inline(always)]
+    pub fn sent(&self) {
+        self.sent_batch(1);
+    }
+
+    // Records a batch of items sent.
+    #[inline(always)]
+    pub fn sent_batch(&self, items: u32) {
+        #[cfg(feature = "stats")]
+        self.counters
+            .sender
+            .items
+            .fetch_add(u64::from(items), Relaxed);
+        #[cfg(not(feature = "stats"))]
+        let _ = items;
+    }
+
+    // Records the number of items waiting in the channel.
+    #[inline(always)]
+    pub fn waiting(&self, items: u32) {
+        #[cfg(feature = "stats")]
+        self.counters
+            .receiver
+            .high_water_mark
+            .fetch_max(items, Relaxed);
+        #[cfg(not(feature = "stats"))]
+        let _ = items;
+    }
+
+    #[inline(always)]
+    pub fn woke(&self) {
+        #[cfg(feature = "stats")]
+        self.counters.sender.wakes.fetch_add(1, Relaxed);
+    }
+
+    #[inline(always)]
+    pub fn slept(&self) {
+        #[cfg(feature = "stats")]
+        self.counters.receiver.sleeps.fetch_add(1, Relaxed);
+    }
+}
+
+#[cfg(feature = "stats")]
+thread_local! {
+    static COLLECTOR: RefCell<Option<Vec<StatsHandle>>> = const { RefCell::new(None) };
+}
+
+// Handles of the channels created by a call to collect().
+#[cfg(feature = "stats")]
+pub type Collected = Vec<StatsHandle>;
+#[cfg(not(feature = "stats"))]
+pub type Collected = ();
+
+// Calls f and returns the statistics handles of the channels created by it in
+// the current thread. This is used by the flowgraph to find the channels that
+// belong to each edge.
+pub fn collect<R>(f: impl FnOnce() -> R) -> (R, Collected) {
+    #[cfg(feature = "stats")]
+    {
+        let previous = COLLECTOR.replace(Some(Vec::new()));
+        let ret = f();
+        let collected = COLLECTOR.replace(previous).unwrap_or_default();
+        (ret, collected)
+    }
+    #[cfg(not(feature = "stats"))]
+    (f(), ())
+}
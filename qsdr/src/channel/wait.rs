@@ -0,0 +1,194 @@
+use std::fmt::Debug;
+
+/// Strategy used by a channel receiver to wait for items.
+///
+/// Strategies that spin have lower latency, but they keep a CPU core busy
+/// while waiting, so they are best suited to receivers that run on dedicated
+/// cores. Strategies that sleep free the core for other threads, but waking up
+/// the receiver is more expensive.
+///
+/// In the futures receivers, "yielding" means returning `Poll::Pending` after
+/// waking the task, so that the executor polls the receiver again, and
+/// "sleeping" means registering the waker of the task, so that the sender
+/// wakes it up.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum WaitStrategy {
+    /// Spin until an item is available, without ever sleeping.
+    BusySpin,
+    /// Spin the given number of times, and then yield the thread (or task)
+    /// until an item is available.
+    SpinYield { spins: u32 },
+    /// Spin the given number of times, and then sleep until an item is
+    /// available.
+    SpinSleep { spins: u32 },
+    /// Spin for a number of times that is adapted according to how long the
+    /// receiver has needed to wait recently, up to `max_spins`, and then
+    /// sleep until an item is available.
+    Adaptive { max_spins: u32 },
+}
+
+impl WaitStrategy {
+    /// Default strategy of the futex receivers.
+    pub const FUTEX_DEFAULT: WaitStrategy = WaitStrategy::SpinSleep { spins: 1 << 13 };
+    /// Default strategy of the futures receivers.
+    pub const FUTURES_DEFAULT: WaitStrategy = WaitStrategy::SpinSleep { spins: 0 };
+}
+
+/// Wait strategy selected at the type level.
+///
+/// This is used to select the wait strategy of the runtime channels, such as
+/// [`Spsc`](crate::channels::Spsc), which use
+/// [`WaitStrategy::FUTURES_DEFAULT`] by default, or
+/// [`FutexSpsc`](crate::channels::FutexSpsc), which use
+/// [`WaitStrategy::FUTEX_DEFAULT`] by default.
+pub trait Wait:
+    Debug + Default + Copy + Ord + std::hash::Hash + Send + Sync + Unpin + 'static
+{
+    const STRATEGY: WaitStrategy;
+}
+
+macro_rules! wait_types {
+    ($($(#[$attr:meta])* $ident:ident$(<const $param:ident: u32>)? => $strategy:expr;)*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+            pub struct $ident$(<const $param: u32>)? {}
+
+            impl$(<const $param: u32>)? Wait for $ident$(<$param>)? {
+                const STRATEGY: WaitStrategy = $strategy;
+            }
+        )*
+    };
+}
+
+wait_types! {
+    /// [`WaitStrategy::FUTURES_DEFAULT`].
+    DefaultWait => WaitStrategy::FUTURES_DEFAULT;
+    /// [`WaitStrategy::FUTEX_DEFAULT`].
+    FutexDefaultWait => WaitStrategy::FUTEX_DEFAULT;
+    /// [`WaitStrategy::BusySpin`].
+    BusySpin => WaitStrategy::BusySpin;
+    /// [`WaitStrategy::SpinYield`].
+    SpinYield<const SPINS: u32> => WaitStrategy::SpinYield { spins: SPINS };
+    /// [`WaitStrategy::SpinSleep`].
+    SpinSleep<const SPINS: u32> => WaitStrategy::SpinSleep { spins: SPINS };
+    /// [`WaitStrategy::Adaptive`].
+    Adaptive<const MAX_SPINS: u32> => WaitStrategy::Adaptive { max_spins: MAX_SPINS };
+}
+
+const ADAPTIVE_MIN_SPINS: u32 = 16;
+const ADAPTIVE_INITIAL_SPINS: u32 = 1 << 10;
+
+// What to do once the spins of a Waiter have been exhausted.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Fallback {
+    Spin,
+    Yield,
+    Sleep,
+}
+
+// Wait strategy together with the state it needs.
+#[derive(Debug, Clone)]
+pub struct Waiter {
+    strategy: WaitStrategy,
+    adaptive_spins: u32,
+}
+
+impl Waiter {
+    pub fn new(strategy: WaitStrategy) -> Waiter {
+        let adaptive_spins = match strategy {
+            WaitStrategy::Adaptive { max_spins } => ADAPTIVE_INITIAL_SPINS.min(max_spins),
+            _ => 0,
+        };
+        Waiter {
+            strategy,
+            adaptive_spins,
+        }
+    }
+
+    pub fn strategy(&self) -> WaitStrategy {
+        self.strategy
+    }
+
+    // Spins until ready() returns true or until the spins given by the
+    // strategy are exhausted. Returns the last value returned by ready().
+    pub fn spin(&mut self, mut ready: impl FnMut() -> bool) -> bool {
+        let spins = match self.strategy {
+            WaitStrategy::BusySpin => 0,
+            WaitStrategy::SpinYield { spins } | WaitStrategy::SpinSleep { spins } => spins,
+            WaitStrategy::Adaptive { .. } => self.adaptive_spins,
+        };
+        for spin in 0..spins {
+            if ready() {
+                self.adapt(Some(spin));
+                return true;
+            }
+            std::hint::spin_loop();
+        }
+        let ready = ready();
+        self.adapt(if ready { Some(spins) } else { None });
+        ready
+    }
+
+    // Updates the spins of the adaptive strategy according to the number of
+    // spins that were needed to receive (None if spinning was not enough).
+    fn adapt(&mut self, spins_needed: Option<u32>) {
+        let WaitStrategy::Adaptive { max_spins } = self.strategy else {
+            return;
+        };
+        let spins = match spins_needed {
+            // there was no wait
+            Some(0) => return,
+            // allow for twice the recent wait time, averaging with the
+            // previous value
+            Some(needed) => (self.adaptive_spins / 2).saturating_add(needed),
+            // waiting was too long, so spinning was a waste
+            None => self.adaptive_spins / 2,
+        };
+        self.adaptive_spins = spins.clamp(ADAPTIVE_MIN_SPINS.min(max_spins), max_spins);
+    }
+
+    pub fn fallback(&self) -> Fallback {
+        match self.strategy {
+            WaitStrategy::BusySpin => Fallback::Spin,
+            WaitStrategy::SpinYield { .. } => Fallback::Yield,
+            WaitStrategy::SpinSleep { .. } | WaitStrategy::Adaptive { .. } => Fallback::Sleep,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn adaptive_spins() {
+        let max_spins = 1 << 12;
+        let mut waiter = Waiter::new(WaitStrategy::Adaptive { max_spins });
+        assert_eq!(waiter.adaptive_spins, ADAPTIVE_INITIAL_SPINS);
+        // waits that are too long make the receiver spin less
+        for _ in 0..20 {
+            assert!(!waiter.spin(|| false));
+        }
+        assert_eq!(waiter.adaptive_spins, ADAPTIVE_MIN_SPINS);
+        // short waits make it spin a bit more than needed
+        for _ in 0..20 {
+            let mut count = 0;
+            assert!(waiter.spin(|| {
+                count += 1;
+                count > 10
+            }));
+        }
+        assert!((ADAPTIVE_MIN_SPINS..=40).contains(&waiter.adaptive_spins));
+        assert!(waiter.adaptive_spins >= 10);
+        // the spins never exceed the maximum
+        for _ in 0..20 {
+            let mut count = 0;
+            waiter.spin(|| {
+                count += 1;
+                count > max_spins
+            });
+        }
+        assert!(waiter.adaptive_spins <= max_spins);
+    }
+}
@@ -0,0 +1,343 @@
+//! Flowgraphs described at runtime.
+//!
+//! A [`FlowgraphDescription`] lists the blocks of a flowgraph, with their
+//! parameters, and its circuits, with the number of quanta, the buffer length
+//! and the connections. It can be read from TOML or JSON. For example:
+//!
+//! ```toml
+//! [[blocks]]
+//! name = "source"
+//! type = "NullSource"
+//!
+//! [[blocks]]
+//! name = "head"
+//! type = "Head"
+//! params = { count = 1000 }
+//!
+//! [[blocks]]
+//! name = "sink"
+//! type = "NullSink"
+//!
+//! [[circuits]]
+//! buffer = "f32"
+//! quanta = 4
+//! buffer_len = 4096
+//! connections = [
+//!     { source = "source.output", destination = "head.input" },
+//!     { source = "head.output", destination = "sink.input", return = "source.input" },
+//! ]
+//! ```
+//!
+//! A [`BlockRegistry`] maps the block types and buffer types used in the
+//! description to Rust types, and builds the [`Flowgraph`].
+
+use crate::{
+    Block, Buffer, Flowgraph, Quantum,
+    ports::ErasedEndpoint,
+    runtime::flowgraph::{Circuit, FlowgraphNode},
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{any::Any, collections::HashMap};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlowgraphDescription {
+    #[serde(default)]
+    pub blocks: Vec<BlockDescription>,
+    #[serde(default)]
+    pub circuits: Vec<CircuitDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockDescription {
+    pub name: String,
+    /// Block type, as registered in the [`BlockRegistry`].
+    #[serde(rename = "type")]
+    pub block_type: String,
+    /// Parameters passed to the block factory.
+    #[serde(default)]
+    pub params: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CircuitDescription {
+    /// Buffer type, as registered in the [`BlockRegistry`].
+    pub buffer: String,
+    /// Number of quanta in the circuit.
+    pub quanta: usize,
+    /// Length of each buffer, in items.
+    pub buffer_len: usize,
+    #[serde(default)]
+    pub connections: Vec<ConnectionDescription>,
+}
+
+/// Connection between ports, given as `"block.port"`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConnectionDescription {
+    pub source: String,
+    pub destination: String,
+    #[serde(default, rename = "return", skip_serializing_if = "Option::is_none")]
+    pub return_destination: Option<String>,
+}
+
+impl FlowgraphDescription {
+    pub fn from_toml(s: &str) -> Result<FlowgraphDescription> {
+        Ok(toml::from_str(s)?)
+    }
+
+    pub fn from_json(s: &str) -> Result<FlowgraphDescription> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+type BlockFactory =
+    Box<dyn Fn(&mut Flowgraph, &str, serde_json::Value) -> Result<Box<dyn AnyNode>>>;
+
+type BufferFactory = Box<dyn Fn(&mut Flowgraph, usize, usize) -> Circuit<Box<dyn Any>>>;
+
+/// Registry of the block types and buffer types that can be used in a
+/// [`FlowgraphDescription`].
+#[derive(Default)]
+pub struct BlockRegistry {
+    blocks: HashMap<String, BlockFactory>,
+    buffers: HashMap<String, BufferFactory>,
+}
+
+impl std::fmt::Debug for BlockRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlockRegistry")
+            .field("blocks", &self.blocks.keys())
+            .field("buffers", &self.buffers.keys())
+            .finish()
+    }
+}
+
+impl BlockRegistry {
+    pub fn new() -> BlockRegistry {
+        BlockRegistry::default()
+    }
+
+    /// Registers a block type.
+    ///
+    /// The factory constructs the block from its parameters, which are
+    /// deserialized into `P`. Blocks without parameters can use `()`.
+    pub fn register<B, P, F>(&mut self, block_type: impl Into<String>, factory: F) -> &mut Self
+    where
+        B: Block + 'static,
+        P: DeserializeOwned,
+        F: Fn(P) -> Result<B> + 'static,
+    {
+        self.blocks.insert(
+            block_type.into(),
+            Box::new(move |fg, name, params| {
+                let params = P::deserialize(params).context("invalid parameters")?;
+                let block = factory(params)?;
+                Ok(Box::new(fg.add_block_named(name, block)))
+            }),
+        );
+        self
+    }
+
+    /// Registers a buffer type.
+    ///
+    /// The circuits that use this buffer type have quanta of type
+    /// `Quantum<B>`, and their buffers are constructed with `new`, which is
+    /// given the buffer length.
+    pub fn register_buffer<B, F>(&mut self, buffer_type: impl Into<String>, new: F) -> &mut Self
+    where
+        B: Buffer + 'static,
+        F: Fn(usize) -> B + 'static,
+    {
+        self.buffers.insert(
+            buffer_type.into(),
+            Box::new(move |fg, quanta, buffer_len| {
+                let messages = std::iter::repeat_with(|| Quantum::new(new(buffer_len)))
+                    .take(quanta)
+                    .collect::<Vec<_>>();
+                fg.new_circuit_erased(messages)
+            }),
+        );
+        self
+    }
+
+    /// Builds the flowgraph given by a description.
+    ///
+    /// The connections are checked in the same way as with
+    /// [`Flowgraph::connect`] and [`Flowgraph::connect_with_return`], but at
+    /// runtime.
+    pub fn load(&self, description: &FlowgraphDescription) -> Result<Flowgraph> {
+        let mut fg = Flowgraph::new();
+        let mut nodes = HashMap::new();
+        for block in &description.blocks {
+            anyhow::ensure!(
+                !nodes.contains_key(&block.name),
+                "duplicate block name {}",
+                block.name
+            );
+            let factory = self
+                .blocks
+                .get(&block.block_type)
+                .with_context(|| format!("unknown block type {}", block.block_type))?;
+            let node = factory(&mut fg, &block.name, block.params.clone())
+                .with_context(|| format!("cannot create block {}", block.name))?;
+            nodes.insert(block.name.clone(), node);
+        }
+
+        for (n, circuit) in description.circuits.iter().enumerate() {
+            let result = (|| {
+                let factory = self
+                    .buffers
+                    .get(&circuit.buffer)
+                    .with_context(|| format!("unknown buffer type {}", circuit.buffer))?;
+                let mut fg_circuit = factory(&mut fg, circuit.quanta, circuit.buffer_len);
+                for connection in &circuit.connections {
+                    let source = endpoint(&nodes, &connection.source)?;
+                    let destination = endpoint(&nodes, &connection.destination)?;
+                    let return_destination = connection
+                        .return_destination
+                        .as_ref()
+                        .map(|name| endpoint(&nodes, name))
+                        .transpose()?;
+                    fg.connect_erased(&mut fg_circuit, source, destination, return_destination)?;
+                }
+                Ok::<_, anyhow::Error>(())
+            })();
+            result.with_context(|| format!("cannot create circuit {n}"))?;
+        }
+
+        Ok(fg)
+    }
+}
+
+fn endpoint<'a>(
+    nodes: &'a HashMap<String, Box<dyn AnyNode>>,
+    name: &str,
+) -> Result<ErasedEndpoint<'a>> {
+    let (block, port) = name
+        .split_once('.')
+        .with_context(|| format!("invalid endpoint {name} (it should be block.port)"))?;
+    nodes
+        .get(block)
+        .with_context(|| format!("unknown block {block}"))?
+        .erased_endpoint(port)
+        .with_context(|| format!("invalid endpoint {name}"))
+}
+
+// Object-safe part of FlowgraphNode.
+trait AnyNode {
+    fn erased_endpoint(&self, port: &str) -> Result<ErasedEndpoint<'_>>;
+}
+
+impl<N: FlowgraphNode + 'static> AnyNode for N {
+    fn erased_endpoint(&self, port: &str) -> Result<ErasedEndpoint<'_>> {
+        FlowgraphNode::erased_endpoint(self, port)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        blocks::basic::{Head, NullSink, NullSource},
+        buffers::CacheAlignedBuffer,
+        channels::{Spsc, SpscRef},
+    };
+
+    type B = CacheAlignedBuffer<u32>;
+
+    #[derive(Deserialize)]
+    struct HeadParams {
+        count: u64,
+    }
+
+    fn registry() -> BlockRegistry {
+        let mut registry = BlockRegistry::new();
+        registry
+            .register("NullSource", |()| {
+                Ok(NullSource::<Quantum<B>, Spsc, Spsc>::new())
+            })
+            .register("Head", |params: HeadParams| {
+                Ok(Head::<Quantum<B>, Spsc, SpscRef>::new(params.count))
+            })
+            .register("NullSink", |()| Ok(NullSink::<Quantum<B>, SpscRef>::new()))
+            .register_buffer("u32", B::new);
+        registry
+    }
+
+    const DESCRIPTION: &str = r#"
+[[blocks]]
+name = "source"
+type = "NullSource"
+
+[[blocks]]
+name = "head"
+type = "Head"
+params = { count = 1000 }
+
+[[blocks]]
+name = "sink"
+type = "NullSink"
+
+[[circuits]]
+buffer = "u32"
+quanta = 4
+buffer_len = 64
+connections = [
+    { source = "source.output", destination = "head.input" },
+    { source = "head.output", destination = "sink.input", return = "source.input" },
+]
+"#;
+
+    #[test]
+    fn load_and_run() {
+        let description = FlowgraphDescription::from_toml(DESCRIPTION).unwrap();
+        let json = serde_json::to_string(&description).unwrap();
+        assert_eq!(FlowgraphDescription::from_json(&json).unwrap(), description);
+        let fg = registry().load(&description).unwrap();
+        fg.validate().unwrap().run().unwrap();
+    }
+
+    #[test]
+    fn load_errors() {
+        let registry = registry();
+        let load = |description: &str| {
+            let description = FlowgraphDescription::from_toml(description).unwrap();
+            format!("{:#}", registry.load(&description).unwrap_err())
+        };
+
+        let err = load(&DESCRIPTION.replace("NullSink", "Sink"));
+        assert!(err.contains("unknown block type Sink"), "{err}");
+        let err = load(&DESCRIPTION.replace("count = 1000", "cnt = 1000"));
+        assert!(err.contains("cannot create block head"), "{err}");
+        let err = load(&DESCRIPTION.replace("\"head\"", "\"sink\""));
+        assert!(err.contains("duplicate block name sink"), "{err}");
+        let err = load(&DESCRIPTION.replace("sink.input", "sink.in"));
+        assert!(err.contains("block does not have a port named in"), "{err}");
+        let err = load(&DESCRIPTION.replace("sink.input", "sink"));
+        assert!(err.contains("invalid endpoint sink"), "{err}");
+        // a connection with a return needs a reference input, and the input of
+        // head is not one
+        let err = load(&DESCRIPTION.replace(
+            "{ source = \"source.output\", destination = \"head.input\" }",
+            "{ source = \"source.output\", destination = \"head.input\", return = \"source.input\" }",
+        ));
+        assert!(
+            err.contains("destination port is In but it should be RefIn"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn buffer_type_mismatch() {
+        let mut registry = registry();
+        registry.register_buffer("f32", CacheAlignedBuffer::<f32>::new);
+        let description =
+            FlowgraphDescription::from_toml(&DESCRIPTION.replace("\"u32\"", "\"f32\"")).unwrap();
+        let err = format!("{:#}", registry.load(&description).unwrap_err());
+        assert!(err.contains("cannot create circuit 0"), "{err}");
+    }
+}
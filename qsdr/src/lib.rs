@@ -1,15 +1,24 @@
 pub mod blocks;
 pub mod channel;
+#[cfg(feature = "description")]
+pub mod description;
 pub mod kernels {
     pub mod saxpy;
 }
 mod runtime;
+pub mod shm;
+#[cfg(feature = "stats")]
+pub use runtime::flowgraph::{EdgeStats, FlowgraphStats};
 pub use runtime::{
     block::{Block, BlockObject, BlockWorkStatus},
     buffer::Buffer,
     channel::{Channel, Receiver, RefReceiver, Sender},
+    error::{BlockError, BlockInfo, FlowgraphError},
     flowgraph::{Flowgraph, ValidatedFlowgraph},
+    metadata::{Metadata, Tag, TagValue},
+    pod::{Pod, cast_slice, cast_slice_mut},
     quantum::{Quantum, QuantumSnapshot},
+    select::{Select, Selected2, Selected3, Selected4},
     work::{
         WorkCustom, WorkInPlace, WorkSink, WorkStatus,
         WorkStatus::{DoneWithOutput, DoneWithoutOutput, Run},
@@ -17,22 +26,29 @@ pub use runtime::{
     },
 };
 pub mod buffers {
-    pub use crate::runtime::buffer::CacheAlignedBuffer;
+    pub use crate::runtime::buffer::{
+        AlignedBuffer, CacheAlignedBuffer, HugePageBuffer, MmapBuffer, RingBuffer, ShmBuffer,
+    };
 }
 pub mod ports {
     pub use crate::runtime::port::{
-        Endpoint, PortIn, PortInQ, PortOut, PortOutQ, PortRefIn, PortRefInQ, PortSource,
-        PortSourceQ,
+        Endpoint, ErasedEndpoint, PortIn, PortInQ, PortInfo, PortKind, PortOut, PortOutQ,
+        PortRefIn, PortRefInQ, PortSource, PortSourceQ,
     };
 }
 pub mod channels {
-    pub use crate::runtime::channel::{Mpsc, SpBroadcast, Spsc, SpscRef, SpscmrRef};
+    pub use crate::runtime::channel::{
+        FutexMpsc, FutexSpBroadcast, FutexSpsc, FutexSpscRef, FutexSpscmrRef, FutexWorkQueue, Mpsc,
+        SpBroadcast, Spsc, SpscRef, SpscmrRef, WorkQueue,
+    };
 }
 
 pub mod scheduler {
     pub use crate::runtime::scheduler::{
-        Sequence2, Sequence3, Sequence4, Sequence5, Sequence6, Sequence7, Sequence8, run,
-        sequence2, sequence3, sequence4, sequence5, sequence6, sequence7, sequence8,
+        BlockThreads, CpuGroups, ErrorPolicy, Executor, FuturesExecutor, Sequence2, Sequence3,
+        Sequence4, Sequence5, Sequence6, Sequence7, Sequence8, SequenceN, StopHandle,
+        pin_current_thread, run, sequence_vec, sequence2, sequence3, sequence4, sequence5,
+        sequence6, sequence7, sequence8,
     };
 }
 
@@ -54,8 +70,9 @@ pub use qsdr_macros::Block;
 #[doc(hidden)]
 pub mod __private {
     pub use crate::runtime::{
-        flowgraph::{FlowgraphId, FlowgraphNode, NodeId},
-        port::{Port, PortId},
+        block::TakeSeeds,
+        flowgraph::{FlowgraphId, FlowgraphNode, NodeId, NodeState},
+        port::{Port, PortId, PortInfo, PortKind},
     };
 
     pub use pin_project_lite;
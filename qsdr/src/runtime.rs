@@ -1,9 +1,13 @@
 pub mod block;
 pub mod buffer;
 pub mod channel;
+pub mod error;
 pub mod flowgraph;
+pub mod metadata;
+pub mod pod;
 pub mod port;
 pub mod quantum;
 pub mod scheduler;
+pub mod select;
 pub mod sheet;
 pub mod work;
@@ -1,7 +1,11 @@
-use super::{flowgraph::FlowgraphNode, work::WorkStatus};
+use super::{
+    error::{BlockError, BlockInfo},
+    flowgraph::FlowgraphNode,
+    work::WorkStatus,
+};
 use anyhow::Result;
 use futures::stream::FusedStream;
-use std::{fmt::Debug, future::Future};
+use std::{fmt::Debug, future::Future, pin::Pin};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
 pub enum BlockWorkStatus {
@@ -21,7 +25,7 @@ impl From<WorkStatus> for BlockWorkStatus {
 
 pub trait Block: Sized {
     type Channels: Debug;
-    type Seeds: TryInto<Self::Channels, Error = anyhow::Error>;
+    type Seeds: TryInto<Self::Channels, Error = anyhow::Error> + TakeSeeds;
     type Node: FlowgraphNode<B = Self>;
 
     fn block_work(
@@ -30,14 +34,41 @@ pub trait Block: Sized {
     ) -> impl Future<Output = Result<BlockWorkStatus>>;
 }
 
+// Implemented by derive(Block) for the seeds of each block. Takes the seeds,
+// leaving unconnected seeds in their place.
+pub trait TakeSeeds: Default {
+    fn take(&self) -> Self;
+}
+
 pub struct BlockObject<B: Block> {
     block: B,
     channels: B::Channels,
+    info: Option<BlockInfo>,
 }
 
 impl<B: Block> BlockObject<B> {
     pub fn new(block: B, channels: B::Channels) -> BlockObject<B> {
-        BlockObject { block, channels }
+        BlockObject {
+            block,
+            channels,
+            info: None,
+        }
+    }
+
+    pub(crate) fn with_info(self, info: BlockInfo) -> BlockObject<B> {
+        BlockObject {
+            info: Some(info),
+            ..self
+        }
+    }
+
+    /// Returns the identity of the block in the flowgraph it was extracted
+    /// from.
+    ///
+    /// When this is known, the errors of the stream returned by
+    /// [`into_stream`](BlockObject::into_stream) are [`BlockError`]s.
+    pub fn info(&self) -> Option<&BlockInfo> {
+        self.info.as_ref()
     }
 
     pub fn into_stream(self) -> impl FusedStream<Item = Result<()>> {
@@ -48,8 +79,34 @@ impl<B: Block> BlockObject<B> {
             match self_.block.block_work(&mut self_.channels).await {
                 Ok(BlockWorkStatus::Run) => Some((Ok(()), self_)),
                 Ok(BlockWorkStatus::Done) => None,
-                Err(err) => Some((Err(err), self_)),
+                Err(err) => {
+                    let err = match &self_.info {
+                        Some(info) => BlockError::new(info.clone(), err).into(),
+                        None => err,
+                    };
+                    Some((Err(err), self_))
+                }
             }
         })
     }
 }
+
+// Type-erased BlockObject. The runners that use several threads need
+// Box<dyn AnyBlockObject + Send> to send the blocks to their threads.
+pub(crate) trait AnyBlockObject {
+    fn into_boxed_stream(self: Box<Self>) -> Pin<Box<dyn FusedStream<Item = Result<()>>>>;
+
+    // Runs one call to the work function of the block, blocking the thread
+    // until it completes.
+    fn work_blocking(&mut self) -> Result<BlockWorkStatus>;
+}
+
+impl<B: Block + 'static> AnyBlockObject for BlockObject<B> {
+    fn into_boxed_stream(self: Box<Self>) -> Pin<Box<dyn FusedStream<Item = Result<()>>>> {
+        Box::pin((*self).into_stream())
+    }
+
+    fn work_blocking(&mut self) -> Result<BlockWorkStatus> {
+        futures::executor::block_on(self.block.block_work(&mut self.channels))
+    }
+}
@@ -1,5 +1,15 @@
+mod aligned;
+pub use aligned::AlignedBuffer;
 mod cache_aligned;
 pub use cache_aligned::CacheAlignedBuffer;
+mod huge_page;
+pub use huge_page::HugePageBuffer;
+mod mmap;
+pub use mmap::MmapBuffer;
+mod ring;
+pub use ring::RingBuffer;
+mod shm;
+pub use shm::ShmBuffer;
 
 /// Buffer.
 ///
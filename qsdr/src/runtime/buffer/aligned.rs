@@ -0,0 +1,209 @@
+use super::{Buffer, cache_aligned::CACHE_LINE_SIZE};
+use crate::Pod;
+use std::{
+    alloc::{Layout, alloc, alloc_zeroed, dealloc, handle_alloc_error},
+    mem::{ManuallyDrop, MaybeUninit},
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+/// Buffer allocated with an alignment of `ALIGN` bytes.
+///
+/// `ALIGN` must be a power of two. If it is smaller than the alignment of `T`,
+/// the alignment of `T` is used instead. Large alignments, such as the page
+/// size, are useful for buffers that are handed to DMA engines or kernels
+/// that require them.
+#[derive(Debug)]
+pub struct AlignedBuffer<T, const ALIGN: usize = CACHE_LINE_SIZE> {
+    ptr: NonNull<T>,
+    len: usize,
+}
+
+unsafe impl<T: Send, const ALIGN: usize> Send for AlignedBuffer<T, ALIGN> {}
+unsafe impl<T: Sync, const ALIGN: usize> Sync for AlignedBuffer<T, ALIGN> {}
+
+impl<T, const ALIGN: usize> AlignedBuffer<T, ALIGN> {
+    fn layout(len: usize) -> Layout {
+        const { assert!(ALIGN.is_power_of_two(), "ALIGN must be a power of two") };
+        Layout::array::<T>(len).unwrap().align_to(ALIGN).unwrap()
+    }
+
+    // Allocates memory for len items, which is not initialized unless zeroed
+    // is true.
+    fn allocate(len: usize, zeroed: bool) -> NonNull<T> {
+        if size_of::<T>() == 0 || len == 0 {
+            return NonNull::dangling();
+        }
+        let layout = Self::layout(len);
+        // SAFETY: the layout is generated for a non-zero size
+        let ptr = unsafe {
+            if zeroed {
+                alloc_zeroed(layout)
+            } else {
+                alloc(layout)
+            }
+        };
+        let Some(ptr) = NonNull::new(ptr.cast::<T>()) else {
+            handle_alloc_error(layout);
+        };
+        ptr
+    }
+}
+
+impl<T: Default, const ALIGN: usize> AlignedBuffer<T, ALIGN> {
+    pub fn new(len: usize) -> AlignedBuffer<T, ALIGN> {
+        AlignedBuffer::from_fn(len, |_| T::default())
+    }
+}
+
+impl<T: Clone, const ALIGN: usize> AlignedBuffer<T, ALIGN> {
+    pub fn from_value(len: usize, value: T) -> AlignedBuffer<T, ALIGN> {
+        AlignedBuffer::from_fn(len, |_| value.clone())
+    }
+}
+
+impl<T: Pod, const ALIGN: usize> AlignedBuffer<T, ALIGN> {
+    /// Creates a buffer filled with zeros.
+    ///
+    /// Unlike [`new`](AlignedBuffer::new), this does not write each item.
+    /// Large allocations get their memory directly from the kernel, which
+    /// provides zeroed pages lazily, so this is much faster than initializing
+    /// the buffer when it is going to be overwritten anyway.
+    pub fn zeroed(len: usize) -> AlignedBuffer<T, ALIGN> {
+        AlignedBuffer {
+            ptr: Self::allocate(len, true),
+            len,
+        }
+    }
+}
+
+impl<T, const ALIGN: usize> AlignedBuffer<MaybeUninit<T>, ALIGN> {
+    /// Creates a buffer with uninitialized contents.
+    pub fn new_uninit(len: usize) -> AlignedBuffer<MaybeUninit<T>, ALIGN> {
+        AlignedBuffer {
+            ptr: Self::allocate(len, false),
+            len,
+        }
+    }
+
+    /// Converts the buffer into a buffer of initialized items.
+    ///
+    /// # Safety
+    ///
+    /// All the items of the buffer must have been initialized.
+    pub unsafe fn assume_init(self) -> AlignedBuffer<T, ALIGN> {
+        // the allocation is transferred to the new buffer
+        let buffer = ManuallyDrop::new(self);
+        AlignedBuffer {
+            ptr: buffer.ptr.cast(),
+            len: buffer.len,
+        }
+    }
+}
+
+impl<T, const ALIGN: usize> AlignedBuffer<T, ALIGN> {
+    pub fn from_fn<F>(len: usize, mut f: F) -> AlignedBuffer<T, ALIGN>
+    where
+        F: FnMut(usize) -> T,
+    {
+        let ptr = Self::allocate(len, false);
+        for n in 0..len {
+            // SAFETY: if T is not ZST, the pointer is in-bounds of an
+            // allocated object that is appropriately aligned for T. If T is
+            // ZST, this is a write of a ZST to a dangling pointer.
+            unsafe {
+                std::ptr::write(ptr.as_ptr().add(n), f(n));
+            }
+        }
+        AlignedBuffer { ptr, len }
+    }
+}
+
+impl<T, const ALIGN: usize> Drop for AlignedBuffer<T, ALIGN> {
+    fn drop(&mut self) {
+        // First drop each element contained in the buffer; This even needs to
+        // be done for ZSTs, since their Drop implementation could have
+        // side-effects.
+        for n in 0..self.len {
+            // SAFETY: if T is not ZST, this drops in place using a valid
+            // pointer to an object of type T that is initialized. If T is ZST,
+            // the pointer is always the same dangling pointer and we have
+            // initialized as many objects of type T as we are dropping here.
+            unsafe {
+                std::ptr::drop_in_place(self.ptr.as_ptr().add(n));
+            }
+        }
+
+        if size_of::<T>() == 0 || self.len == 0 {
+            // no allocation was done, so there is no need to deallocate
+            return;
+        }
+
+        // SAFETY: self.ptr was allocated with the same allocator and layout
+        unsafe {
+            dealloc(self.ptr.as_ptr().cast::<u8>(), Self::layout(self.len));
+        }
+    }
+}
+
+impl<T, const ALIGN: usize> Deref for AlignedBuffer<T, ALIGN> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: self.ptr either points to an allocation of the correct length
+        // containing initialized data or is a dangling pointer and self.len is
+        // zero or T is ZST
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr().cast_const(), self.len) }
+    }
+}
+
+impl<T, const ALIGN: usize> DerefMut for AlignedBuffer<T, ALIGN> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: self.ptr either points to an allocation of the correct length
+        // containing initialized data or is a dangling pointer and self.len is
+        // zero or T is ZST
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+unsafe impl<T: Send, const ALIGN: usize> Buffer for AlignedBuffer<T, ALIGN> {
+    type Item = T;
+
+    fn as_mut_ptr(&self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn alignment() {
+        let buffer = AlignedBuffer::<u8>::from_fn(100, |n| n as u8);
+        assert_eq!(buffer.as_mut_ptr().align_offset(CACHE_LINE_SIZE), 0);
+        assert!(buffer.iter().enumerate().all(|(n, &x)| x == n as u8));
+        let buffer = AlignedBuffer::<u32, 4096>::zeroed(1 << 20);
+        assert_eq!(buffer.as_mut_ptr().align_offset(4096), 0);
+        assert!(buffer.iter().all(|&x| x == 0));
+        // the alignment of the type is used if it is larger
+        let buffer = AlignedBuffer::<u64, 1>::new(3);
+        assert_eq!(buffer.as_mut_ptr().align_offset(align_of::<u64>()), 0);
+    }
+
+    #[test]
+    fn uninit() {
+        let mut buffer = AlignedBuffer::<MaybeUninit<String>, 128>::new_uninit(16);
+        assert_eq!(buffer.as_mut_ptr().align_offset(128), 0);
+        for (n, x) in buffer.iter_mut().enumerate() {
+            x.write(n.to_string());
+        }
+        let buffer = unsafe { buffer.assume_init() };
+        assert_eq!(buffer[15], "15");
+        assert!(AlignedBuffer::<MaybeUninit<u32>>::new_uninit(0).is_empty());
+    }
+}
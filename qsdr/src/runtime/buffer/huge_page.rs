@@ -0,0 +1,251 @@
+use super::Buffer;
+use anyhow::{Context, Result};
+use std::{
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::OnceLock,
+};
+
+// Huge page size used when it cannot be read from /proc/meminfo.
+const DEFAULT_HUGE_PAGE_SIZE: usize = 2 << 20;
+
+/// Buffer backed by huge pages.
+///
+/// The buffer is allocated with `mmap`, using pages from the hugetlbfs pool
+/// (`MAP_HUGETLB`) if there are enough free huge pages, and otherwise
+/// requesting transparent huge pages with `madvise(MADV_HUGEPAGE)`. This
+/// reduces the TLB misses when accessing large quanta.
+///
+/// The allocation is rounded up to a multiple of the huge page size, so this
+/// buffer is only worth it for large quanta.
+#[derive(Debug)]
+pub struct HugePageBuffer<T> {
+    ptr: NonNull<T>,
+    len: usize,
+    // length of the mapping in bytes, or zero if there is no mapping
+    map_len: usize,
+    huge_tlb: bool,
+}
+
+unsafe impl<T: Send> Send for HugePageBuffer<T> {}
+unsafe impl<T: Sync> Sync for HugePageBuffer<T> {}
+
+impl<T: Default> HugePageBuffer<T> {
+    pub fn new(len: usize) -> Result<HugePageBuffer<T>> {
+        HugePageBuffer::from_fn(len, |_| T::default())
+    }
+}
+
+impl<T: Clone> HugePageBuffer<T> {
+    pub fn from_value(len: usize, value: T) -> Result<HugePageBuffer<T>> {
+        HugePageBuffer::from_fn(len, |_| value.clone())
+    }
+}
+
+impl<T> HugePageBuffer<T> {
+    pub fn from_fn<F>(len: usize, mut f: F) -> Result<HugePageBuffer<T>>
+    where
+        F: FnMut(usize) -> T,
+    {
+        let size = size_of::<T>()
+            .checked_mul(len)
+            .context("buffer is too large")?;
+        let (ptr, map_len, huge_tlb) = if size == 0 {
+            (NonNull::dangling(), 0, false)
+        } else {
+            let page_size = huge_page_size();
+            let map_len = size
+                .checked_next_multiple_of(page_size)
+                .context("buffer is too large")?;
+            assert!(align_of::<T>() <= page_size);
+            match map_huge_tlb(map_len) {
+                Some(ptr) => (ptr.cast::<T>(), map_len, true),
+                None => (
+                    map_transparent(map_len, page_size)?.cast::<T>(),
+                    map_len,
+                    false,
+                ),
+            }
+        };
+        let mut buffer = HugePageBuffer {
+            ptr,
+            len: 0,
+            map_len,
+            huge_tlb,
+        };
+        for n in 0..len {
+            // SAFETY: if T is not ZST, the pointer is in-bounds of the mapping,
+            // which is page aligned. If T is ZST, this is a write of a ZST to a
+            // dangling pointer.
+            unsafe { buffer.ptr.as_ptr().add(n).write(f(n)) };
+            // the buffer length is updated as the items are initialized, so
+            // that they are dropped if f panics
+            buffer.len = n + 1;
+        }
+        Ok(buffer)
+    }
+
+    /// Returns `true` if the buffer uses pages from the hugetlbfs pool, or
+    /// `false` if it uses transparent huge pages.
+    ///
+    /// Transparent huge pages are only a hint to the kernel, which can back
+    /// the buffer with normal pages instead.
+    pub fn huge_tlb(&self) -> bool {
+        self.huge_tlb
+    }
+
+    /// Locks the buffer in memory with `mlock`.
+    ///
+    /// This prevents the buffer from being swapped out. It usually requires
+    /// increasing `RLIMIT_MEMLOCK` or the `CAP_IPC_LOCK` capability. The
+    /// buffer stays locked until it is dropped.
+    pub fn lock(&mut self) -> Result<()> {
+        if self.map_len == 0 {
+            return Ok(());
+        }
+        if unsafe { libc::mlock(self.ptr.as_ptr().cast(), self.map_len) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("could not lock buffer");
+        }
+        Ok(())
+    }
+}
+
+fn huge_page_size() -> usize {
+    static SIZE: OnceLock<usize> = OnceLock::new();
+    *SIZE.get_or_init(|| {
+        std::fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|meminfo| {
+                let line = meminfo
+                    .lines()
+                    .find(|line| line.starts_with("Hugepagesize:"))?;
+                let kib = line
+                    .trim_start_matches("Hugepagesize:")
+                    .trim()
+                    .strip_suffix("kB")?
+                    .trim()
+                    .parse::<usize>()
+                    .ok()?;
+                Some(kib * 1024)
+            })
+            .unwrap_or(DEFAULT_HUGE_PAGE_SIZE)
+    })
+}
+
+fn mmap_anonymous(len: usize, flags: libc::c_int) -> Option<NonNull<u8>> {
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | flags,
+            -1,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        None
+    } else {
+        NonNull::new(ptr.cast())
+    }
+}
+
+// Maps pages from the hugetlbfs pool. This fails if there are not enough free
+// huge pages.
+fn map_huge_tlb(len: usize) -> Option<NonNull<u8>> {
+    mmap_anonymous(len, libc::MAP_HUGETLB)
+}
+
+// Maps normal pages aligned to the huge page size and asks the kernel to back
+// them with transparent huge pages.
+fn map_transparent(len: usize, page_size: usize) -> Result<NonNull<u8>> {
+    // The mapping is enlarged and then trimmed so that it is aligned to the
+    // huge page size, since otherwise the kernel cannot use huge pages at its
+    // beginning and end.
+    let padded_len = len.checked_add(page_size).context("buffer is too large")?;
+    let ptr = mmap_anonymous(padded_len, 0)
+        .ok_or_else(std::io::Error::last_os_error)
+        .context("could not map buffer")?;
+    let head = ptr.as_ptr().align_offset(page_size);
+    let tail = padded_len - head - len;
+    unsafe {
+        if head != 0 {
+            libc::munmap(ptr.as_ptr().cast(), head);
+        }
+        if tail != 0 {
+            libc::munmap(ptr.as_ptr().add(head + len).cast(), tail);
+        }
+    }
+    let ptr = unsafe { ptr.add(head) };
+    // The kernel might not support transparent huge pages, in which case the
+    // buffer still works with normal pages.
+    unsafe { libc::madvise(ptr.as_ptr().cast(), len, libc::MADV_HUGEPAGE) };
+    Ok(ptr)
+}
+
+impl<T> Drop for HugePageBuffer<T> {
+    fn drop(&mut self) {
+        for n in 0..self.len {
+            // SAFETY: the first self.len items of the buffer are initialized
+            unsafe {
+                std::ptr::drop_in_place(self.ptr.as_ptr().add(n));
+            }
+        }
+        if self.map_len != 0 {
+            unsafe { libc::munmap(self.ptr.as_ptr().cast(), self.map_len) };
+        }
+    }
+}
+
+impl<T> Deref for HugePageBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: self.ptr either points to a mapping that contains self.len
+        // initialized items or is a dangling pointer and self.len is zero or T
+        // is ZST
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr().cast_const(), self.len) }
+    }
+}
+
+impl<T> DerefMut for HugePageBuffer<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: self.ptr either points to a mapping that contains self.len
+        // initialized items or is a dangling pointer and self.len is zero or T
+        // is ZST
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+unsafe impl<T: Send> Buffer for HugePageBuffer<T> {
+    type Item = T;
+
+    fn as_mut_ptr(&self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_fn() {
+        let len = 1 << 20;
+        let mut buffer = HugePageBuffer::<u32>::from_fn(len, |n| n as u32).unwrap();
+        assert_eq!(buffer.len(), len);
+        assert_eq!(buffer.as_mut_ptr().align_offset(huge_page_size()), 0);
+        assert!(buffer.iter().enumerate().all(|(n, &x)| x == n as u32));
+        buffer.fill(7);
+        assert!(buffer.iter().all(|&x| x == 7));
+
+        let empty = HugePageBuffer::<u32>::new(0).unwrap();
+        assert!(empty.is_empty());
+        let zst = HugePageBuffer::<()>::new(len).unwrap();
+        assert_eq!(zst.len(), len);
+    }
+}
@@ -0,0 +1,248 @@
+use super::Buffer;
+use crate::Pod;
+use anyhow::{Context, Result};
+use std::{
+    ops::{Deref, DerefMut},
+    os::fd::{AsFd, AsRawFd},
+    path::Path,
+    ptr::NonNull,
+    sync::Arc,
+};
+
+/// Buffer in a memory mapping of a file descriptor.
+///
+/// This maps a region of an arbitrary file descriptor, such as a UIO device,
+/// a dma-buf or a regular file, so that data written by a driver can be
+/// processed in place. A large mapping can be carved into many buffers of
+/// equal size with [`split`](MmapBuffer::split), which share the mapping.
+#[derive(Debug)]
+pub struct MmapBuffer<T> {
+    mapping: Arc<Mapping>,
+    ptr: NonNull<T>,
+    len: usize,
+}
+
+unsafe impl<T: Send> Send for MmapBuffer<T> {}
+unsafe impl<T: Sync> Sync for MmapBuffer<T> {}
+
+#[derive(Debug)]
+struct Mapping {
+    ptr: NonNull<u8>,
+    len: usize,
+    // offset in the file of the start of the mapping
+    file_offset: u64,
+}
+
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl<T: Pod> MmapBuffer<T> {
+    /// Maps `len` items at `offset` bytes in a file descriptor.
+    ///
+    /// The offset does not need to be a multiple of the page size, but it
+    /// must be aligned for `T`. The file descriptor can be closed after the
+    /// mapping has been created.
+    ///
+    /// # Safety
+    ///
+    /// The region must be backed by the file for as long as the buffer is
+    /// alive (for instance, the file must not be truncated), and it must not
+    /// be modified by other means while it is accessed through the buffer. If
+    /// a device writes to the region, the block that owns the quantum must
+    /// synchronize with the driver before accessing it.
+    pub unsafe fn map(fd: impl AsFd, offset: u64, len: usize) -> Result<MmapBuffer<T>> {
+        anyhow::ensure!(size_of::<T>() != 0, "cannot map zero-sized items");
+        anyhow::ensure!(
+            offset.is_multiple_of(align_of::<T>() as u64),
+            "offset {offset} is not aligned for the items"
+        );
+        let size = size_of::<T>()
+            .checked_mul(len)
+            .context("buffer is too large")?;
+        let mapping = Mapping::new(fd, offset, size)?;
+        let ptr = unsafe { mapping.ptr.add((offset - mapping.file_offset) as usize) }.cast();
+        Ok(MmapBuffer {
+            mapping: Arc::new(mapping),
+            ptr,
+            len,
+        })
+    }
+
+    /// Opens a file and maps `len` items at `offset` bytes.
+    ///
+    /// # Safety
+    ///
+    /// See [`map`](MmapBuffer::map).
+    pub unsafe fn open(path: impl AsRef<Path>, offset: u64, len: usize) -> Result<MmapBuffer<T>> {
+        let path = path.as_ref();
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("could not open {}", path.display()))?;
+        unsafe { MmapBuffer::map(&file, offset, len) }
+    }
+}
+
+impl<T> MmapBuffer<T> {
+    /// Splits the buffer into buffers of `len` items.
+    ///
+    /// The length of the buffer must be a multiple of `len`. The buffers are
+    /// returned in the order in which they appear in the mapping.
+    pub fn split(self, len: usize) -> Result<Vec<MmapBuffer<T>>> {
+        anyhow::ensure!(
+            len != 0 && self.len.is_multiple_of(len),
+            "buffer of {} items cannot be split into buffers of {len} items",
+            self.len
+        );
+        Ok((0..self.len / len)
+            .map(|n| MmapBuffer {
+                mapping: Arc::clone(&self.mapping),
+                // SAFETY: the pointer is in-bounds of this buffer
+                ptr: unsafe { self.ptr.add(n * len) },
+                len,
+            })
+            .collect())
+    }
+
+    /// Returns the offset of the buffer in the file, in bytes.
+    pub fn file_offset(&self) -> u64 {
+        let offset = unsafe { self.ptr.cast::<u8>().offset_from(self.mapping.ptr) };
+        self.mapping.file_offset + offset as u64
+    }
+}
+
+impl Mapping {
+    fn new(fd: impl AsFd, offset: u64, len: usize) -> Result<Mapping> {
+        let page_size = u64::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) })?;
+        // mmap requires an offset that is a multiple of the page size
+        let file_offset = offset - offset % page_size;
+        let len = len
+            .checked_add((offset - file_offset) as usize)
+            .context("buffer is too large")?;
+        if len == 0 {
+            return Ok(Mapping {
+                ptr: NonNull::dangling(),
+                len,
+                file_offset,
+            });
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_fd().as_raw_fd(),
+                libc::off_t::try_from(file_offset)?,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error()).context("could not map file");
+        }
+        Ok(Mapping {
+            ptr: NonNull::new(ptr.cast()).unwrap(),
+            len,
+            file_offset,
+        })
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            unsafe { libc::munmap(self.ptr.as_ptr().cast(), self.len) };
+        }
+    }
+}
+
+impl<T> Deref for MmapBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: self.ptr points to self.len items in the mapping, which is
+        // kept alive by self.mapping
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr().cast_const(), self.len) }
+    }
+}
+
+impl<T> DerefMut for MmapBuffer<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: self.ptr points to self.len items in the mapping, which is
+        // kept alive by self.mapping
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+unsafe impl<T: Send> Buffer for MmapBuffer<T> {
+    type Item = T;
+
+    fn as_mut_ptr(&self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        io::{Read, Seek, SeekFrom, Write},
+        os::fd::{FromRawFd, OwnedFd},
+    };
+
+    #[test]
+    fn map_memfd_and_split() {
+        let fd = unsafe { libc::memfd_create(c"test".as_ptr(), libc::MFD_CLOEXEC) };
+        assert!(fd >= 0);
+        let mut file = std::fs::File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+        let data = (0..4096u32).collect::<Vec<_>>();
+        file.write_all(&[0; 8]).unwrap();
+        for x in &data {
+            file.write_all(&x.to_ne_bytes()).unwrap();
+        }
+
+        // the offset does not need to be page aligned
+        let buffer = unsafe { MmapBuffer::<u32>::map(&file, 8, data.len()) }.unwrap();
+        assert_eq!(&buffer[..], &data[..]);
+        let mut buffers = buffer.split(1024).unwrap();
+        assert_eq!(buffers.len(), 4);
+        assert_eq!(buffers[2].file_offset(), 8 + 2 * 4096);
+        assert_eq!(buffers[2][0], 2048);
+        buffers[3].fill(7);
+        drop(buffers);
+
+        // the writes reach the file
+        let mut contents = Vec::new();
+        file.seek(SeekFrom::Start(8 + 3 * 4096)).unwrap();
+        file.read_to_end(&mut contents).unwrap();
+        assert!(contents.chunks(4).all(|x| x == 7u32.to_ne_bytes()));
+
+        assert!(unsafe { MmapBuffer::<u32>::map(&file, 2, 16) }.is_err());
+        let buffer = unsafe { MmapBuffer::<u32>::map(&file, 0, 16) }.unwrap();
+        assert!(buffer.split(3).is_err());
+    }
+
+    #[test]
+    fn open_file() {
+        let path = std::env::temp_dir().join(format!("qsdr-mmap-test-{}", std::process::id()));
+        std::fs::write(&path, [0u8; 8192]).unwrap();
+        let buffers = unsafe { MmapBuffer::<[i16; 2]>::open(&path, 4096, 1024) }
+            .unwrap()
+            .split(256)
+            .unwrap();
+        let mut quanta = buffers
+            .into_iter()
+            .map(crate::Quantum::new)
+            .collect::<Vec<_>>();
+        quanta[1].as_mut_slice()[0] = [1, -1];
+        drop(quanta);
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents[4096 + 1024..4096 + 1026], 1i16.to_ne_bytes());
+        assert_eq!(contents[4096 + 1026..4096 + 1028], (-1i16).to_ne_bytes());
+    }
+}
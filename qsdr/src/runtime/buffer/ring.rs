@@ -0,0 +1,284 @@
+use super::Buffer;
+use crate::Quantum;
+use anyhow::{Context, Result};
+use std::{
+    ops::{Deref, DerefMut},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr::NonNull,
+    sync::Arc,
+};
+
+/// Buffer in a circular ring of quanta that is mapped twice.
+///
+/// The quanta of the ring are consecutive in memory, and the memory of the
+/// ring is mapped twice consecutively (this is sometimes called a "magic ring
+/// buffer"), so the last quantum of the ring is also placed immediately
+/// before the first quantum. Therefore, the end of the previous quantum and
+/// the start of a quantum form a contiguous slice, which can be obtained
+/// without copying with [`Quantum::window`]. This is useful for kernels such
+/// as FIR filters, which need some history from the previous quantum.
+///
+/// The quanta of the ring are created with [`RingBuffer::ring`].
+#[derive(Debug)]
+pub struct RingBuffer<T> {
+    ring: Arc<Ring>,
+    ptr: NonNull<T>,
+    len: usize,
+    index: usize,
+}
+
+unsafe impl<T: Send> Send for RingBuffer<T> {}
+unsafe impl<T: Sync> Sync for RingBuffer<T> {}
+
+// Double mapping of the memory of a ring.
+#[derive(Debug)]
+struct Ring {
+    base: NonNull<u8>,
+    // size of the ring in bytes; the mapping is twice as large
+    len: usize,
+    num_quanta: usize,
+}
+
+unsafe impl Send for Ring {}
+unsafe impl Sync for Ring {}
+
+impl<T: Copy> RingBuffer<T> {
+    /// Creates the buffers of a ring of `num_quanta` quanta with `len` items
+    /// each, filled with `value`.
+    ///
+    /// The size of the ring in bytes must be a multiple of the page size. The
+    /// buffers are returned in the order of the ring, which is the order in
+    /// which they should be circulated, so that each quantum is preceded in
+    /// memory by the quantum that has been sent before it.
+    pub fn ring(num_quanta: usize, len: usize, value: T) -> Result<Vec<RingBuffer<T>>> {
+        anyhow::ensure!(
+            num_quanta != 0 && len != 0 && size_of::<T>() != 0,
+            "the ring cannot be empty"
+        );
+        let quantum_size = size_of::<T>()
+            .checked_mul(len)
+            .context("ring is too large")?;
+        let ring_size = quantum_size
+            .checked_mul(num_quanta)
+            .context("ring is too large")?;
+        let page_size = usize::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) })?;
+        anyhow::ensure!(
+            ring_size % page_size == 0,
+            "the size of the ring ({ring_size} bytes) is not a multiple \
+             of the page size ({page_size} bytes)"
+        );
+        let ring = Arc::new(Ring::new(ring_size, num_quanta)?);
+        let buffers = (0..num_quanta)
+            .map(|index| {
+                // The quanta are placed in the second mapping, so that the
+                // first mapping contains the quanta that precede them.
+                let ptr = unsafe { ring.base.add(ring_size + index * quantum_size) }.cast::<T>();
+                for n in 0..len {
+                    // SAFETY: the pointer is in-bounds of the mapping, and it
+                    // is aligned for T because the mapping is page aligned
+                    unsafe { ptr.add(n).write(value) };
+                }
+                RingBuffer {
+                    ring: Arc::clone(&ring),
+                    ptr,
+                    len,
+                    index,
+                }
+            })
+            .collect();
+        Ok(buffers)
+    }
+}
+
+impl<T> RingBuffer<T> {
+    /// Returns the index of the buffer in its ring.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    // Returns true if self is the buffer that follows previous in the same
+    // ring.
+    fn follows(&self, previous: &RingBuffer<T>) -> bool {
+        Arc::ptr_eq(&self.ring, &previous.ring)
+            && (previous.index + 1) % self.ring.num_quanta == self.index
+    }
+}
+
+impl Ring {
+    fn new(len: usize, num_quanta: usize) -> Result<Ring> {
+        let fd = unsafe { libc::memfd_create(c"qsdr-ring".as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error()).context("could not create memfd");
+        }
+        // SAFETY: fd is a file descriptor that we own. It is closed when it
+        // goes out of scope, since the mappings keep the memory alive.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        if unsafe { libc::ftruncate(fd.as_raw_fd(), libc::off_t::try_from(len)?) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("could not resize memfd");
+        }
+        let map_len = len.checked_mul(2).context("ring is too large")?;
+        // reserve the address space for both mappings
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                map_len,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error()).context("could not map ring");
+        }
+        let ring = Ring {
+            base: NonNull::new(base.cast()).unwrap(),
+            len,
+            num_quanta,
+        };
+        for offset in [0, len] {
+            let ptr = unsafe {
+                libc::mmap(
+                    ring.base.as_ptr().add(offset).cast(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED | libc::MAP_FIXED,
+                    fd.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(std::io::Error::last_os_error()).context("could not map ring");
+            }
+        }
+        Ok(ring)
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.base.as_ptr().cast(), 2 * self.len) };
+    }
+}
+
+impl<T> Deref for RingBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: self.ptr points to self.len initialized items in the ring,
+        // which is kept mapped by self.ring
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr().cast_const(), self.len) }
+    }
+}
+
+impl<T> DerefMut for RingBuffer<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: self.ptr points to self.len initialized items in the ring,
+        // which is kept mapped by self.ring
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+unsafe impl<T: Send> Buffer for RingBuffer<T> {
+    type Item = T;
+
+    fn as_mut_ptr(&self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<T: Send> Quantum<RingBuffer<T>> {
+    /// Returns a window that contains the last `history_len` items before
+    /// this quantum followed by this quantum.
+    ///
+    /// The items before this quantum belong to its left margin and to the
+    /// `previous` quantum, which needs to be borrowed so that it cannot be
+    /// overwritten while the window is in use. Therefore, a block that needs
+    /// history keeps each quantum until it has processed the next one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `previous` is not the quantum that precedes this one in the
+    /// same ring, or if `history_len` is larger than the length of the
+    /// previous buffer plus the left margin of this quantum.
+    pub fn window<'a>(
+        &'a self,
+        previous: &'a Quantum<RingBuffer<T>>,
+        history_len: usize,
+    ) -> &'a [T] {
+        assert!(
+            self.buffer().follows(previous.buffer()),
+            "the previous quantum does not precede this quantum in the ring"
+        );
+        assert!(
+            history_len <= self.left_margin_len() + previous.buffer().len(),
+            "the history does not fit in the previous quantum"
+        );
+        // SAFETY: the window spans the previous buffer, which is the buffer
+        // immediately before this buffer in memory thanks to the double
+        // mapping, and this buffer up to the end of the text. Both buffers are
+        // borrowed, so the items in the window cannot be mutated.
+        unsafe {
+            std::slice::from_raw_parts(
+                self.as_slice().as_ptr().sub(history_len),
+                history_len + self.len(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn window() {
+        let num_quanta = 4;
+        let len = 1024;
+        let mut quanta = RingBuffer::<u32>::ring(num_quanta, len, 0)
+            .unwrap()
+            .into_iter()
+            .map(Quantum::new)
+            .collect::<Vec<_>>();
+        for (k, quantum) in quanta.iter_mut().enumerate() {
+            for (n, x) in quantum.as_mut_slice().iter_mut().enumerate() {
+                *x = (k * len + n) as u32;
+            }
+        }
+        for k in 0..num_quanta {
+            let previous = &quanta[(k + num_quanta - 1) % num_quanta];
+            let window = quanta[k].window(previous, 16);
+            assert_eq!(window.len(), len + 16);
+            assert_eq!(&window[..16], &previous.as_slice()[len - 16..]);
+            assert_eq!(&window[16..], quanta[k].as_slice());
+        }
+
+        // the history can also include the left margin
+        quanta[1].set_margins(8, 0);
+        let window = quanta[1].window(&quanta[0], 12);
+        assert_eq!(window.len(), len - 8 + 12);
+        assert_eq!(window[0] as usize, len - 4);
+        assert_eq!(window[12] as usize, len + 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not precede")]
+    fn window_not_previous() {
+        let quanta = RingBuffer::<u32>::ring(4, 1024, 0)
+            .unwrap()
+            .into_iter()
+            .map(Quantum::new)
+            .collect::<Vec<_>>();
+        quanta[2].window(&quanta[0], 1);
+    }
+
+    #[test]
+    fn invalid_size() {
+        assert!(RingBuffer::<u8>::ring(3, 100, 0).is_err());
+        assert!(RingBuffer::<u8>::ring(0, 4096, 0).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use super::{Buffer, cache_aligned::CACHE_LINE_SIZE};
+use crate::shm::Segment;
+use anyhow::Result;
+use std::{
+    alloc::Layout,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::Arc,
+};
+
+/// Buffer in a shared memory segment.
+///
+/// The buffer can be accessed by several processes that map the same
+/// [`Segment`]. Each process creates its own `ShmBuffer` for the same memory,
+/// either with one of the constructors or from the offset of the buffer in the
+/// segment with [`from_raw`](ShmBuffer::from_raw). Then quanta can be
+/// exchanged between processes by sending the index of their buffer through a
+/// [shared memory channel](crate::channel::spsc::shm).
+#[derive(Debug)]
+pub struct ShmBuffer<T> {
+    segment: Arc<Segment>,
+    ptr: NonNull<T>,
+    len: usize,
+}
+
+unsafe impl<T: Send> Send for ShmBuffer<T> {}
+unsafe impl<T: Sync> Sync for ShmBuffer<T> {}
+
+impl<T: Copy> ShmBuffer<T> {
+    fn layout(len: usize) -> Layout {
+        Layout::array::<T>(len)
+            .unwrap()
+            .align_to(CACHE_LINE_SIZE)
+            .unwrap()
+    }
+
+    pub fn from_fn<F>(segment: &Arc<Segment>, len: usize, mut f: F) -> Result<ShmBuffer<T>>
+    where
+        F: FnMut(usize) -> T,
+    {
+        let offset = segment.alloc(Self::layout(len))?;
+        let ptr = segment.ptr(offset).cast::<T>();
+        for n in 0..len {
+            // SAFETY: the pointer is in-bounds of an allocation in the segment
+            // that is appropriately aligned for T
+            unsafe { ptr.add(n).write(f(n)) };
+        }
+        Ok(ShmBuffer {
+            segment: Arc::clone(segment),
+            ptr,
+            len,
+        })
+    }
+
+    pub fn from_value(segment: &Arc<Segment>, len: usize, value: T) -> Result<ShmBuffer<T>> {
+        ShmBuffer::from_fn(segment, len, |_| value)
+    }
+
+    /// Recreates a buffer from its offset in the segment.
+    ///
+    /// # Safety
+    ///
+    /// The offset and length must be those of a `ShmBuffer` with the same item
+    /// type in the same segment (possibly mapped by another process). The
+    /// buffer must not be accessed at the same time through several
+    /// `ShmBuffer` objects.
+    pub unsafe fn from_raw(segment: &Arc<Segment>, offset: usize, len: usize) -> ShmBuffer<T> {
+        ShmBuffer {
+            segment: Arc::clone(segment),
+            ptr: segment.ptr(offset).cast::<T>(),
+            len,
+        }
+    }
+}
+
+impl<T: Copy + Default> ShmBuffer<T> {
+    pub fn new(segment: &Arc<Segment>, len: usize) -> Result<ShmBuffer<T>> {
+        ShmBuffer::from_fn(segment, len, |_| T::default())
+    }
+}
+
+impl<T> ShmBuffer<T> {
+    /// Returns the offset of the buffer in its segment.
+    pub fn offset(&self) -> usize {
+        self.segment.offset(self.ptr.cast::<u8>())
+    }
+
+    pub fn segment(&self) -> &Arc<Segment> {
+        &self.segment
+    }
+}
+
+impl<T> Deref for ShmBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        // SAFETY: self.ptr points to an allocation of the correct length in the
+        // segment, which is kept mapped by self.segment
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr().cast_const(), self.len) }
+    }
+}
+
+impl<T> DerefMut for ShmBuffer<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: self.ptr points to an allocation of the correct length in the
+        // segment, which is kept mapped by self.segment
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+unsafe impl<T: Send> Buffer for ShmBuffer<T> {
+    type Item = T;
+
+    fn as_mut_ptr(&self) -> *mut T {
+        self.ptr.as_ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Quantum, channel::spsc::shm};
+    use std::os::fd::AsFd;
+
+    #[test]
+    fn exchange_indices() {
+        let buffer_size = 1024;
+        let num_buffers = 4;
+        let num_quanta = 100;
+        let segment = Arc::new(Segment::new("test", 1 << 20).unwrap());
+        let buffers = std::iter::repeat_with(|| ShmBuffer::<u32>::new(&segment, buffer_size))
+            .take(num_buffers)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let offsets = buffers.iter().map(|b| b.offset()).collect::<Vec<_>>();
+        let mut quanta = buffers.into_iter().map(Quantum::new).collect::<Vec<_>>();
+        let (mut forward_tx, forward_rx) = shm::channel::<usize>(&segment, num_buffers).unwrap();
+        let (return_tx, mut return_rx) = shm::channel::<usize>(&segment, num_buffers).unwrap();
+
+        // the other process fills the buffers that it receives
+        let fd = segment.as_fd().try_clone_to_owned().unwrap();
+        let (forward_rx, return_tx) = (forward_rx.into_raw(), return_tx.into_raw());
+        let other_process = std::thread::spawn(move || {
+            let segment = Arc::new(Segment::from_fd(fd).unwrap());
+            let mut quanta = offsets
+                .iter()
+                .map(|&offset| {
+                    Quantum::new(unsafe {
+                        ShmBuffer::<u32>::from_raw(&segment, offset, buffer_size)
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut rx = unsafe { shm::Receiver::<usize>::from_raw(&segment, forward_rx) };
+            let mut tx = unsafe { shm::Sender::<usize>::from_raw(&segment, return_tx) };
+            let mut count = 0u32;
+            while let Some(index) = rx.recv() {
+                quanta[index].as_mut_slice().fill(count);
+                count += 1;
+                tx.send(index);
+            }
+        });
+
+        for index in 0..num_buffers {
+            forward_tx.send(index);
+        }
+        for n in 0..num_quanta as u32 {
+            let index = return_rx.recv().unwrap();
+            assert!(quanta[index].as_slice().iter().all(|&x| x == n));
+            quanta[index].as_mut_slice().fill(0);
+            if n as usize + num_buffers < num_quanta {
+                forward_tx.send(index);
+            }
+        }
+        drop(forward_tx);
+        other_process.join().unwrap();
+        assert!(return_rx.recv().is_none());
+    }
+}
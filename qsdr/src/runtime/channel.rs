@@ -2,15 +2,17 @@ use anyhow::Result;
 use std::{borrow::Borrow, fmt::Debug, future::Future};
 
 macro_rules! impl_ref_receiver_for_receiver {
-    ($ident:ident) => {
+    ($ident:ident, $blocking:expr) => {
         impl<T> crate::runtime::channel::RefReceiver<T> for $ident<T> {
+            const BLOCKING: bool = $blocking;
+
             type Ref<'a>
                 = T
             where
                 T: 'a;
 
             fn ref_recv(&mut self) -> impl std::future::Future<Output = Option<Self::Ref<'_>>> {
-                self.recv()
+                crate::runtime::channel::Receiver::recv(self)
             }
         }
     };
@@ -20,22 +22,32 @@ pub mod base;
 pub mod ref_receiver;
 
 pub mod spsc;
-pub use spsc::Spsc;
+pub use spsc::{FutexSpsc, Spsc};
 
 pub mod spsc_ref;
-pub use spsc_ref::SpscRef;
+pub use spsc_ref::{FutexSpscRef, SpscRef};
 
 // single-producer single-consumer multiple-returners
 pub mod spscmr_ref;
-pub use spscmr_ref::SpscmrRef;
+pub use spscmr_ref::{FutexSpscmrRef, SpscmrRef};
 
 pub mod mpsc;
-pub use mpsc::Mpsc;
+pub use mpsc::{FutexMpsc, Mpsc};
 
 pub mod spbroadcast;
-pub use spbroadcast::SpBroadcast;
+pub use spbroadcast::{FutexSpBroadcast, SpBroadcast};
+
+pub mod work_queue;
+pub use work_queue::{FutexWorkQueue, WorkQueue};
 
 pub trait Channel: Debug + Default + Send + Unpin + 'static {
+    /// Whether the receivers of the channel block the thread while they wait.
+    ///
+    /// The blocks with inputs from these channels need to run on their own
+    /// thread, as in
+    /// [`ValidatedFlowgraph::run_blocking`](crate::ValidatedFlowgraph::run_blocking).
+    const BLOCKING: bool = false;
+
     type Sender<T>: Sender<T>;
 
     type Receiver<T>: RefReceiver<T>;
@@ -62,6 +74,9 @@ pub trait Sender<T> {
 }
 
 pub trait RefReceiver<T> {
+    /// Whether the receiver blocks the thread while it waits.
+    const BLOCKING: bool = false;
+
     type Ref<'a>: Borrow<T>
     where
         Self: 'a;
@@ -1,16 +1,70 @@
-use super::{Receiver, Sender};
+use super::{Receiver, RefReceiver, Sender};
+use crate::channel::WaitStrategy;
 
 pub trait BaseChannel: 'static {
+    // Whether the receivers block the thread while they wait.
+    const BLOCKING: bool;
+
     type Sender<T>: Sender<T>;
 
-    type Receiver<T>: Receiver<T>;
+    type Receiver<T>: Receiver<T> + RefReceiver<T>;
+
+    fn channel<T>(size: usize, wait_strategy: WaitStrategy)
+    -> (Self::Sender<T>, Self::Receiver<T>);
+}
+
+// Base channel with multiple senders, which can send through a shared
+// reference.
+pub trait MpscBaseChannel: BaseChannel {
+    fn clone_sender<T>(sender: &Self::Sender<T>) -> Self::Sender<T>;
+
+    fn send_shared<T>(sender: &Self::Sender<T>, value: T);
+}
+
+// Base channel with multiple senders and multiple receivers.
+pub trait MpmcBaseChannel: MpscBaseChannel {
+    fn clone_receiver<T>(receiver: &Self::Receiver<T>) -> Self::Receiver<T>;
 }
 
 macro_rules! impl_base_channel {
-    ($ident:ident) => {
+    ($ident:ident, $blocking:expr) => {
         impl BaseChannel for $ident {
+            const BLOCKING: bool = $blocking;
+
             type Sender<T> = Sender<T>;
             type Receiver<T> = Receiver<T>;
+
+            fn channel<T>(size: usize, wait_strategy: WaitStrategy) -> (Sender<T>, Receiver<T>) {
+                channel_with_wait_strategy(size, wait_strategy)
+            }
+        }
+    };
+}
+
+macro_rules! impl_mpsc_base_channel {
+    ($ident:ident, $blocking:expr) => {
+        impl_base_channel!($ident, $blocking);
+
+        impl MpscBaseChannel for $ident {
+            fn clone_sender<T>(sender: &Sender<T>) -> Sender<T> {
+                sender.clone()
+            }
+
+            fn send_shared<T>(sender: &Sender<T>, value: T) {
+                sender.send(value)
+            }
+        }
+    };
+}
+
+macro_rules! impl_mpmc_base_channel {
+    ($ident:ident, $blocking:expr) => {
+        impl_mpsc_base_channel!($ident, $blocking);
+
+        impl MpmcBaseChannel for $ident {
+            fn clone_receiver<T>(receiver: &Receiver<T>) -> Receiver<T> {
+                receiver.clone()
+            }
         }
     };
 }
@@ -18,21 +72,83 @@ macro_rules! impl_base_channel {
 pub use spsc::Spsc;
 mod spsc {
     use super::BaseChannel;
-    use crate::channel::spsc::futures::{Receiver, Sender};
+    use crate::channel::{
+        WaitStrategy,
+        spsc::futures::{Receiver, Sender, channel_with_wait_strategy},
+    };
 
     #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
     pub struct Spsc {}
 
-    impl_base_channel!(Spsc);
+    impl_base_channel!(Spsc, false);
 }
 
 pub use mpsc::Mpsc;
 mod mpsc {
-    use super::BaseChannel;
-    use crate::channel::mpsc::futures::{Receiver, Sender};
+    use super::{BaseChannel, MpscBaseChannel};
+    use crate::channel::{
+        WaitStrategy,
+        mpsc::futures::{Receiver, Sender, channel_with_wait_strategy},
+    };
 
     #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
     pub struct Mpsc {}
 
-    impl_base_channel!(Mpsc);
+    impl_mpsc_base_channel!(Mpsc, false);
+}
+
+pub use futex_spsc::FutexSpsc;
+mod futex_spsc {
+    use super::BaseChannel;
+    use crate::channel::{
+        WaitStrategy,
+        spsc::futex::{Receiver, Sender, channel_with_wait_strategy},
+    };
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+    pub struct FutexSpsc {}
+
+    impl_base_channel!(FutexSpsc, true);
+}
+
+pub use futex_mpsc::FutexMpsc;
+mod futex_mpsc {
+    use super::{BaseChannel, MpscBaseChannel};
+    use crate::channel::{
+        WaitStrategy,
+        mpsc::futex::{Receiver, Sender, channel_with_wait_strategy},
+    };
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+    pub struct FutexMpsc {}
+
+    impl_mpsc_base_channel!(FutexMpsc, true);
+}
+
+pub use mpmc::Mpmc;
+mod mpmc {
+    use super::{BaseChannel, MpmcBaseChannel, MpscBaseChannel};
+    use crate::channel::{
+        WaitStrategy,
+        mpmc::futures::{Receiver, Sender, channel_with_wait_strategy},
+    };
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+    pub struct Mpmc {}
+
+    impl_mpmc_base_channel!(Mpmc, false);
+}
+
+pub use futex_mpmc::FutexMpmc;
+mod futex_mpmc {
+    use super::{BaseChannel, MpmcBaseChannel, MpscBaseChannel};
+    use crate::channel::{
+        WaitStrategy,
+        mpmc::futex::{Receiver, Sender, channel_with_wait_strategy},
+    };
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+    pub struct FutexMpmc {}
+
+    impl_mpmc_base_channel!(FutexMpmc, true);
 }
@@ -1,106 +1,145 @@
-use super::Channel;
-use crate::channel::mpsc::futures::{Receiver, Sender, channel};
+use super::{
+    Channel,
+    base::{self, BaseChannel},
+};
+use crate::channel::{
+    DefaultWait, FutexDefaultWait, Wait,
+    mpsc::{
+        futex::{Receiver as FutexReceiver, Sender as FutexSender},
+        futures::{Receiver, Sender},
+    },
+};
 use anyhow::Result;
-use std::{fmt::Debug, future::Future};
+use std::{fmt::Debug, future::Future, marker::PhantomData};
 
+/// The wait strategy of the receivers of the channel is given by `W`.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
-pub struct Mpsc {}
+pub struct Mpsc<W = DefaultWait>(PhantomData<W>);
 
-impl Channel for Mpsc {
-    type Sender<T> = Sender<T>;
-
-    type Receiver<T> = Receiver<T>;
-
-    type ReturnReceiver<T> = ();
-
-    type SenderSeed<T> = SenderSeed<T>;
-
-    type ReceiverSeed<T> = ReceiverSeed<T>;
-
-    type ReturnReceiverSeed<T> = ();
-
-    fn connect<T, I: Iterator<Item = T>>(
-        size: usize,
-        source: &mut Self::SenderSeed<T>,
-        dest: &mut Self::ReceiverSeed<T>,
-        _return_dest: &mut Self::ReturnReceiverSeed<T>,
-        inject_messages: I,
-    ) -> Result<()> {
-        anyhow::ensure!(source.0.is_none(), "source is already connected");
-        if let Some(seed) = &dest.0 {
-            // channel already exists
-            source.0.replace(seed.sender.clone());
-        } else {
-            // create new channel
-            let (tx, rx) = channel(size);
-            for message in inject_messages.take(size) {
-                tx.send(message);
+/// [`Mpsc`] implemented with futex channels.
+///
+/// See [`FutexSpsc`](super::FutexSpsc).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct FutexMpsc<W = FutexDefaultWait>(PhantomData<W>);
+
+macro_rules! impl_channel {
+    ($channel:ident, $base:ty) => {
+        impl<W: Wait> Channel for $channel<W> {
+            const BLOCKING: bool = <$base as BaseChannel>::BLOCKING;
+
+            type Sender<T> = <$base as BaseChannel>::Sender<T>;
+
+            type Receiver<T> = <$base as BaseChannel>::Receiver<T>;
+
+            type ReturnReceiver<T> = ();
+
+            type SenderSeed<T> = SenderSeed<T, $base>;
+
+            type ReceiverSeed<T> = ReceiverSeed<T, $base>;
+
+            type ReturnReceiverSeed<T> = ();
+
+            fn connect<T, I: Iterator<Item = T>>(
+                size: usize,
+                source: &mut Self::SenderSeed<T>,
+                dest: &mut Self::ReceiverSeed<T>,
+                _return_dest: &mut Self::ReturnReceiverSeed<T>,
+                inject_messages: I,
+            ) -> Result<()> {
+                anyhow::ensure!(source.0.is_none(), "source is already connected");
+                if let Some(seed) = &dest.0 {
+                    // channel already exists
+                    source.0.replace(seed.sender.clone());
+                } else {
+                    // create new channel
+                    let (tx, rx) = <$base>::channel(size, W::STRATEGY);
+                    for message in inject_messages.take(size) {
+                        tx.send(message);
+                    }
+                    source.0.replace(tx.clone());
+                    dest.0.replace(RxSeed {
+                        receiver: rx,
+                        sender: tx,
+                    });
+                }
+                Ok(())
             }
-            source.0.replace(tx.clone());
-            dest.0.replace(RxSeed {
-                receiver: rx,
-                sender: tx,
-            });
         }
-        Ok(())
-    }
+    };
 }
 
+impl_channel!(Mpsc, base::Mpsc);
+impl_channel!(FutexMpsc, base::FutexMpsc);
+
 #[derive(Debug)]
-pub struct SenderSeed<T>(pub(super) Option<Sender<T>>);
+pub struct SenderSeed<T, B: BaseChannel = base::Mpsc>(pub(super) Option<B::Sender<T>>);
 
-impl<T> Default for SenderSeed<T> {
+impl<T, B: BaseChannel> Default for SenderSeed<T, B> {
     fn default() -> Self {
         Self(None)
     }
 }
 
-impl<T> TryFrom<SenderSeed<T>> for Sender<T> {
-    type Error = anyhow::Error;
-
-    fn try_from(value: SenderSeed<T>) -> Result<Sender<T>> {
-        value
-            .0
-            .ok_or_else(|| anyhow::anyhow!("port is not connected"))
-    }
-}
+pub struct ReceiverSeed<T, B: BaseChannel = base::Mpsc>(pub(super) Option<RxSeed<T, B>>);
 
 #[derive(Debug)]
-pub struct ReceiverSeed<T>(pub(super) Option<RxSeed<T>>);
-
-#[derive(Debug)]
-pub(super) struct RxSeed<T> {
-    pub(super) receiver: Receiver<T>,
-    pub(super) sender: Sender<T>,
+pub(super) struct RxSeed<T, B: BaseChannel> {
+    pub(super) receiver: B::Receiver<T>,
+    pub(super) sender: B::Sender<T>,
 }
 
-impl<T> Default for ReceiverSeed<T> {
+impl<T, B: BaseChannel> Default for ReceiverSeed<T, B> {
     fn default() -> Self {
         Self(None)
     }
 }
 
-impl<T> TryFrom<ReceiverSeed<T>> for Receiver<T> {
-    type Error = anyhow::Error;
+macro_rules! impl_endpoints {
+    ($base:ty, $sender:ident, $receiver:ident) => {
+        impl<T> TryFrom<SenderSeed<T, $base>> for $sender<T> {
+            type Error = anyhow::Error;
 
-    fn try_from(value: ReceiverSeed<T>) -> Result<Receiver<T>> {
-        Ok(value
-            .0
-            .ok_or_else(|| anyhow::anyhow!("port is not connected"))?
-            .receiver)
-    }
-}
+            fn try_from(value: SenderSeed<T, $base>) -> Result<$sender<T>> {
+                value
+                    .0
+                    .ok_or_else(|| anyhow::anyhow!("port is not connected"))
+            }
+        }
 
-impl<T> super::Sender<T> for Sender<T> {
-    fn send(&mut self, value: T) {
-        Sender::send(self, value)
-    }
+        impl<T> TryFrom<ReceiverSeed<T, $base>> for $receiver<T> {
+            type Error = anyhow::Error;
+
+            fn try_from(value: ReceiverSeed<T, $base>) -> Result<$receiver<T>> {
+                Ok(value
+                    .0
+                    .ok_or_else(|| anyhow::anyhow!("port is not connected"))?
+                    .receiver)
+            }
+        }
+
+        impl<T> super::Sender<T> for $sender<T> {
+            fn send(&mut self, value: T) {
+                $sender::send(self, value)
+            }
+        }
+    };
 }
 
+impl_endpoints!(base::Mpsc, Sender, Receiver);
+impl_endpoints!(base::FutexMpsc, FutexSender, FutexReceiver);
+
 impl<T> super::Receiver<T> for Receiver<T> {
     fn recv(&mut self) -> impl Future<Output = Option<T>> {
         Receiver::recv(self)
     }
 }
 
-impl_ref_receiver_for_receiver!(Receiver);
+impl<T> super::Receiver<T> for FutexReceiver<T> {
+    // the future blocks the thread when it is polled until an item is received
+    async fn recv(&mut self) -> Option<T> {
+        FutexReceiver::recv(self)
+    }
+}
+
+impl_ref_receiver_for_receiver!(Receiver, false);
+impl_ref_receiver_for_receiver!(FutexReceiver, true);
@@ -56,6 +56,8 @@ impl<T, Tx: BaseChannel> Drop for RefEnvelope<'_, T, Tx> {
 }
 
 impl<T, Rx: BaseChannel, Tx: BaseChannel> super::RefReceiver<T> for RefReceiver<T, Rx, Tx> {
+    const BLOCKING: bool = Rx::BLOCKING;
+
     type Ref<'a>
         = RefEnvelope<'a, T, Tx>
     where
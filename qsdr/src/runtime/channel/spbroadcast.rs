@@ -1,9 +1,14 @@
-use super::{Channel, RefReceiver};
-use crate::channel::{mpsc::futures as mpsc, spsc::futures as spsc};
+use super::{
+    Channel, RefReceiver,
+    base::{self, BaseChannel, MpscBaseChannel},
+};
+use crate::channel::{DefaultWait, FutexDefaultWait, Wait, WaitStrategy};
 use anyhow::Result;
 use std::{
     alloc::{Layout, alloc, dealloc, handle_alloc_error},
     borrow::Borrow,
+    fmt,
+    marker::PhantomData,
     mem::size_of,
     ptr::NonNull,
     sync::{
@@ -11,96 +16,145 @@ use std::{
         atomic::{
             AtomicUsize,
             Ordering::{Acquire, Relaxed, Release},
+            fence,
         },
     },
 };
 
+/// The wait strategy of the receivers of the channel is given by `W`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct SpBroadcast<W = DefaultWait>(PhantomData<W>);
+
+/// [`SpBroadcast`] implemented with futex channels.
+///
+/// See [`FutexSpsc`](super::FutexSpsc).
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
-pub struct SpBroadcast {}
+pub struct FutexSpBroadcast<W = FutexDefaultWait>(PhantomData<W>);
 
-impl Channel for SpBroadcast {
-    type Sender<T> = Sender<T>;
+macro_rules! impl_channel {
+    ($channel:ident, $spsc:ty, $mpsc:ty) => {
+        impl<W: Wait> Channel for $channel<W> {
+            const BLOCKING: bool = <$spsc as BaseChannel>::BLOCKING;
 
-    type Receiver<T> = Receiver<T>;
+            type Sender<T> = Sender<T, $spsc>;
 
-    type ReturnReceiver<T> = mpsc::Receiver<T>;
+            type Receiver<T> = Receiver<T, $spsc, $mpsc>;
 
-    type SenderSeed<T> = SenderSeed<T>;
+            type ReturnReceiver<T> = <$mpsc as BaseChannel>::Receiver<T>;
 
-    type ReceiverSeed<T> = ReceiverSeed<T>;
+            type SenderSeed<T> = SenderSeed<T, $spsc>;
 
-    type ReturnReceiverSeed<T> = super::mpsc::ReceiverSeed<T>;
+            type ReceiverSeed<T> = ReceiverSeed<T, $spsc, $mpsc>;
 
-    fn connect<T, I: Iterator<Item = T>>(
-        size: usize,
-        source: &mut Self::SenderSeed<T>,
-        dest: &mut Self::ReceiverSeed<T>,
-        return_dest: &mut Self::ReturnReceiverSeed<T>,
-        inject_messages: I,
-    ) -> Result<()> {
-        anyhow::ensure!(dest.0.is_none(), "destination is already connected");
-        let return_tx = if let Some(return_seed) = &return_dest.0 {
-            // return channel already exists
-            return_seed.sender.clone()
-        } else {
-            // return channel does not exist yet
-            let (return_tx, return_rx) = mpsc::channel(size);
-            for message in inject_messages.take(size) {
-                return_tx.send(message);
+            type ReturnReceiverSeed<T> = super::mpsc::ReceiverSeed<T, $mpsc>;
+
+            fn connect<T, I: Iterator<Item = T>>(
+                size: usize,
+                source: &mut Self::SenderSeed<T>,
+                dest: &mut Self::ReceiverSeed<T>,
+                return_dest: &mut Self::ReturnReceiverSeed<T>,
+                inject_messages: I,
+            ) -> Result<()> {
+                connect::<T, $spsc, $mpsc, I>(
+                    size,
+                    W::STRATEGY,
+                    source,
+                    dest,
+                    return_dest,
+                    inject_messages,
+                )
             }
-            return_dest.0.replace(super::mpsc::RxSeed {
-                receiver: return_rx,
-                sender: return_tx.clone(),
-            });
-            return_tx
+        }
+    };
+}
+
+impl_channel!(SpBroadcast, base::Spsc, base::Mpsc);
+impl_channel!(FutexSpBroadcast, base::FutexSpsc, base::FutexMpsc);
+
+fn connect<T, S: BaseChannel, M: MpscBaseChannel, I: Iterator<Item = T>>(
+    size: usize,
+    wait_strategy: WaitStrategy,
+    source: &mut SenderSeed<T, S>,
+    dest: &mut ReceiverSeed<T, S, M>,
+    return_dest: &mut super::mpsc::ReceiverSeed<T, M>,
+    inject_messages: I,
+) -> Result<()> {
+    anyhow::ensure!(dest.0.is_none(), "destination is already connected");
+    let return_tx = if let Some(return_seed) = &return_dest.0 {
+        // return channel already exists
+        M::clone_sender(&return_seed.sender)
+    } else {
+        // return channel does not exist yet
+        let (return_tx, return_rx) = M::channel(size, wait_strategy);
+        for message in inject_messages.take(size) {
+            M::send_shared(&return_tx, message);
+        }
+        return_dest.0.replace(super::mpsc::RxSeed {
+            receiver: return_rx,
+            sender: M::clone_sender(&return_tx),
+        });
+        return_tx
+    };
+    let (forward_tx, forward_rx) = S::channel(size, wait_strategy);
+    if source.senders.is_empty() {
+        // allocate buffer
+        let buffer_size = size.next_power_of_two();
+        let layout = Layout::array::<Slot<T>>(buffer_size).unwrap();
+        let ptr = unsafe { alloc(layout) };
+        let Some(ptr) = NonNull::new(ptr.cast::<Slot<T>>()) else {
+            handle_alloc_error(layout);
         };
-        let (forward_tx, forward_rx) = spsc::channel(size);
-        if source.senders.is_empty() {
-            // allocate buffer
-            let buffer_size = size.next_power_of_two();
-            let layout = Layout::array::<Slot<T>>(buffer_size).unwrap();
-            let ptr = unsafe { alloc(layout) };
-            let Some(ptr) = NonNull::new(ptr.cast::<Slot<T>>()) else {
-                handle_alloc_error(layout);
-            };
-            for n in 0..buffer_size {
-                // SAFETY: the buffer has been allocated immediately above
-                unsafe {
-                    std::ptr::write(
-                        &raw mut (*ptr.add(n).as_ptr()).refcount,
-                        AtomicUsize::new(usize::MAX),
-                    );
-                }
+        for n in 0..buffer_size {
+            // SAFETY: the buffer has been allocated immediately above
+            unsafe {
+                std::ptr::write(
+                    &raw mut (*ptr.add(n).as_ptr()).refcount,
+                    AtomicUsize::new(usize::MAX),
+                );
             }
-            let buffer = Buffer {
-                buffer: ptr,
-                mask: buffer_size - 1,
-            };
-            let previous = source.buffer.replace(Arc::new(buffer));
-            assert!(previous.is_none());
         }
-        source.senders.push(forward_tx);
-        dest.0.replace(Receiver {
-            receiver: forward_rx,
-            return_sender: return_tx,
-        });
-        Ok(())
+        let buffer = Buffer {
+            buffer: ptr,
+            mask: buffer_size - 1,
+        };
+        let previous = source.buffer.replace(Arc::new(buffer));
+        assert!(previous.is_none());
     }
+    source.senders.push(forward_tx);
+    dest.0.replace(Receiver {
+        receiver: forward_rx,
+        return_sender: return_tx,
+    });
+    Ok(())
 }
 
-#[derive(Debug)]
-pub struct Sender<T> {
-    senders: Vec<spsc::Sender<Message<T>>>,
+pub struct Sender<T, S: BaseChannel = base::Spsc> {
+    senders: Vec<S::Sender<Message<T>>>,
     buffer: Arc<Buffer<T>>,
     buffer_idx: usize,
 }
 
+impl<T, S: BaseChannel> fmt::Debug for Sender<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("receivers", &self.senders.len())
+            .field("buffer_idx", &self.buffer_idx)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 struct Buffer<T> {
     buffer: NonNull<Slot<T>>,
     mask: usize,
 }
 
+// SAFETY: the values stored in the buffer are dropped or returned by whichever
+// thread releases them last, and are accessed through shared references by all
+// the receivers, possibly concurrently.
+unsafe impl<T: Send + Sync> Send for Buffer<T> {}
+unsafe impl<T: Send + Sync> Sync for Buffer<T> {}
+
 impl<T> Drop for Buffer<T> {
     fn drop(&mut self) {
         let size = self.mask + 1;
@@ -147,7 +201,11 @@ struct Message<T> {
     buffer: Arc<Buffer<T>>,
 }
 
-impl<T> super::Sender<T> for Sender<T> {
+// SAFETY: a Message is a reference to a slot in a Buffer, so it can be sent
+// across threads whenever the Buffer can.
+unsafe impl<T: Send + Sync> Send for Message<T> {}
+
+impl<T, S: BaseChannel> super::Sender<T> for Sender<T, S> {
     fn send(&mut self, value: T) {
         // SAFETY: the calculated offset is in-bounds of the allocation. If
         // refcount contains usize::MAX, the slot is vacant and can be
@@ -181,20 +239,27 @@ impl<T> super::Sender<T> for Sender<T> {
     }
 }
 
-#[derive(Debug)]
-pub struct Receiver<T> {
-    receiver: spsc::Receiver<Message<T>>,
-    return_sender: mpsc::Sender<T>,
+pub struct Receiver<T, S: BaseChannel = base::Spsc, M: MpscBaseChannel = base::Mpsc> {
+    receiver: S::Receiver<Message<T>>,
+    return_sender: M::Sender<T>,
+}
+
+impl<T, S: BaseChannel, M: MpscBaseChannel> fmt::Debug for Receiver<T, S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
 }
 
-impl<T> RefReceiver<T> for Receiver<T> {
+impl<T, S: BaseChannel, M: MpscBaseChannel> RefReceiver<T> for Receiver<T, S, M> {
+    const BLOCKING: bool = S::BLOCKING;
+
     type Ref<'a>
-        = RefEnvelope<'a, T>
+        = RefEnvelope<'a, T, M>
     where
         T: 'a;
 
     async fn ref_recv(&mut self) -> Option<Self::Ref<'_>> {
-        let message = self.receiver.recv().await?;
+        let message = super::Receiver::recv(&mut self.receiver).await?;
         Some(RefEnvelope {
             slot: message.slot,
             _buffer: message.buffer,
@@ -203,36 +268,47 @@ impl<T> RefReceiver<T> for Receiver<T> {
     }
 }
 
-#[derive(Debug)]
-pub struct RefEnvelope<'a, T> {
+pub struct RefEnvelope<'a, T, M: MpscBaseChannel = base::Mpsc> {
     slot: NonNull<Slot<T>>,
     // _buffer is only used to keep alive the buffer allocation at least until
     // RefEnvelope is dropped
     _buffer: Arc<Buffer<T>>,
-    return_sender: &'a mpsc::Sender<T>,
+    return_sender: &'a M::Sender<T>,
+}
+
+impl<T, M: MpscBaseChannel> fmt::Debug for RefEnvelope<'_, T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RefEnvelope")
+            .field("slot", &self.slot)
+            .finish_non_exhaustive()
+    }
 }
 
-impl<T> Borrow<T> for RefEnvelope<'_, T> {
+impl<T, M: MpscBaseChannel> Borrow<T> for RefEnvelope<'_, T, M> {
     fn borrow(&self) -> &T {
         // SAFETY: while RefEnvelope is alive, slot points to a valid T
         unsafe { &self.slot.as_ref().value }
     }
 }
 
-impl<T> AsRef<T> for RefEnvelope<'_, T> {
+impl<T, M: MpscBaseChannel> AsRef<T> for RefEnvelope<'_, T, M> {
     fn as_ref(&self) -> &T {
         self.borrow()
     }
 }
 
-impl<T> Drop for RefEnvelope<'_, T> {
+impl<T, M: MpscBaseChannel> Drop for RefEnvelope<'_, T, M> {
     fn drop(&mut self) {
         let ptr = self.slot.as_ptr();
         // SAFETY: if refcount is 1, then the slot is no longer in use, so it
         // can be read and sent to the return_sender.
         unsafe {
-            if (*ptr).refcount.fetch_sub(1, Relaxed) == 1 {
+            // The receivers may run in different threads, so the accesses to
+            // the value through each RefEnvelope must happen before the value
+            // is read below (this is the same pattern as in Arc).
+            if (*ptr).refcount.fetch_sub(1, Release) == 1 {
                 // this was the last RefEnvelope holding a reference to the object
+                fence(Acquire);
                 let value = std::ptr::read(&raw mut (*ptr).value);
                 // mark slot as vacant
                 let ordering = if size_of::<T>() == 0 {
@@ -243,20 +319,19 @@ impl<T> Drop for RefEnvelope<'_, T> {
                     Release
                 };
                 (*ptr).refcount.store(usize::MAX, ordering);
-                self.return_sender.send(value);
+                M::send_shared(self.return_sender, value);
             }
         }
     }
 }
 
-#[derive(Debug)]
-pub struct SenderSeed<T> {
-    senders: Vec<spsc::Sender<Message<T>>>,
+pub struct SenderSeed<T, S: BaseChannel = base::Spsc> {
+    senders: Vec<S::Sender<Message<T>>>,
     buffer: Option<Arc<Buffer<T>>>,
     buffer_idx: usize,
 }
 
-impl<T> Default for SenderSeed<T> {
+impl<T, S: BaseChannel> Default for SenderSeed<T, S> {
     fn default() -> Self {
         Self {
             senders: Vec::new(),
@@ -266,10 +341,10 @@ impl<T> Default for SenderSeed<T> {
     }
 }
 
-impl<T> TryFrom<SenderSeed<T>> for Sender<T> {
+impl<T, S: BaseChannel> TryFrom<SenderSeed<T, S>> for Sender<T, S> {
     type Error = anyhow::Error;
 
-    fn try_from(value: SenderSeed<T>) -> Result<Sender<T>> {
+    fn try_from(value: SenderSeed<T, S>) -> Result<Sender<T, S>> {
         anyhow::ensure!(!value.senders.is_empty(), "port is not connected");
         Ok(Sender {
             senders: value.senders,
@@ -279,19 +354,20 @@ impl<T> TryFrom<SenderSeed<T>> for Sender<T> {
     }
 }
 
-#[derive(Debug)]
-pub struct ReceiverSeed<T>(Option<Receiver<T>>);
+pub struct ReceiverSeed<T, S: BaseChannel = base::Spsc, M: MpscBaseChannel = base::Mpsc>(
+    Option<Receiver<T, S, M>>,
+);
 
-impl<T> Default for ReceiverSeed<T> {
+impl<T, S: BaseChannel, M: MpscBaseChannel> Default for ReceiverSeed<T, S, M> {
     fn default() -> Self {
         Self(None)
     }
 }
 
-impl<T> TryFrom<ReceiverSeed<T>> for Receiver<T> {
+impl<T, S: BaseChannel, M: MpscBaseChannel> TryFrom<ReceiverSeed<T, S, M>> for Receiver<T, S, M> {
     type Error = anyhow::Error;
 
-    fn try_from(value: ReceiverSeed<T>) -> Result<Receiver<T>> {
+    fn try_from(value: ReceiverSeed<T, S, M>) -> Result<Receiver<T, S, M>> {
         value
             .0
             .ok_or_else(|| anyhow::anyhow!("port is not connected"))
@@ -1,52 +1,68 @@
 use super::{
     Channel,
-    base::Spsc,
+    base::{self, BaseChannel},
     ref_receiver::{RefReceiver, RefReceiverSeed},
 };
-use crate::channel::spsc::futures::{Receiver, Sender, channel};
+use crate::channel::{DefaultWait, FutexDefaultWait, Wait};
 use anyhow::Result;
-use std::fmt::Debug;
+use std::{fmt::Debug, marker::PhantomData};
 
+/// The wait strategy of the receivers of the channel is given by `W`.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
-pub struct SpscRef {}
-
-impl Channel for SpscRef {
-    type Sender<T> = Sender<T>;
-
-    type Receiver<T> = RefReceiver<T, Spsc, Spsc>;
-
-    type ReturnReceiver<T> = Receiver<T>;
-
-    type SenderSeed<T> = super::spsc::SenderSeed<T>;
-
-    type ReceiverSeed<T> = RefReceiverSeed<T, Spsc, Spsc>;
-
-    type ReturnReceiverSeed<T> = super::spsc::ReceiverSeed<T>;
-
-    fn connect<T, I: Iterator<Item = T>>(
-        size: usize,
-        source: &mut Self::SenderSeed<T>,
-        dest: &mut Self::ReceiverSeed<T>,
-        return_dest: &mut Self::ReturnReceiverSeed<T>,
-        inject_messages: I,
-    ) -> Result<()> {
-        anyhow::ensure!(source.0.is_none(), "source is already connected");
-        anyhow::ensure!(dest.0.is_none(), "destination is already connected");
-        anyhow::ensure!(
-            return_dest.0.is_none(),
-            "return destination is already connected"
-        );
-        let (forward_tx, forward_rx) = channel(size);
-        let (mut return_tx, return_rx) = channel(size);
-        for message in inject_messages.take(size) {
-            return_tx.send(message);
+pub struct SpscRef<W = DefaultWait>(PhantomData<W>);
+
+/// [`SpscRef`] implemented with futex channels.
+///
+/// See [`FutexSpsc`](super::FutexSpsc).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct FutexSpscRef<W = FutexDefaultWait>(PhantomData<W>);
+
+macro_rules! impl_channel {
+    ($channel:ident, $base:ty) => {
+        impl<W: Wait> Channel for $channel<W> {
+            const BLOCKING: bool = <$base as BaseChannel>::BLOCKING;
+
+            type Sender<T> = <$base as BaseChannel>::Sender<T>;
+
+            type Receiver<T> = RefReceiver<T, $base, $base>;
+
+            type ReturnReceiver<T> = <$base as BaseChannel>::Receiver<T>;
+
+            type SenderSeed<T> = super::spsc::SenderSeed<T, $base>;
+
+            type ReceiverSeed<T> = RefReceiverSeed<T, $base, $base>;
+
+            type ReturnReceiverSeed<T> = super::spsc::ReceiverSeed<T, $base>;
+
+            fn connect<T, I: Iterator<Item = T>>(
+                size: usize,
+                source: &mut Self::SenderSeed<T>,
+                dest: &mut Self::ReceiverSeed<T>,
+                return_dest: &mut Self::ReturnReceiverSeed<T>,
+                inject_messages: I,
+            ) -> Result<()> {
+                anyhow::ensure!(source.0.is_none(), "source is already connected");
+                anyhow::ensure!(dest.0.is_none(), "destination is already connected");
+                anyhow::ensure!(
+                    return_dest.0.is_none(),
+                    "return destination is already connected"
+                );
+                let (forward_tx, forward_rx) = <$base>::channel(size, W::STRATEGY);
+                let (mut return_tx, return_rx) = <$base>::channel(size, W::STRATEGY);
+                for message in inject_messages.take(size) {
+                    return_tx.send(message);
+                }
+                source.0.replace(forward_tx);
+                dest.0.replace(RefReceiver {
+                    receiver: forward_rx,
+                    return_sender: return_tx,
+                });
+                return_dest.0.replace(return_rx);
+                Ok(())
+            }
         }
-        source.0.replace(forward_tx);
-        dest.0.replace(RefReceiver {
-            receiver: forward_rx,
-            return_sender: return_tx,
-        });
-        return_dest.0.replace(return_rx);
-        Ok(())
-    }
+    };
 }
+
+impl_channel!(SpscRef, base::Spsc);
+impl_channel!(FutexSpscRef, base::FutexSpsc);
@@ -0,0 +1,156 @@
+use super::{
+    Channel,
+    base::{self, BaseChannel, MpmcBaseChannel},
+};
+use crate::channel::{
+    DefaultWait, FutexDefaultWait, Wait,
+    mpmc::{
+        futex::{Receiver as FutexReceiver, Sender as FutexSender},
+        futures::{Receiver, Sender},
+    },
+};
+use anyhow::Result;
+use std::{fmt::Debug, future::Future, marker::PhantomData};
+
+/// Channel that distributes the items among several receivers.
+///
+/// An output port can be connected with this channel to several input ports.
+/// Each item is received by only one of the destinations, which is the first
+/// one that is ready to receive it. This balances the load among several
+/// identical blocks, because a block that is slower or that has been
+/// descheduled receives fewer items. The order of the items is not preserved
+/// across the destinations.
+///
+/// The wait strategy of the receivers of the channel is given by `W`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct WorkQueue<W = DefaultWait>(PhantomData<W>);
+
+/// [`WorkQueue`] implemented with futex channels.
+///
+/// See [`FutexSpsc`](super::FutexSpsc).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct FutexWorkQueue<W = FutexDefaultWait>(PhantomData<W>);
+
+macro_rules! impl_channel {
+    ($channel:ident, $base:ty) => {
+        impl<W: Wait> Channel for $channel<W> {
+            const BLOCKING: bool = <$base as BaseChannel>::BLOCKING;
+
+            type Sender<T> = <$base as BaseChannel>::Sender<T>;
+
+            type Receiver<T> = <$base as BaseChannel>::Receiver<T>;
+
+            type ReturnReceiver<T> = ();
+
+            type SenderSeed<T> = SenderSeed<T, $base>;
+
+            type ReceiverSeed<T> = ReceiverSeed<T, $base>;
+
+            type ReturnReceiverSeed<T> = ();
+
+            fn connect<T, I: Iterator<Item = T>>(
+                size: usize,
+                source: &mut Self::SenderSeed<T>,
+                dest: &mut Self::ReceiverSeed<T>,
+                _return_dest: &mut Self::ReturnReceiverSeed<T>,
+                inject_messages: I,
+            ) -> Result<()> {
+                anyhow::ensure!(dest.0.is_none(), "destination is already connected");
+                let receiver = if let Some(seed) = &source.0 {
+                    // channel already exists
+                    <$base>::clone_receiver(&seed.receiver)
+                } else {
+                    // create new channel
+                    let (tx, rx) = <$base>::channel(size, W::STRATEGY);
+                    for message in inject_messages.take(size) {
+                        tx.send(message);
+                    }
+                    source.0.replace(TxSeed {
+                        sender: tx,
+                        receiver: <$base>::clone_receiver(&rx),
+                    });
+                    rx
+                };
+                dest.0.replace(receiver);
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_channel!(WorkQueue, base::Mpmc);
+impl_channel!(FutexWorkQueue, base::FutexMpmc);
+
+pub struct SenderSeed<T, B: MpmcBaseChannel = base::Mpmc>(Option<TxSeed<T, B>>);
+
+// The seed keeps a receiver to be able to connect more destinations to the
+// channel.
+struct TxSeed<T, B: MpmcBaseChannel> {
+    sender: B::Sender<T>,
+    receiver: B::Receiver<T>,
+}
+
+impl<T, B: MpmcBaseChannel> Default for SenderSeed<T, B> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+#[derive(Debug)]
+pub struct ReceiverSeed<T, B: BaseChannel = base::Mpmc>(Option<B::Receiver<T>>);
+
+impl<T, B: BaseChannel> Default for ReceiverSeed<T, B> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+macro_rules! impl_endpoints {
+    ($base:ty, $sender:ident, $receiver:ident) => {
+        impl<T> TryFrom<SenderSeed<T, $base>> for $sender<T> {
+            type Error = anyhow::Error;
+
+            fn try_from(value: SenderSeed<T, $base>) -> Result<$sender<T>> {
+                Ok(value
+                    .0
+                    .ok_or_else(|| anyhow::anyhow!("port is not connected"))?
+                    .sender)
+            }
+        }
+
+        impl<T> TryFrom<ReceiverSeed<T, $base>> for $receiver<T> {
+            type Error = anyhow::Error;
+
+            fn try_from(value: ReceiverSeed<T, $base>) -> Result<$receiver<T>> {
+                value
+                    .0
+                    .ok_or_else(|| anyhow::anyhow!("port is not connected"))
+            }
+        }
+
+        impl<T> super::Sender<T> for $sender<T> {
+            fn send(&mut self, value: T) {
+                $sender::send(self, value)
+            }
+        }
+    };
+}
+
+impl_endpoints!(base::Mpmc, Sender, Receiver);
+impl_endpoints!(base::FutexMpmc, FutexSender, FutexReceiver);
+
+impl<T> super::Receiver<T> for Receiver<T> {
+    fn recv(&mut self) -> impl Future<Output = Option<T>> {
+        Receiver::recv(self)
+    }
+}
+
+impl<T> super::Receiver<T> for FutexReceiver<T> {
+    // the future blocks the thread when it is polled until an item is received
+    async fn recv(&mut self) -> Option<T> {
+        FutexReceiver::recv(self)
+    }
+}
+
+impl_ref_receiver_for_receiver!(Receiver, false);
+impl_ref_receiver_for_receiver!(FutexReceiver, true);
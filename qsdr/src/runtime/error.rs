@@ -0,0 +1,144 @@
+use super::flowgraph::NodeId;
+use std::fmt;
+
+/// Identity of a block in a flowgraph.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BlockInfo {
+    name: String,
+    node: NodeId,
+    type_name: &'static str,
+}
+
+impl BlockInfo {
+    pub(crate) fn new(name: String, node: NodeId, type_name: &'static str) -> BlockInfo {
+        BlockInfo {
+            name,
+            node,
+            type_name,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn node(&self) -> NodeId {
+        self.node
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+}
+
+impl fmt::Display for BlockInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}, {:?})", self.name, self.type_name, self.node)
+    }
+}
+
+/// Error returned by the `block_work` of a block of a flowgraph.
+#[derive(Debug)]
+pub struct BlockError {
+    block: BlockInfo,
+    error: anyhow::Error,
+}
+
+impl BlockError {
+    pub(crate) fn new(block: BlockInfo, error: anyhow::Error) -> BlockError {
+        BlockError { block, error }
+    }
+
+    /// Returns the block that failed.
+    pub fn block(&self) -> &BlockInfo {
+        &self.block
+    }
+
+    /// Returns the error returned by the block.
+    pub fn error(&self) -> &anyhow::Error {
+        &self.error
+    }
+
+    pub fn into_error(self) -> anyhow::Error {
+        self.error
+    }
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "block {} failed", self.block)
+    }
+}
+
+impl std::error::Error for BlockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+/// What to do with the rest of the blocks of a flowgraph when a block fails.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum ErrorPolicy {
+    /// Stop running all the blocks immediately.
+    #[default]
+    Abort,
+    /// Stop the source blocks and let the rest of the blocks drain the quanta
+    /// that are in flight, as with a
+    /// [`StopHandle`](crate::scheduler::StopHandle). Errors returned by other
+    /// blocks while draining are also collected.
+    Drain,
+}
+
+/// Error returned when running a flowgraph.
+///
+/// It contains the errors of all the blocks that failed, in the order in
+/// which they failed.
+#[derive(Debug)]
+pub struct FlowgraphError {
+    policy: ErrorPolicy,
+    errors: Vec<BlockError>,
+}
+
+impl FlowgraphError {
+    pub(crate) fn new(policy: ErrorPolicy, errors: Vec<BlockError>) -> FlowgraphError {
+        assert!(!errors.is_empty());
+        FlowgraphError { policy, errors }
+    }
+
+    /// Returns the error policy that was used to run the flowgraph.
+    pub fn policy(&self) -> ErrorPolicy {
+        self.policy
+    }
+
+    pub fn errors(&self) -> &[BlockError] {
+        &self.errors
+    }
+
+    pub fn into_errors(self) -> Vec<BlockError> {
+        self.errors
+    }
+}
+
+impl fmt::Display for FlowgraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.errors[..] {
+            [_] => write!(f, "flowgraph failed"),
+            errors => {
+                write!(f, "flowgraph failed: {} blocks failed (", errors.len())?;
+                for (n, error) in errors.iter().enumerate() {
+                    if n != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", error.block.name)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FlowgraphError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.errors[0])
+    }
+}
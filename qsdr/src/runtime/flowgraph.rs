@@ -1,26 +1,47 @@
 use super::{
-    block::{Block, BlockObject},
+    block::{AnyBlockObject, Block, BlockObject, TakeSeeds},
     channel::Channel,
-    port::{ConnectsTo, ConnectsWithReturn, Endpoint, Port, PortId},
+    error::{BlockInfo, ErrorPolicy},
+    port::{
+        ConnectsTo, ConnectsWithReturn, Endpoint, ErasedEndpoint, Port, PortId, PortInfo, PortKind,
+    },
+    scheduler::StopHandle,
 };
-use anyhow::Result;
+use crate::channel::stats::{Collected, collect as collect_stats};
+use anyhow::{Context, Result};
 use std::{
+    any::Any,
     collections::{HashMap, HashSet},
+    fmt,
     iter::ExactSizeIterator,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
+mod dot;
+mod run;
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "stats")]
+pub use stats::{EdgeStats, FlowgraphStats};
+
 #[derive(Debug)]
 pub struct Flowgraph {
     id: FlowgraphId,
     next_circuit: CircuitId,
-    next_node: NodeId,
+    nodes: Vec<NodeData>,
     circuits: HashMap<CircuitId, CircuitData>,
 }
 
 #[derive(Debug)]
 pub struct ValidatedFlowgraph {
     id: FlowgraphId,
+    nodes: Vec<NodeData>,
+    circuits: HashMap<CircuitId, CircuitData>,
+    stop: StopHandle,
+    error_policy: ErrorPolicy,
 }
 
 #[derive(Debug)]
@@ -30,6 +51,14 @@ pub struct Circuit<Messages> {
     messages: Option<Messages>,
 }
 
+#[derive(Debug)]
+struct NodeData {
+    name: String,
+    type_name: &'static str,
+    ports: &'static [PortInfo],
+    state: Arc<dyn ErasedNode>,
+}
+
 #[derive(Debug)]
 struct CircuitData {
     size: usize,
@@ -41,6 +70,8 @@ struct Edge {
     source: EndpointKey,
     dest: EndpointKey,
     return_endpoint: Option<EndpointKey>,
+    // statistics of the channels created when connecting the edge
+    stats: Collected,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -60,8 +91,8 @@ pub struct NodeId(usize);
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct CircuitId(usize);
 
-impl<P: Port> From<Endpoint<'_, P>> for EndpointKey {
-    fn from(value: Endpoint<'_, P>) -> EndpointKey {
+impl<P: Port> From<&Endpoint<'_, P>> for EndpointKey {
+    fn from(value: &Endpoint<'_, P>) -> EndpointKey {
         EndpointKey {
             node: value.node(),
             port: value.port(),
@@ -71,10 +102,129 @@ impl<P: Port> From<Endpoint<'_, P>> for EndpointKey {
 
 pub trait FlowgraphNode {
     type B: Block;
+    const PORTS: &'static [PortInfo];
     fn flowgraph_id(&self) -> FlowgraphId;
     fn node_id(&self) -> NodeId;
-    fn wrap_block(flowgraph_id: FlowgraphId, node_id: NodeId, block: Self::B) -> Self;
-    fn try_into_object(self, fg: &mut ValidatedFlowgraph) -> Result<BlockObject<Self::B>>;
+    fn wrap_state(
+        flowgraph_id: FlowgraphId,
+        node_id: NodeId,
+        state: Arc<NodeState<Self::B>>,
+    ) -> Self;
+    fn state(&self) -> &Arc<NodeState<Self::B>>;
+    // Returns the endpoint of the port with the given name.
+    fn erased_endpoint(&self, port: &str) -> Result<ErasedEndpoint<'_>>
+    where
+        Self: 'static;
+}
+
+// The block and the seeds of its ports. This is shared by the FlowgraphNode
+// returned to the user, which uses the seeds to connect the ports, and by the
+// flowgraph, which can extract the block by itself when running.
+pub struct NodeState<B: Block> {
+    block: Mutex<Option<B>>,
+    seeds: B::Seeds,
+}
+
+impl<B: Block> NodeState<B> {
+    fn new(block: B) -> NodeState<B> {
+        NodeState {
+            block: Mutex::new(Some(block)),
+            seeds: Default::default(),
+        }
+    }
+
+    pub fn seeds(&self) -> &B::Seeds {
+        &self.seeds
+    }
+
+    /// Calls a function with a mutable reference to the block.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block has been extracted from the flowgraph.
+    pub fn with_block<R>(&self, f: impl FnOnce(&mut B) -> R) -> R {
+        let mut block = self.lock_block();
+        f(block
+            .as_mut()
+            .expect("block has already been extracted from the flowgraph"))
+    }
+
+    fn lock_block(&self) -> MutexGuard<'_, Option<B>> {
+        self.block.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn take_object(&self) -> Result<BlockObject<B>> {
+        let mut block = self.lock_block();
+        anyhow::ensure!(block.is_some(), "block has already been extracted");
+        let channels = self.seeds.take().try_into()?;
+        Ok(BlockObject::new(block.take().unwrap(), channels))
+    }
+}
+
+impl<B: Block> fmt::Debug for NodeState<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeState")
+            .field("block", &std::any::type_name::<B>())
+            .field("extracted", &self.lock_block().is_none())
+            .finish_non_exhaustive()
+    }
+}
+
+trait ErasedNode: fmt::Debug {
+    fn is_extracted(&self) -> bool;
+    fn take_object(&self) -> Result<Box<dyn AnyBlockObject>>;
+}
+
+impl<B: Block + 'static> ErasedNode for NodeState<B> {
+    fn is_extracted(&self) -> bool {
+        self.lock_block().is_none()
+    }
+
+    fn take_object(&self) -> Result<Box<dyn AnyBlockObject>> {
+        Ok(Box::new(NodeState::take_object(self)?))
+    }
+}
+
+// Node whose block can be sent to another thread. This is obtained from the
+// FlowgraphNode handles by the runners that use several threads, since the
+// flowgraph itself also accepts blocks that are not Send.
+pub(crate) trait SendNode: fmt::Debug + Send + Sync {
+    fn take_object(&self) -> Result<Box<dyn AnyBlockObject + Send>>;
+}
+
+impl<B> SendNode for NodeState<B>
+where
+    B: Block + Send + 'static,
+    B::Channels: Send,
+    B::Seeds: Send + Sync,
+{
+    fn take_object(&self) -> Result<Box<dyn AnyBlockObject + Send>> {
+        Ok(Box::new(NodeState::take_object(self)?))
+    }
+}
+
+// Node assigned to a thread by CpuGroups or BlockThreads.
+#[derive(Debug, Clone)]
+pub(crate) struct AssignedNode {
+    pub(crate) flowgraph_id: FlowgraphId,
+    pub(crate) node_id: NodeId,
+    pub(crate) state: Arc<dyn SendNode>,
+}
+
+impl AssignedNode {
+    pub(crate) fn new<N>(node: &N) -> AssignedNode
+    where
+        N: FlowgraphNode,
+        N::B: Send + 'static,
+        <N::B as Block>::Channels: Send,
+        <N::B as Block>::Seeds: Send + Sync,
+    {
+        AssignedNode {
+            flowgraph_id: node.flowgraph_id(),
+            node_id: node.node_id(),
+            state: Arc::clone(node.state()) as _,
+        }
+    }
 }
 
 impl Flowgraph {
@@ -84,7 +234,7 @@ impl Flowgraph {
         Flowgraph {
             id: FlowgraphId(id),
             next_circuit: CircuitId(0),
-            next_node: NodeId(0),
+            nodes: Vec::new(),
             circuits: HashMap::new(),
         }
     }
@@ -115,12 +265,52 @@ impl Flowgraph {
         }
     }
 
+    /// Creates a circuit to be used with
+    /// [`connect_erased`](Flowgraph::connect_erased).
+    ///
+    /// The circuit can be used with ports whose items are of type `T`.
+    #[must_use]
+    pub fn new_circuit_erased<T: 'static>(&mut self, messages: Vec<T>) -> Circuit<Box<dyn Any>> {
+        let circuit = self.new_circuit(messages.into_iter());
+        Circuit {
+            id: circuit.id,
+            flowgraph_id: circuit.flowgraph_id,
+            messages: circuit
+                .messages
+                .map(|messages| Box::new(messages.collect::<Vec<_>>()) as _),
+        }
+    }
+
+    /// Adds a block to the flowgraph.
+    ///
+    /// The block is named after its type, without module paths.
+    ///
+    /// The flowgraph keeps ownership of the block, so it can be run with
+    /// [`ValidatedFlowgraph::run`]. The returned node is used to connect the
+    /// ports of the block, and optionally to extract the block from the
+    /// flowgraph with [`ValidatedFlowgraph::extract_block`].
     #[must_use]
-    pub fn add_block<B: Block>(&mut self, block: B) -> B::Node {
-        assert!(self.next_node.0 < usize::MAX);
-        let node_id = self.next_node;
-        self.next_node = NodeId(self.next_node.0 + 1);
-        B::Node::wrap_block(self.id, node_id, block)
+    pub fn add_block<B: Block + 'static>(&mut self, block: B) -> B::Node {
+        self.add_block_named(short_type_name(std::any::type_name::<B>()), block)
+    }
+
+    /// Adds a block to the flowgraph with a name that is used in errors and in
+    /// the DOT representation of the flowgraph.
+    #[must_use]
+    pub fn add_block_named<B: Block + 'static>(
+        &mut self,
+        name: impl Into<String>,
+        block: B,
+    ) -> B::Node {
+        let node_id = NodeId(self.nodes.len());
+        let state = Arc::new(NodeState::new(block));
+        self.nodes.push(NodeData {
+            name: name.into(),
+            type_name: std::any::type_name::<B>(),
+            ports: B::Node::PORTS,
+            state: Arc::clone(&state) as _,
+        });
+        B::Node::wrap_state(self.id, node_id, state)
     }
 
     pub fn connect<PS, PD, M, C, T>(
@@ -136,21 +326,26 @@ impl Flowgraph {
         PD: Port<ChannelType = C, Seed = C::ReceiverSeed<T>>,
     {
         self.ensure_belong(circuit, &source, &destination)?;
-        let circuit_data = self.circuits.get_mut(&circuit.id).unwrap();
-
-        C::connect(
-            circuit_data.size,
-            source.seed(),
-            destination.seed(),
-            &mut (),
-            std::iter::empty(),
-        )?;
-
-        let edge = Edge {
-            source: source.into(),
-            dest: destination.into(),
+        let mut edge = Edge {
+            source: (&source).into(),
+            dest: (&destination).into(),
             return_endpoint: None,
+            stats: Default::default(),
         };
+        let circuit_data = self.circuits.get_mut(&circuit.id).unwrap();
+
+        let (result, stats) = collect_stats(|| {
+            C::connect(
+                circuit_data.size,
+                source.seed(),
+                destination.seed(),
+                &mut (),
+                std::iter::empty(),
+            )
+        });
+        result.with_context(|| edge.describe(&self.nodes, circuit.id))?;
+        edge.stats = stats;
+
         circuit_data.edges.push(edge);
 
         Ok(())
@@ -173,34 +368,138 @@ impl Flowgraph {
         self.ensure_belong(circuit, &source, &destination)?;
         anyhow::ensure!(
             return_destination.flowgraph() == self.id,
-            "return_destination does not belong to this flowgraph"
+            "return destination (port {} of {:?}) does not belong to this flowgraph",
+            return_destination.port_name(),
+            return_destination.node()
         );
+        let mut edge = Edge {
+            source: (&source).into(),
+            dest: (&destination).into(),
+            return_endpoint: Some((&return_destination).into()),
+            stats: Default::default(),
+        };
         let circuit_data = self.circuits.get_mut(&circuit.id).unwrap();
 
         macro_rules! connect {
             ($iter:expr) => {
-                CF::connect(
-                    circuit_data.size,
-                    source.seed(),
-                    destination.seed(),
-                    return_destination.seed(),
-                    $iter,
-                )?;
+                collect_stats(|| {
+                    CF::connect(
+                        circuit_data.size,
+                        source.seed(),
+                        destination.seed(),
+                        return_destination.seed(),
+                        $iter,
+                    )
+                })
             };
         }
 
-        if let Some(messages) = circuit.messages.take() {
-            connect!(messages);
+        let (result, stats) = if let Some(messages) = circuit.messages.take() {
+            connect!(messages)
         } else {
-            connect!(std::iter::empty());
-        }
+            connect!(std::iter::empty())
+        };
+        result.with_context(|| edge.describe(&self.nodes, circuit.id))?;
+        edge.stats = stats;
+
+        circuit_data.edges.push(edge);
 
-        // TODO: add return information to the graph somehow
-        let edge = Edge {
-            source: source.into(),
-            dest: destination.into(),
-            return_endpoint: Some(return_destination.into()),
+        Ok(())
+    }
+
+    /// Connects ports whose types are only known at runtime.
+    ///
+    /// Without a return destination, this is equivalent to
+    /// [`connect`](Flowgraph::connect), and with a return destination it is
+    /// equivalent to [`connect_with_return`](Flowgraph::connect_with_return).
+    /// The port types, which are checked at compile time by these methods,
+    /// are checked at runtime.
+    pub fn connect_erased(
+        &mut self,
+        circuit: &mut Circuit<Box<dyn Any>>,
+        mut source: ErasedEndpoint,
+        mut destination: ErasedEndpoint,
+        mut return_destination: Option<ErasedEndpoint>,
+    ) -> Result<()> {
+        anyhow::ensure!(
+            circuit.flowgraph_id == self.id,
+            "circuit does not belong to this flowgraph"
+        );
+        for (what, endpoint) in [
+            ("source", Some(&source)),
+            ("destination", Some(&destination)),
+        ]
+        .into_iter()
+        .chain(std::iter::once((
+            "return destination",
+            return_destination.as_ref(),
+        ))) {
+            if let Some(endpoint) = endpoint {
+                anyhow::ensure!(
+                    endpoint.flowgraph() == self.id,
+                    "{what} (port {} of {:?}) does not belong to this flowgraph",
+                    endpoint.port_name(),
+                    endpoint.node()
+                );
+            }
+        }
+        let mut edge = Edge {
+            source: EndpointKey {
+                node: source.node(),
+                port: source.port(),
+            },
+            dest: EndpointKey {
+                node: destination.node(),
+                port: destination.port(),
+            },
+            return_endpoint: return_destination.as_ref().map(|endpoint| EndpointKey {
+                node: endpoint.node(),
+                port: endpoint.port(),
+            }),
+            stats: Default::default(),
         };
+        let circuit_data = self.circuits.get_mut(&circuit.id).unwrap();
+
+        let (result, stats) = collect_stats(|| {
+            let connector = source.connector();
+            anyhow::ensure!(
+                connector.is_some(),
+                "source port is an input port ({:?})",
+                source.kind()
+            );
+            let expected_destination = if return_destination.is_some() {
+                PortKind::RefIn
+            } else {
+                PortKind::In
+            };
+            anyhow::ensure!(
+                destination.kind() == expected_destination,
+                "destination port is {:?} but it should be {expected_destination:?}",
+                destination.kind()
+            );
+            if let Some(return_destination) = &return_destination {
+                anyhow::ensure!(
+                    return_destination.kind() == PortKind::Source,
+                    "return destination port is {:?} but it should be {:?}",
+                    return_destination.kind(),
+                    PortKind::Source
+                );
+            }
+            let mut messages = return_destination
+                .is_some()
+                .then(|| circuit.messages.take())
+                .flatten();
+            connector.unwrap()(
+                circuit_data.size,
+                source.seed(),
+                destination.seed(),
+                return_destination.as_mut().map(|endpoint| endpoint.seed()),
+                messages.as_deref_mut(),
+            )
+        });
+        result.with_context(|| edge.describe(&self.nodes, circuit.id))?;
+        edge.stats = stats;
+
         circuit_data.edges.push(edge);
 
         Ok(())
@@ -222,20 +521,71 @@ impl Flowgraph {
         );
         anyhow::ensure!(
             source.flowgraph() == self.id,
-            "source does not belong to this flowgraph"
+            "source (port {} of {:?}) does not belong to this flowgraph",
+            source.port_name(),
+            source.node()
         );
         anyhow::ensure!(
             destination.flowgraph() == self.id,
-            "destination does not belong to this flowgraph"
+            "destination (port {} of {:?}) does not belong to this flowgraph",
+            destination.port_name(),
+            destination.node()
         );
         Ok(())
     }
 
     pub fn validate(self) -> Result<ValidatedFlowgraph> {
         for (&id, circuit) in self.circuits.iter() {
-            circuit.validate(id)?;
+            circuit.validate(id, &self.nodes)?;
         }
-        Ok(ValidatedFlowgraph { id: self.id })
+        self.validate_ports_connected()?;
+        Ok(ValidatedFlowgraph {
+            id: self.id,
+            stop: StopHandle::new(self.nodes.len()),
+            error_policy: ErrorPolicy::default(),
+            nodes: self.nodes,
+            circuits: self.circuits,
+        })
+    }
+
+    fn validate_ports_connected(&self) -> Result<()> {
+        let connected = self
+            .circuits
+            .values()
+            .flat_map(|circuit| circuit.edges.iter())
+            .flat_map(|edge| [Some(edge.source), Some(edge.dest), edge.return_endpoint])
+            .flatten()
+            .collect::<HashSet<_>>();
+        let unconnected = self
+            .nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(node, data)| {
+                (0..data.ports.len()).map(move |port| EndpointKey {
+                    node: NodeId(node),
+                    port: PortId::from(u32::try_from(port).unwrap()),
+                })
+            })
+            .filter(|endpoint| !connected.contains(endpoint))
+            .map(|endpoint| endpoint.name(&self.nodes))
+            .collect::<Vec<_>>();
+        anyhow::ensure!(
+            unconnected.is_empty(),
+            "flowgraph has unconnected ports: {}",
+            unconnected.join(", ")
+        );
+        Ok(())
+    }
+
+    /// Returns a Graphviz DOT representation of the flowgraph.
+    pub fn to_dot(&self) -> String {
+        dot::to_dot(&self.nodes, &self.circuits)
+    }
+
+    /// Returns the statistics of the channels of each edge of the flowgraph.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> FlowgraphStats {
+        stats::stats(&self.nodes, &self.circuits)
     }
 }
 
@@ -243,9 +593,46 @@ impl ValidatedFlowgraph {
     pub fn extract_block<N: FlowgraphNode>(&mut self, node: N) -> Result<BlockObject<N::B>> {
         anyhow::ensure!(
             node.flowgraph_id() == self.id,
-            "node does not belong to this flowgraph"
+            "block {:?} does not belong to this flowgraph",
+            node.node_id()
         );
-        Ok(node.try_into_object(self).unwrap())
+        let data = &self.nodes[node.node_id().0];
+        Ok(node
+            .state()
+            .take_object()
+            .with_context(|| format!("cannot extract block {}", data.name))?
+            .with_info(data.info(node.node_id())))
+    }
+
+    /// Returns a handle that can be used to stop the flowgraph while it runs.
+    ///
+    /// Only the blocks run by the flowgraph, as opposed to the blocks
+    /// extracted with [`extract_block`](ValidatedFlowgraph::extract_block),
+    /// are stopped by the handle.
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Sets what to do with the rest of the blocks when a block fails while
+    /// the flowgraph runs.
+    ///
+    /// The default is [`ErrorPolicy::Abort`].
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.error_policy = policy;
+    }
+
+    /// Returns a Graphviz DOT representation of the flowgraph.
+    pub fn to_dot(&self) -> String {
+        dot::to_dot(&self.nodes, &self.circuits)
+    }
+
+    /// Returns the statistics of the channels of each edge of the flowgraph.
+    ///
+    /// The statistics are updated while the flowgraph runs, so they can be
+    /// obtained before running it and read during or after the run.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> FlowgraphStats {
+        stats::stats(&self.nodes, &self.circuits)
     }
 }
 
@@ -255,8 +642,45 @@ impl Default for Flowgraph {
     }
 }
 
+impl NodeData {
+    fn info(&self, node: NodeId) -> BlockInfo {
+        BlockInfo::new(self.name.clone(), node, self.type_name)
+    }
+
+    // Source blocks are those that do not have any inputs other than the
+    // return of their circuits.
+    fn is_source(&self) -> bool {
+        !self
+            .ports
+            .iter()
+            .any(|port| matches!(port.kind, PortKind::In | PortKind::RefIn))
+    }
+}
+
+impl EndpointKey {
+    fn name(&self, nodes: &[NodeData]) -> String {
+        let node = &nodes[self.node.0];
+        format!("{}.{}", node.name, node.ports[self.port.index()].name)
+    }
+}
+
+impl Edge {
+    fn describe(&self, nodes: &[NodeData], circuit: CircuitId) -> String {
+        let mut description = format!(
+            "edge {} -> {}",
+            self.source.name(nodes),
+            self.dest.name(nodes)
+        );
+        if let Some(return_endpoint) = self.return_endpoint {
+            description.push_str(&format!(" (return to {})", return_endpoint.name(nodes)));
+        }
+        description.push_str(&format!(" in circuit {circuit:?}"));
+        description
+    }
+}
+
 impl CircuitData {
-    fn validate(&self, id: CircuitId) -> Result<()> {
+    fn validate(&self, id: CircuitId, nodes: &[NodeData]) -> Result<()> {
         let return_endpoints = self
             .edges
             .iter()
@@ -265,7 +689,8 @@ impl CircuitData {
         let num_returns = return_endpoints.len();
         anyhow::ensure!(
             num_returns == 1,
-            "circuit {id:?} does not have a single return endpoint (it has {num_returns})",
+            "circuit {id:?} does not have a single return endpoint (it has {num_returns}: {})",
+            endpoint_list(return_endpoints.iter(), nodes)
         );
 
         // check that the circuit is a tree with the return node at the root and
@@ -276,13 +701,15 @@ impl CircuitData {
         while let Some(endpoint) = pending.pop() {
             anyhow::ensure!(
                 visited.insert(endpoint.node),
-                "circuit {id:?} contains a cycle"
+                "circuit {id:?} contains a cycle through block {}",
+                nodes[endpoint.node.0].name
             );
             for edge in self.edges_from_node(endpoint.node) {
                 if self.is_leaf(edge.dest.node) {
                     anyhow::ensure!(
                         edge.return_endpoint.is_some(),
-                        "edge {edge:?} in circuit {id:?} connects to a leaf but does not have a return"
+                        "{} connects to a leaf but does not have a return",
+                        edge.describe(nodes, id)
                     );
                 }
                 pending.push(edge.dest);
@@ -299,10 +726,23 @@ impl CircuitData {
                 nodes
             })
             .collect::<HashSet<_>>();
-        anyhow::ensure!(
-            visited == all_nodes,
-            "circuit {id:?} is not a tree with the return at the root"
-        );
+        if visited != all_nodes {
+            let mut unreachable = all_nodes
+                .difference(&visited)
+                .map(|node| node.0)
+                .collect::<Vec<_>>();
+            unreachable.sort();
+            let unreachable = unreachable
+                .into_iter()
+                .map(|node| nodes[node].name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            anyhow::bail!(
+                "circuit {id:?} is not a tree with the return at the root {} \
+                 (blocks not reachable from the root: {unreachable})",
+                root.name(nodes)
+            );
+        }
 
         Ok(())
     }
@@ -317,3 +757,143 @@ impl CircuitData {
         self.edges_from_node(node).next().is_none()
     }
 }
+
+fn endpoint_list<'a>(
+    endpoints: impl Iterator<Item = &'a EndpointKey>,
+    nodes: &[NodeData],
+) -> String {
+    let mut names = endpoints
+        .map(|endpoint| endpoint.name(nodes))
+        .collect::<Vec<_>>();
+    names.sort();
+    names.join(", ")
+}
+
+// Removes the module paths from a type name, so that
+// "qsdr::blocks::basic::head::Head<qsdr::runtime::quantum::Quantum<u32>>" becomes
+// "Head<Quantum<u32>>".
+fn short_type_name(type_name: &str) -> String {
+    let mut short = String::with_capacity(type_name.len());
+    let mut path = String::new();
+    for c in type_name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            path.push(c);
+        } else {
+            short.push_str(path.rsplit("::").next().unwrap());
+            path.clear();
+            short.push(c);
+        }
+    }
+    short.push_str(path.rsplit("::").next().unwrap());
+    short
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        Quantum,
+        blocks::basic::{Head, NullSink, NullSource, Passthrough, RoundRobin},
+        buffers::CacheAlignedBuffer,
+        channels::{Spsc, SpscRef},
+        scheduler::CpuGroups,
+    };
+
+    #[test]
+    fn short_type_names() {
+        assert_eq!(
+            short_type_name("qsdr::blocks::basic::head::Head<qsdr::Quantum<u32>, a::Spsc>"),
+            "Head<Quantum<u32>, Spsc>"
+        );
+        assert_eq!(short_type_name("[u8; 4]"), "[u8; 4]");
+    }
+
+    #[test]
+    fn validate_error_names_blocks() {
+        type B = CacheAlignedBuffer<u32>;
+        let buffers = std::iter::repeat_with(|| Quantum::new(B::new(16)))
+            .take(4)
+            .collect::<Vec<_>>()
+            .into_iter();
+        let mut fg = Flowgraph::new();
+        let source = fg.add_block_named("source", NullSource::<_, Spsc, Spsc>::new());
+        let other_source = fg.add_block_named("other_source", NullSource::<_, Spsc, Spsc>::new());
+        let round_robin = fg.add_block(RoundRobin::<_, Spsc, SpscRef>::new());
+        let sink0 = fg.add_block_named("sink0", NullSink::<_, SpscRef>::new());
+        let sink1 = fg.add_block_named("sink1", NullSink::<_, SpscRef>::new());
+        let mut circ = fg.new_circuit(buffers);
+        fg.connect(&mut circ, source.output(), round_robin.input())
+            .unwrap();
+        fg.connect_with_return(
+            &mut circ,
+            round_robin.output0(),
+            sink0.input(),
+            source.input(),
+        )
+        .unwrap();
+        fg.connect_with_return(
+            &mut circ,
+            round_robin.output1(),
+            sink1.input(),
+            other_source.input(),
+        )
+        .unwrap();
+        let err = fg.validate().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "circuit CircuitId(0) does not have a single return endpoint \
+             (it has 2: other_source.input, source.input)"
+        );
+    }
+
+    #[test]
+    fn validate_unconnected_ports() {
+        type B = CacheAlignedBuffer<u32>;
+        let buffers = std::iter::repeat_with(|| Quantum::new(B::new(16)))
+            .take(4)
+            .collect::<Vec<_>>()
+            .into_iter();
+        let mut fg = Flowgraph::new();
+        let source = fg.add_block_named("source", NullSource::<_, Spsc, Spsc>::new());
+        let head = fg.add_block_named("head", Head::<_, Spsc, SpscRef>::new(100));
+        let sink = fg.add_block_named("sink", NullSink::<_, SpscRef>::new());
+        let _passthrough = fg.add_block_named("passthrough", Passthrough::<B, Spsc, Spsc>::new());
+        let mut circ = fg.new_circuit(buffers);
+        fg.connect(&mut circ, source.output(), head.input())
+            .unwrap();
+        fg.connect_with_return(&mut circ, head.output(), sink.input(), source.input())
+            .unwrap();
+        let err = fg.validate().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "flowgraph has unconnected ports: passthrough.input, passthrough.output"
+        );
+    }
+
+    #[test]
+    fn run_multi_core_unassigned_blocks() {
+        type B = CacheAlignedBuffer<u32>;
+        let buffers = std::iter::repeat_with(|| Quantum::new(B::new(16)))
+            .take(4)
+            .collect::<Vec<_>>()
+            .into_iter();
+        let mut fg = Flowgraph::new();
+        let source = fg.add_block_named("source", NullSource::<_, Spsc, Spsc>::new());
+        let head = fg.add_block_named("head", Head::<_, Spsc, SpscRef>::new(100));
+        let sink = fg.add_block_named("sink", NullSink::<_, SpscRef>::new());
+        let mut circ = fg.new_circuit(buffers);
+        fg.connect(&mut circ, source.output(), head.input())
+            .unwrap();
+        fg.connect_with_return(&mut circ, head.output(), sink.input(), source.input())
+            .unwrap();
+        let mut groups = CpuGroups::new();
+        groups.add(0, &source).add(0, &head);
+        let err = fg
+            .validate()
+            .unwrap()
+            .run_multi_core(&groups)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "blocks not assigned to any CPU: sink");
+    }
+}
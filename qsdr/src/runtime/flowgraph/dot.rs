@@ -0,0 +1,157 @@
+use super::{CircuitData, CircuitId, EndpointKey, NodeData};
+use std::{collections::HashMap, fmt::Write};
+
+const CIRCUIT_COLORS: &[&str] = &[
+    "blue",
+    "red",
+    "darkgreen",
+    "darkorange",
+    "purple",
+    "brown",
+    "magenta",
+    "cyan4",
+];
+
+pub(super) fn to_dot(nodes: &[NodeData], circuits: &HashMap<CircuitId, CircuitData>) -> String {
+    let mut dot = String::new();
+    // writing to a String cannot fail
+    write_dot(&mut dot, nodes, circuits).unwrap();
+    dot
+}
+
+fn write_dot(
+    w: &mut impl Write,
+    nodes: &[NodeData],
+    circuits: &HashMap<CircuitId, CircuitData>,
+) -> std::fmt::Result {
+    writeln!(w, "digraph flowgraph {{")?;
+    writeln!(w, "    rankdir=LR;")?;
+    writeln!(w, "    node [shape=record];")?;
+
+    for (id, node) in nodes.iter().enumerate() {
+        // inputs are drawn on the left of the block and outputs on the right
+        let ports = |inputs: bool| {
+            node.ports
+                .iter()
+                .enumerate()
+                .filter(|(_, port)| port.kind.is_input() == inputs)
+                .map(|(n, port)| format!("<p{n}> {}", escape(port.name)))
+                .collect::<Vec<_>>()
+                .join("|")
+        };
+        // the type is only shown if the block has been given a custom name
+        let short_type_name = super::short_type_name(node.type_name);
+        let label = if node.name == short_type_name {
+            escape(&node.name)
+        } else {
+            format!("{}\\n{}", escape(&node.name), escape(&short_type_name))
+        };
+        writeln!(
+            w,
+            "    n{id} [label=\"{{{{{}}}|{label}|{{{}}}}}\"];",
+            ports(true),
+            ports(false)
+        )?;
+    }
+
+    let mut circuits = circuits.iter().collect::<Vec<_>>();
+    circuits.sort_by_key(|(id, _)| id.0);
+    for (id, circuit) in &circuits {
+        let color = circuit_color(**id);
+        for edge in &circuit.edges {
+            writeln!(
+                w,
+                "    {} -> {} [color={color}];",
+                endpoint(edge.source),
+                endpoint(edge.dest)
+            )?;
+            if let Some(return_endpoint) = edge.return_endpoint {
+                writeln!(
+                    w,
+                    "    {} -> {} [color={color}, style=dashed, constraint=false];",
+                    endpoint(edge.dest),
+                    endpoint(return_endpoint)
+                )?;
+            }
+        }
+    }
+
+    if !circuits.is_empty() {
+        writeln!(w, "    subgraph cluster_legend {{")?;
+        writeln!(w, "        label=\"circuits\";")?;
+        for (id, circuit) in &circuits {
+            writeln!(
+                w,
+                "        c{} [shape=plaintext, fontcolor={}, label=\"circuit {}: {} quanta\"];",
+                id.0,
+                circuit_color(**id),
+                id.0,
+                circuit.size
+            )?;
+        }
+        writeln!(w, "    }}")?;
+    }
+
+    writeln!(w, "}}")
+}
+
+fn circuit_color(id: CircuitId) -> &'static str {
+    CIRCUIT_COLORS[id.0 % CIRCUIT_COLORS.len()]
+}
+
+fn endpoint(key: EndpointKey) -> String {
+    format!("n{}:p{}", key.node.0, key.port.index())
+}
+
+// escapes the characters that have a special meaning in record labels
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '{' | '}' | '|' | '<' | '>' | '"' | '\\' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Flowgraph, Quantum,
+        blocks::basic::{Head, NullSink, NullSource},
+        buffers::CacheAlignedBuffer,
+        channels::{Spsc, SpscRef},
+    };
+
+    #[test]
+    fn null_source_head_null_sink() {
+        type B = CacheAlignedBuffer<u32>;
+        let buffers = std::iter::repeat_with(|| Quantum::new(B::new(16)))
+            .take(4)
+            .collect::<Vec<_>>()
+            .into_iter();
+        let mut fg = Flowgraph::new();
+        let source = fg.add_block_named("source", NullSource::<_, Spsc, Spsc>::new());
+        let head = fg.add_block(Head::<_, Spsc, SpscRef>::new(100));
+        let sink = fg.add_block(NullSink::<_, SpscRef>::new());
+        let mut circ = fg.new_circuit(buffers);
+        fg.connect(&mut circ, source.output(), head.input())
+            .unwrap();
+        fg.connect_with_return(&mut circ, head.output(), sink.input(), source.input())
+            .unwrap();
+        let fg = fg.validate().unwrap();
+        let dot = fg.to_dot();
+
+        assert!(dot.starts_with("digraph flowgraph {\n"));
+        assert!(dot.contains(
+            "    n0 [label=\"{{<p0> input}|source\\nNullSource\\<Quantum\\<CacheAlignedBuffer\\<u32\\>\\>\\>|{<p1> output}}\"];\n"
+        ));
+        assert!(dot.contains("    n1 [label=\"{{<p0> input}|Head\\<"));
+        assert!(dot.contains("    n0:p1 -> n1:p0 [color=blue];\n"));
+        assert!(dot.contains("    n1:p1 -> n2:p0 [color=blue];\n"));
+        assert!(dot.contains("    n2:p0 -> n0:p0 [color=blue, style=dashed, constraint=false];\n"));
+        assert!(dot.contains("label=\"circuit 0: 4 quanta\""));
+        assert!(dot.ends_with("}\n"));
+    }
+}
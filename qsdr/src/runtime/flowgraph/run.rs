@@ -0,0 +1,385 @@
+use super::{AssignedNode, NodeId, ValidatedFlowgraph};
+use crate::runtime::{
+    block::{AnyBlockObject, BlockWorkStatus},
+    error::{BlockError, BlockInfo, ErrorPolicy, FlowgraphError},
+    scheduler::{
+        BlockThreads, CpuGroups, Executor, FuturesExecutor, StopHandle, Stoppable,
+        pin_current_thread, run, sequence_vec,
+    },
+};
+use anyhow::{Context, Result};
+use futures::stream::{FusedStream, Stream};
+use std::{
+    collections::HashSet,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context as TaskContext, Poll},
+    thread,
+};
+
+type BoxedStream = Pin<Box<dyn FusedStream<Item = Result<()>>>>;
+
+// A block taken from the flowgraph to be run by it. The runners that use
+// several threads use dyn AnyBlockObject + Send.
+struct RunnableBlock<O: ?Sized = dyn AnyBlockObject> {
+    object: Box<O>,
+    info: BlockInfo,
+    // Source blocks are stopped by the stop handle.
+    is_source: bool,
+}
+
+// State shared by all the blocks of a flowgraph run.
+#[derive(Debug, Clone)]
+struct RunState {
+    stop: StopHandle,
+    policy: ErrorPolicy,
+    errors: Arc<Mutex<Vec<BlockError>>>,
+}
+
+impl RunState {
+    fn new(flowgraph: &ValidatedFlowgraph) -> RunState {
+        RunState {
+            stop: flowgraph.stop.clone(),
+            policy: flowgraph.error_policy,
+            errors: Default::default(),
+        }
+    }
+
+    fn fail(&self, error: BlockError) {
+        self.errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(error);
+        match self.policy {
+            ErrorPolicy::Abort => self.stop.abort(),
+            ErrorPolicy::Drain => self.stop.stop(),
+        }
+    }
+
+    fn result(&self) -> Result<()> {
+        let errors =
+            std::mem::take(&mut *self.errors.lock().unwrap_or_else(PoisonError::into_inner));
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(FlowgraphError::new(self.policy, errors).into())
+        }
+    }
+}
+
+impl<O: AnyBlockObject + ?Sized> RunnableBlock<O> {
+    fn into_stream(self, state: &RunState) -> BoxedStream {
+        let node = self.info.node().0;
+        Box::pin(Supervised {
+            stream: state
+                .stop
+                .stoppable(node, self.is_source, self.object.into_boxed_stream()),
+            info: self.info,
+            state: state.clone(),
+        })
+    }
+
+    // Calls the work function of the block until it is done, or until the
+    // flowgraph is stopped (for source blocks) or aborted. Errors are recorded
+    // in the RunState.
+    fn run_blocking(mut self, state: &RunState) {
+        loop {
+            if state.stop.is_aborted() || (self.is_source && state.stop.is_stopped()) {
+                break;
+            }
+            match self.object.work_blocking() {
+                Ok(BlockWorkStatus::Run) => (),
+                Ok(BlockWorkStatus::Done) => break,
+                Err(err) => {
+                    state.fail(BlockError::new(self.info, err));
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Stream of a block run by the flowgraph. It terminates when the flowgraph is
+// stopped (for source blocks) or aborted, and it records the errors of the
+// block in the RunState instead of returning them, so that the behaviour on
+// error is given by the ErrorPolicy.
+struct Supervised {
+    stream: Stoppable<BoxedStream>,
+    info: BlockInfo,
+    state: RunState,
+}
+
+impl Stream for Supervised {
+    type Item = Result<()>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let self_ = self.get_mut();
+        match Pin::new(&mut self_.stream).poll_next(cx) {
+            Poll::Ready(Some(Err(err))) => {
+                self_.stream.terminate();
+                self_.state.fail(BlockError::new(self_.info.clone(), err));
+                Poll::Ready(None)
+            }
+            poll => poll,
+        }
+    }
+}
+
+impl FusedStream for Supervised {
+    fn is_terminated(&self) -> bool {
+        self.stream.is_terminated()
+    }
+}
+
+impl ValidatedFlowgraph {
+    /// Runs all the blocks of the flowgraph on the current thread until they
+    /// are done, using [`FuturesExecutor`].
+    ///
+    /// Blocks that have been extracted with
+    /// [`extract_block`](ValidatedFlowgraph::extract_block) are not run. The
+    /// flowgraph can be stopped before its blocks are done with a
+    /// [`stop_handle`](ValidatedFlowgraph::stop_handle). If any block fails, a
+    /// [`FlowgraphError`] is returned, and the rest of the blocks are handled
+    /// according to the [`ErrorPolicy`] set with
+    /// [`set_error_policy`](ValidatedFlowgraph::set_error_policy).
+    ///
+    /// An error is returned if any of the blocks has an input from a blocking
+    /// channel (see [`Channel::BLOCKING`](crate::Channel::BLOCKING)), since it
+    /// would block the rest of the blocks. These flowgraphs must be run with
+    /// [`run_blocking`](ValidatedFlowgraph::run_blocking).
+    pub fn run(self) -> Result<()> {
+        self.run_on(&FuturesExecutor::default())
+    }
+
+    /// Runs all the blocks of the flowgraph on the current thread until they
+    /// are done, using the given executor.
+    ///
+    /// See [`run`](ValidatedFlowgraph::run).
+    pub fn run_on<E: Executor>(self, executor: &E) -> Result<()> {
+        self.check_not_blocking()?;
+        let blocks = self.take_blocks()?;
+        let state = RunState::new(&self);
+        executor.block_on(async {
+            let streams = blocks
+                .into_iter()
+                .map(|block| block.into_stream(&state))
+                .collect();
+            run(sequence_vec(streams)).await
+        })?;
+        state.result()
+    }
+
+    /// Runs all the blocks of the flowgraph using one thread per CPU group.
+    ///
+    /// Each thread is pinned to its CPU and runs the blocks of its group with
+    /// a sequence scheduler, in the order in which they were added to the
+    /// group. All the blocks that have not been extracted with
+    /// [`extract_block`](ValidatedFlowgraph::extract_block) must be assigned
+    /// to exactly one CPU. The method waits for all the threads to finish.
+    /// Errors and blocking channels are handled as in
+    /// [`run`](ValidatedFlowgraph::run).
+    pub fn run_multi_core(self, groups: &CpuGroups) -> Result<()> {
+        self.check_not_blocking()?;
+        self.check_assigned(groups.groups().iter().flat_map(|group| &group.nodes), "CPU")?;
+
+        let groups = groups
+            .groups()
+            .iter()
+            .map(|group| {
+                let blocks = group
+                    .nodes
+                    .iter()
+                    .map(|node| self.take_assigned_block(node))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((group.cpu, blocks))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let state = RunState::new(&self);
+        let threads = groups
+            .into_iter()
+            .map(|(cpu, blocks)| {
+                let thread_name = format!("CPU {cpu}");
+                let state = state.clone();
+                thread::Builder::new()
+                    .name(format!("qsdr-cpu{cpu}"))
+                    .spawn(move || {
+                        if let Err(err) = pin_current_thread(cpu) {
+                            state.stop.abort();
+                            return Err(err);
+                        }
+                        let streams = blocks
+                            .into_iter()
+                            .map(|block| block.into_stream(&state))
+                            .collect();
+                        FuturesExecutor::default().block_on(run(sequence_vec(streams)))
+                    })
+                    .map(|thread| (thread_name, thread))
+                    .with_context(|| format!("could not spawn thread for CPU {cpu}"))
+            })
+            .collect::<Vec<_>>();
+        join_threads(threads, &state)?;
+        state.result()
+    }
+
+    /// Runs each block of the flowgraph on its own thread until they are
+    /// done.
+    ///
+    /// This is intended for flowgraphs that use blocking channels (see
+    /// [`Channel::BLOCKING`](crate::Channel::BLOCKING)), such as
+    /// [`FutexSpsc`](crate::channels::FutexSpsc), whose receivers block the
+    /// thread while they wait, so that a block waiting for items does not
+    /// prevent the rest from running. Each thread calls the work function of
+    /// its block in a loop, and checks if the flowgraph has been stopped or
+    /// aborted between calls. All the blocks that have not been extracted with
+    /// [`extract_block`](ValidatedFlowgraph::extract_block) must be added to
+    /// `threads`. The method waits for all the threads to finish. Errors are
+    /// handled as in [`run`](ValidatedFlowgraph::run), but a block that is
+    /// waiting in a blocking channel only notices that the flowgraph has been
+    /// stopped or aborted when it receives an item or the channel is closed.
+    pub fn run_blocking(self, threads: &BlockThreads) -> Result<()> {
+        self.check_assigned(threads.nodes().iter(), "thread")?;
+        let blocks = threads
+            .nodes()
+            .iter()
+            .map(|node| self.take_assigned_block(node))
+            .collect::<Result<Vec<_>>>()?;
+        let state = RunState::new(&self);
+        let threads = blocks
+            .into_iter()
+            .map(|block| {
+                let thread_name = format!("block {}", block.info.name());
+                let state = state.clone();
+                thread::Builder::new()
+                    .name(format!("qsdr-{}", block.info.name()))
+                    .spawn(move || {
+                        block.run_blocking(&state);
+                        Ok(())
+                    })
+                    .with_context(|| format!("could not spawn thread for {thread_name}"))
+                    .map(|thread| (thread_name, thread))
+            })
+            .collect::<Vec<_>>();
+        join_threads(threads, &state)?;
+        state.result()
+    }
+
+    // Checks that the blocks that have not been extracted by the user have no
+    // blocking inputs, which would block the thread of the rest of the blocks.
+    fn check_not_blocking(&self) -> Result<()> {
+        for node in &self.nodes {
+            if node.state.is_extracted() {
+                continue;
+            }
+            if let Some(port) = node
+                .ports
+                .iter()
+                .find(|port| port.kind.is_input() && port.blocking)
+            {
+                anyhow::bail!(
+                    "block {} has the blocking input {}, so it can only be run with \
+                     run_blocking",
+                    node.name,
+                    port.name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Checks that the blocks assigned to threads belong to this flowgraph and
+    // that all the blocks that have not been extracted by the user are
+    // assigned to exactly one thread.
+    fn check_assigned<'a>(
+        &self,
+        nodes: impl Iterator<Item = &'a AssignedNode>,
+        thread: &str,
+    ) -> Result<()> {
+        let mut assigned = HashSet::new();
+        for node in nodes {
+            let node_id = node.node_id;
+            anyhow::ensure!(
+                node.flowgraph_id == self.id,
+                "block {node_id:?} does not belong to this flowgraph"
+            );
+            let name = &self.nodes[node_id.0].name;
+            anyhow::ensure!(
+                assigned.insert(node_id),
+                "block {name} is assigned to more than one {thread}"
+            );
+        }
+        let unassigned = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(n, node)| !node.state.is_extracted() && !assigned.contains(&NodeId(*n)))
+            .map(|(_, node)| node.name.as_str())
+            .collect::<Vec<_>>();
+        anyhow::ensure!(
+            unassigned.is_empty(),
+            "blocks not assigned to any {thread}: {}",
+            unassigned.join(", ")
+        );
+        Ok(())
+    }
+
+    // Extracts all the blocks that have not been extracted by the user yet.
+    fn take_blocks(&self) -> Result<Vec<RunnableBlock>> {
+        (0..self.nodes.len())
+            .map(NodeId)
+            .filter(|node_id| !self.nodes[node_id.0].state.is_extracted())
+            .map(|node_id| {
+                let node = &self.nodes[node_id.0];
+                let object = node
+                    .state
+                    .take_object()
+                    .with_context(|| format!("cannot extract block {}", node.name))?;
+                Ok(self.runnable_block(node_id, object))
+            })
+            .collect()
+    }
+
+    fn take_assigned_block(
+        &self,
+        node: &AssignedNode,
+    ) -> Result<RunnableBlock<dyn AnyBlockObject + Send>> {
+        let object = node
+            .state
+            .take_object()
+            .with_context(|| format!("cannot extract block {}", self.nodes[node.node_id.0].name))?;
+        Ok(self.runnable_block(node.node_id, object))
+    }
+
+    fn runnable_block<O: ?Sized>(&self, node_id: NodeId, object: Box<O>) -> RunnableBlock<O> {
+        let node = &self.nodes[node_id.0];
+        RunnableBlock {
+            object,
+            info: node.info(node_id),
+            is_source: node.is_source(),
+        }
+    }
+}
+
+// Waits for the threads of a flowgraph run, which are given with a name for
+// the error messages. The flowgraph is aborted if any thread fails.
+fn join_threads(
+    threads: Vec<Result<(String, thread::JoinHandle<Result<()>>)>>,
+    state: &RunState,
+) -> Result<()> {
+    let mut result = Ok(());
+    for thread in threads {
+        let thread_result = thread.and_then(|(name, thread)| {
+            thread
+                .join()
+                .unwrap_or_else(|payload| std::panic::resume_unwind(payload))
+                .with_context(|| format!("error in thread for {name}"))
+        });
+        if thread_result.is_err() {
+            state.stop.abort();
+        }
+        if result.is_ok() {
+            result = thread_result;
+        }
+    }
+    result
+}
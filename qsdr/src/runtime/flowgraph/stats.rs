@@ -0,0 +1,163 @@
+use super::{CircuitData, CircuitId, NodeData};
+use crate::channel::{ChannelStats, StatsHandle};
+use std::{collections::HashMap, fmt};
+
+/// Statistics of the channels of a flowgraph.
+///
+/// This is obtained with [`Flowgraph::stats`](crate::Flowgraph::stats) or
+/// [`ValidatedFlowgraph::stats`](crate::ValidatedFlowgraph::stats).
+#[derive(Debug, Clone)]
+pub struct FlowgraphStats {
+    edges: Vec<EdgeStats>,
+}
+
+/// Statistics of the channels of an edge of a flowgraph.
+///
+/// The channels of an edge are those that were created when connecting it,
+/// which include the return channel for edges connected with a return.
+/// Channels that are shared by several edges, such as the channel of an
+/// [`Mpsc`](crate::channels::Mpsc) input or the return channel of a
+/// [`SpscmrRef`](crate::channels::SpscmrRef) circuit, are only listed in the
+/// edge that created them.
+#[derive(Debug, Clone)]
+pub struct EdgeStats {
+    source: String,
+    destination: String,
+    return_destination: Option<String>,
+    channels: Vec<StatsHandle>,
+}
+
+pub(super) fn stats(
+    nodes: &[NodeData],
+    circuits: &HashMap<CircuitId, CircuitData>,
+) -> FlowgraphStats {
+    let mut circuits = circuits.iter().collect::<Vec<_>>();
+    circuits.sort_by_key(|(id, _)| id.0);
+    let edges = circuits
+        .into_iter()
+        .flat_map(|(_, circuit)| circuit.edges.iter())
+        .map(|edge| EdgeStats {
+            source: edge.source.name(nodes),
+            destination: edge.dest.name(nodes),
+            return_destination: edge.return_endpoint.map(|endpoint| endpoint.name(nodes)),
+            channels: edge.stats.clone(),
+        })
+        .collect();
+    FlowgraphStats { edges }
+}
+
+impl FlowgraphStats {
+    /// Returns the statistics of each edge, grouped by circuit and in the
+    /// order in which the edges were connected.
+    pub fn edges(&self) -> &[EdgeStats] {
+        &self.edges
+    }
+
+    /// Returns the statistics of the edge whose source is the given
+    /// `"block.port"` endpoint.
+    pub fn edge(&self, source: &str) -> Option<&EdgeStats> {
+        self.edges.iter().find(|edge| edge.source == source)
+    }
+}
+
+impl EdgeStats {
+    /// Source endpoint of the edge, as `"block.port"`.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Destination endpoint of the edge, as `"block.port"`.
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// Return destination endpoint of the edge, as `"block.port"`.
+    pub fn return_destination(&self) -> Option<&str> {
+        self.return_destination.as_deref()
+    }
+
+    /// Returns the current statistics of the channels of the edge.
+    pub fn channels(&self) -> Vec<ChannelStats> {
+        self.channels.iter().map(|channel| channel.get()).collect()
+    }
+}
+
+impl fmt::Display for FlowgraphStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for edge in &self.edges {
+            write!(f, "{} -> {}", edge.source, edge.destination)?;
+            if let Some(return_destination) = &edge.return_destination {
+                write!(f, " (return to {return_destination})")?;
+            }
+            writeln!(f)?;
+            for (n, stats) in edge.channels().iter().enumerate() {
+                writeln!(
+                    f,
+                    "    channel {n}: {} items, high-water mark {}, {} sleeps, {} wakes",
+                    stats.items, stats.high_water_mark, stats.sleeps, stats.wakes
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Flowgraph, Quantum,
+        blocks::basic::{Head, NullSink, NullSource},
+        buffers::CacheAlignedBuffer,
+        channels::{Spsc, SpscRef},
+    };
+
+    #[test]
+    fn null_source_head_null_sink() {
+        type B = CacheAlignedBuffer<u32>;
+        let num_buffers = 4u32;
+        let buffers = std::iter::repeat_with(|| Quantum::new(B::new(16)))
+            .take(num_buffers as usize)
+            .collect::<Vec<_>>()
+            .into_iter();
+        let mut fg = Flowgraph::new();
+        let source = fg.add_block_named("source", NullSource::<_, Spsc, Spsc>::new());
+        let head = fg.add_block_named("head", Head::<_, Spsc, SpscRef>::new(100));
+        let sink = fg.add_block_named("sink", NullSink::<_, SpscRef>::new());
+        let mut circ = fg.new_circuit(buffers);
+        fg.connect(&mut circ, source.output(), head.input())
+            .unwrap();
+        fg.connect_with_return(&mut circ, head.output(), sink.input(), source.input())
+            .unwrap();
+        let fg = fg.validate().unwrap();
+        let stats = fg.stats();
+        fg.run().unwrap();
+
+        assert_eq!(stats.edges().len(), 2);
+        let edge = stats.edge("source.output").unwrap();
+        assert_eq!(edge.destination(), "head.input");
+        assert_eq!(edge.return_destination(), None);
+        let channels = edge.channels();
+        assert_eq!(channels.len(), 1);
+        assert!(channels[0].items >= 100);
+        // there cannot be more items waiting than quanta in the circuit
+        assert!((1..=num_buffers).contains(&channels[0].high_water_mark));
+
+        let edge = stats.edge("head.output").unwrap();
+        assert_eq!(edge.destination(), "sink.input");
+        assert_eq!(edge.return_destination(), Some("source.input"));
+        // forward and return channels
+        let channels = edge.channels();
+        assert_eq!(channels.len(), 2);
+        // the 4 quanta are injected in the return channel
+        assert!(channels.iter().all(|channel| channel.items >= 100));
+        assert!(
+            channels
+                .iter()
+                .all(|channel| channel.high_water_mark <= num_buffers)
+        );
+
+        let report = stats.to_string();
+        assert!(report.starts_with("source.output -> head.input\n    channel 0: "));
+        assert!(report.contains("head.output -> sink.input (return to source.input)\n"));
+    }
+}
@@ -0,0 +1,215 @@
+//! Metadata travelling with the quanta.
+
+use std::{
+    borrow::Cow,
+    time::{Duration, SystemTime},
+};
+
+/// Metadata of a quantum.
+///
+/// The metadata refers to the text of the [`Quantum`](crate::Quantum) it
+/// belongs to: `sample_offset` and `timestamp` are those of the first item of
+/// the text, and the offsets of the tags are relative to the start of the
+/// text. When the text of the quantum is moved with its margin methods, the
+/// metadata is adjusted accordingly.
+///
+/// The metadata implements `Eq` so that snapshots of quanta can be compared.
+/// For this, the sample rate and the [`TagValue::F64`] values are compared
+/// bitwise, so a NaN is equal to itself and `0.0` is not equal to `-0.0`.
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    /// Index of the first item of the text in the stream.
+    ///
+    /// This wraps around when the text is extended to the left beyond the
+    /// beginning of the stream.
+    pub sample_offset: u64,
+    /// Acquisition time of the first item of the text.
+    pub timestamp: Option<SystemTime>,
+    /// Sample rate of the stream, in samples per second.
+    ///
+    /// If this is known and positive, the timestamp is adjusted when the start
+    /// of the text is moved.
+    pub sample_rate: Option<f64>,
+    /// Tags of the items of the text, sorted by offset.
+    pub tags: Vec<Tag>,
+}
+
+impl PartialEq for Metadata {
+    fn eq(&self, other: &Metadata) -> bool {
+        self.sample_offset == other.sample_offset
+            && self.timestamp == other.timestamp
+            && self.sample_rate.map(f64::to_bits) == other.sample_rate.map(f64::to_bits)
+            && self.tags == other.tags
+    }
+}
+
+impl Eq for Metadata {}
+
+/// Key-value tag attached to an item of a quantum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    /// Offset of the item with respect to the start of the text.
+    pub offset: usize,
+    pub key: Cow<'static, str>,
+    pub value: TagValue,
+}
+
+/// Value of a [`Tag`].
+///
+/// `F64` values are compared bitwise, so that the type can implement `Eq`.
+#[derive(Debug, Clone)]
+pub enum TagValue {
+    Empty,
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    String(String),
+}
+
+impl PartialEq for TagValue {
+    fn eq(&self, other: &TagValue) -> bool {
+        match (self, other) {
+            (TagValue::Empty, TagValue::Empty) => true,
+            (TagValue::Bool(a), TagValue::Bool(b)) => a == b,
+            (TagValue::U64(a), TagValue::U64(b)) => a == b,
+            (TagValue::I64(a), TagValue::I64(b)) => a == b,
+            (TagValue::F64(a), TagValue::F64(b)) => a.to_bits() == b.to_bits(),
+            (TagValue::String(a), TagValue::String(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for TagValue {}
+
+impl Metadata {
+    pub fn new(sample_offset: u64) -> Metadata {
+        Metadata {
+            sample_offset,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a tag, keeping the tags sorted by offset.
+    pub fn add_tag(&mut self, offset: usize, key: impl Into<Cow<'static, str>>, value: TagValue) {
+        let index = self.tags.partition_point(|tag| tag.offset <= offset);
+        self.tags.insert(
+            index,
+            Tag {
+                offset,
+                key: key.into(),
+                value,
+            },
+        );
+    }
+
+    /// Returns the tags at a given offset.
+    pub fn tags_at(&self, offset: usize) -> impl Iterator<Item = &Tag> {
+        let start = self.tags.partition_point(|tag| tag.offset < offset);
+        self.tags[start..]
+            .iter()
+            .take_while(move |tag| tag.offset == offset)
+    }
+
+    // Moves the start of the text by delta items. The tags that are left
+    // before the start of the text are removed.
+    pub(crate) fn advance(&mut self, delta: isize) {
+        self.sample_offset = self.sample_offset.wrapping_add_signed(delta as i64);
+        if let (Some(timestamp), Some(sample_rate)) = (self.timestamp, self.sample_rate) {
+            // the timestamp is left unchanged if the sample rate is not valid
+            if let Ok(elapsed) =
+                Duration::try_from_secs_f64(delta.unsigned_abs() as f64 / sample_rate)
+            {
+                self.timestamp = if delta >= 0 {
+                    timestamp.checked_add(elapsed)
+                } else {
+                    timestamp.checked_sub(elapsed)
+                };
+            }
+        }
+        let len = delta.unsigned_abs();
+        if delta >= 0 {
+            self.tags.retain(|tag| tag.offset >= len);
+            for tag in &mut self.tags {
+                tag.offset -= len;
+            }
+        } else {
+            for tag in &mut self.tags {
+                tag.offset += len;
+            }
+        }
+    }
+
+    // Removes the tags beyond the end of a text of length len.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        let end = self.tags.partition_point(|tag| tag.offset < len);
+        self.tags.truncate(end);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn advance() {
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let mut metadata = Metadata {
+            timestamp: Some(t0),
+            sample_rate: Some(1e3),
+            ..Metadata::new(100)
+        };
+        metadata.add_tag(20, "retune", TagValue::F64(1e9));
+        metadata.add_tag(5, "burst_start", TagValue::Empty);
+        metadata.add_tag(20, "overflow", TagValue::U64(3));
+        assert_eq!(metadata.tags[0].key, "burst_start");
+        assert_eq!(
+            metadata.tags_at(20).map(|tag| &tag.key).collect::<Vec<_>>(),
+            ["retune", "overflow"]
+        );
+
+        metadata.advance(10);
+        assert_eq!(metadata.sample_offset, 110);
+        assert_eq!(metadata.timestamp, Some(t0 + Duration::from_millis(10)));
+        assert_eq!(metadata.tags.len(), 2);
+        assert_eq!(metadata.tags[0].offset, 10);
+
+        metadata.advance(-4);
+        assert_eq!(metadata.sample_offset, 106);
+        assert_eq!(metadata.timestamp, Some(t0 + Duration::from_millis(6)));
+        assert_eq!(metadata.tags[0].offset, 14);
+
+        metadata.truncate(14);
+        assert!(metadata.tags.is_empty());
+    }
+
+    #[test]
+    fn invalid_sample_rate() {
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        for sample_rate in [0.0, -1e3, f64::NAN] {
+            let mut metadata = Metadata {
+                timestamp: Some(t0),
+                sample_rate: Some(sample_rate),
+                ..Metadata::new(100)
+            };
+            metadata.advance(10);
+            metadata.advance(0);
+            metadata.advance(-4);
+            assert_eq!(metadata.sample_offset, 106);
+            assert_eq!(metadata.timestamp, Some(t0));
+        }
+    }
+
+    #[test]
+    fn float_equality() {
+        let mut metadata = Metadata {
+            sample_rate: Some(f64::NAN),
+            ..Metadata::new(0)
+        };
+        metadata.add_tag(0, "gain", TagValue::F64(f64::NAN));
+        assert_eq!(metadata, metadata.clone());
+        assert_ne!(TagValue::F64(0.0), TagValue::F64(-0.0));
+        assert_ne!(TagValue::U64(1), TagValue::I64(1));
+    }
+}
@@ -0,0 +1,96 @@
+//! Plain-old-data items.
+
+use anyhow::Result;
+
+/// Plain-old-data type.
+///
+/// Slices of plain-old-data types can be reinterpreted as slices of other
+/// plain-old-data types, which is used by [`Quantum::view`](crate::Quantum::view)
+/// to give different element views of the same quantum without copying.
+///
+/// # Safety
+///
+/// The type must be `Copy`, have no padding bytes, and any bit pattern must be
+/// a valid value of the type.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(
+            unsafe impl Pod for $t {}
+        )*
+    };
+}
+
+impl_pod!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64
+);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+fn check_cast<T: Pod, U: Pod>(slice: &[T]) -> Result<usize> {
+    anyhow::ensure!(
+        size_of::<T>() != 0 && size_of::<U>() != 0,
+        "cannot reinterpret zero-sized types"
+    );
+    let size = size_of_val(slice);
+    anyhow::ensure!(
+        size.is_multiple_of(size_of::<U>()),
+        "slice of {size} bytes cannot be reinterpreted as items of {} bytes",
+        size_of::<U>()
+    );
+    anyhow::ensure!(
+        slice.as_ptr().cast::<U>().is_aligned(),
+        "slice is not aligned to {} bytes",
+        align_of::<U>()
+    );
+    Ok(size / size_of::<U>())
+}
+
+/// Reinterprets a slice as a slice of another plain-old-data type.
+///
+/// This fails if the size of the slice is not a multiple of the size of `U`,
+/// or if the slice is not aligned for `U`.
+pub fn cast_slice<T: Pod, U: Pod>(slice: &[T]) -> Result<&[U]> {
+    let len = check_cast::<T, U>(slice)?;
+    // SAFETY: the pointer is aligned and spans the same bytes as the slice,
+    // which are valid for U because it is Pod
+    Ok(unsafe { std::slice::from_raw_parts(slice.as_ptr().cast(), len) })
+}
+
+/// Reinterprets a mutable slice as a mutable slice of another plain-old-data
+/// type.
+///
+/// This fails if the size of the slice is not a multiple of the size of `U`,
+/// or if the slice is not aligned for `U`.
+pub fn cast_slice_mut<T: Pod, U: Pod>(slice: &mut [T]) -> Result<&mut [U]> {
+    let len = check_cast::<T, U>(slice)?;
+    // SAFETY: the pointer is aligned and spans the same bytes as the slice,
+    // which are valid for U because it is Pod, and any value written through
+    // the new slice is valid for T because it is also Pod
+    Ok(unsafe { std::slice::from_raw_parts_mut(slice.as_mut_ptr().cast(), len) })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn casts() {
+        let mut iq = [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let pairs = cast_slice_mut::<f32, [f32; 2]>(&mut iq).unwrap();
+        assert_eq!(pairs, [[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]]);
+        pairs[1] = [0.0, -1.0];
+        assert_eq!(iq, [1.0, 2.0, 0.0, -1.0, 5.0, 6.0]);
+
+        let words = [0x0102u16, 0x0304];
+        let bytes = cast_slice::<u16, u8>(&words).unwrap();
+        assert_eq!(bytes.len(), 4);
+        assert_eq!(cast_slice::<u8, u16>(bytes).unwrap(), words);
+
+        // size is not a multiple
+        assert!(cast_slice::<f32, [f32; 2]>(&iq[..5]).is_err());
+        // misaligned
+        assert!(cast_slice::<u8, u16>(&bytes[1..3]).is_err());
+    }
+}
@@ -3,7 +3,13 @@ use super::{
     flowgraph::{FlowgraphId, NodeId},
     quantum::Quantum,
 };
-use std::{any::type_name, cell::RefMut, cmp, fmt, hash, marker::PhantomData};
+use anyhow::{Context, Result};
+use std::{
+    any::{Any, TypeId, type_name},
+    cmp, fmt, hash,
+    marker::PhantomData,
+    sync::MutexGuard,
+};
 
 macro_rules! define_port_types {
     ($($ident:ident),*) => {
@@ -88,20 +94,23 @@ pub struct Endpoint<'a, P: Port> {
     flowgraph: FlowgraphId,
     node: NodeId,
     port: PortId,
-    seed: RefMut<'a, P::Seed>,
+    port_name: &'static str,
+    seed: MutexGuard<'a, P::Seed>,
 }
 
-impl<P: Port> Endpoint<'_, P> {
+impl<'a, P: Port> Endpoint<'a, P> {
     pub fn new(
         flowgraph: FlowgraphId,
         node: NodeId,
         port: PortId,
-        seed: RefMut<'_, <P as Port>::Seed>,
-    ) -> Endpoint<'_, P> {
+        port_name: &'static str,
+        seed: MutexGuard<'a, <P as Port>::Seed>,
+    ) -> Endpoint<'a, P> {
         Endpoint {
             flowgraph,
             node,
             port,
+            port_name,
             seed,
         }
     }
@@ -118,16 +127,209 @@ impl<P: Port> Endpoint<'_, P> {
         self.port
     }
 
+    pub fn port_name(&self) -> &'static str {
+        self.port_name
+    }
+
     pub fn seed(&mut self) -> &mut P::Seed {
         &mut self.seed
     }
 }
 
+/// Endpoint whose port type is only known at runtime.
+///
+/// It is obtained from the name of the port, and it is used to connect blocks
+/// described at runtime with [`Flowgraph::connect_erased`]. The port types are
+/// checked when connecting.
+///
+/// [`Flowgraph::connect_erased`]: crate::Flowgraph::connect_erased
+pub struct ErasedEndpoint<'a> {
+    flowgraph: FlowgraphId,
+    node: NodeId,
+    port: PortId,
+    port_name: &'static str,
+    kind: PortKind,
+    seed: Box<dyn AnySeed + 'a>,
+    connector: Option<ErasedConnector>,
+}
+
+impl<'a> ErasedEndpoint<'a> {
+    pub fn new<P: Port + 'static>(
+        flowgraph: FlowgraphId,
+        node: NodeId,
+        port: PortId,
+        port_name: &'static str,
+        seed: MutexGuard<'a, P::Seed>,
+    ) -> ErasedEndpoint<'a> {
+        ErasedEndpoint {
+            flowgraph,
+            node,
+            port,
+            port_name,
+            kind: P::KIND,
+            seed: Box::new(seed),
+            connector: P::erased_connector(),
+        }
+    }
+
+    pub fn flowgraph(&self) -> FlowgraphId {
+        self.flowgraph
+    }
+
+    pub fn node(&self) -> NodeId {
+        self.node
+    }
+
+    pub fn port(&self) -> PortId {
+        self.port
+    }
+
+    pub fn port_name(&self) -> &'static str {
+        self.port_name
+    }
+
+    pub fn kind(&self) -> PortKind {
+        self.kind
+    }
+
+    pub(crate) fn seed(&mut self) -> &mut dyn Any {
+        self.seed.as_any()
+    }
+
+    pub(crate) fn connector(&self) -> Option<ErasedConnector> {
+        self.connector
+    }
+}
+
+impl fmt::Debug for ErasedEndpoint<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErasedEndpoint")
+            .field("flowgraph", &self.flowgraph)
+            .field("node", &self.node)
+            .field("port", &self.port)
+            .field("port_name", &self.port_name)
+            .field("kind", &self.kind)
+            .finish_non_exhaustive()
+    }
+}
+
+trait AnySeed {
+    fn as_any(&mut self) -> &mut dyn Any;
+}
+
+impl<S: 'static> AnySeed for MutexGuard<'_, S> {
+    fn as_any(&mut self) -> &mut dyn Any {
+        &mut **self
+    }
+}
+
+// Connects the seed of a PortOut to the seeds of a destination and optionally
+// a return destination, which are only known at runtime. The messages are a
+// Vec of the items of the channel.
+pub(crate) type ErasedConnector = fn(
+    size: usize,
+    source: &mut dyn Any,
+    destination: &mut dyn Any,
+    return_destination: Option<&mut dyn Any>,
+    messages: Option<&mut dyn Any>,
+) -> Result<()>;
+
+fn erased_connect<T: 'static, C: Channel>(
+    size: usize,
+    source: &mut dyn Any,
+    destination: &mut dyn Any,
+    return_destination: Option<&mut dyn Any>,
+    messages: Option<&mut dyn Any>,
+) -> Result<()> {
+    let source = source
+        .downcast_mut::<C::SenderSeed<T>>()
+        .expect("source seed does not match the connector");
+    let destination = destination
+        .downcast_mut::<C::ReceiverSeed<T>>()
+        .with_context(|| {
+            format!(
+                "destination port type does not match source port type {}",
+                type_name::<PortOut<T, C>>()
+            )
+        })?;
+    let Some(return_destination) = return_destination else {
+        anyhow::ensure!(
+            TypeId::of::<C::ReturnReceiverSeed<T>>() == TypeId::of::<()>(),
+            "channel {} requires a return destination",
+            type_name::<C>()
+        );
+        return C::connect(
+            size,
+            source,
+            destination,
+            &mut Default::default(),
+            std::iter::empty(),
+        );
+    };
+    let return_destination = return_destination
+        .downcast_mut::<C::ReturnReceiverSeed<T>>()
+        .with_context(|| {
+            format!(
+                "return destination port type does not match source port type {}",
+                type_name::<PortOut<T, C>>()
+            )
+        })?;
+    let messages = match messages {
+        Some(messages) => {
+            std::mem::take(messages.downcast_mut::<Vec<T>>().with_context(|| {
+                format!("circuit messages are not of type {}", type_name::<T>())
+            })?)
+        }
+        None => Vec::new(),
+    };
+    C::connect(
+        size,
+        source,
+        destination,
+        return_destination,
+        messages.into_iter(),
+    )
+}
+
 pub trait Port: Default {
     type Channel;
     type Seed;
     type ItemType;
     type ChannelType: Channel;
+    const KIND: PortKind;
+    const BLOCKING: bool = <Self::ChannelType as Channel>::BLOCKING;
+
+    // Used to connect output ports with ErasedEndpoint.
+    #[doc(hidden)]
+    fn erased_connector() -> Option<ErasedConnector>
+    where
+        Self: 'static,
+    {
+        None
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PortKind {
+    Out,
+    In,
+    RefIn,
+    Source,
+}
+
+impl PortKind {
+    pub fn is_input(&self) -> bool {
+        !matches!(self, PortKind::Out)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PortInfo {
+    pub name: &'static str,
+    pub kind: PortKind,
+    /// Whether the channel of the port is a blocking channel (see
+    /// [`Channel::BLOCKING`]).
+    pub blocking: bool,
 }
 
 impl<T, C: Channel> Port for PortOut<T, C> {
@@ -135,6 +337,14 @@ impl<T, C: Channel> Port for PortOut<T, C> {
     type Seed = C::SenderSeed<T>;
     type ItemType = T;
     type ChannelType = C;
+    const KIND: PortKind = PortKind::Out;
+
+    fn erased_connector() -> Option<ErasedConnector>
+    where
+        Self: 'static,
+    {
+        Some(erased_connect::<T, C>)
+    }
 }
 
 impl<T, C> Port for PortIn<T, C>
@@ -146,6 +356,7 @@ where
     type Seed = C::ReceiverSeed<T>;
     type ItemType = T;
     type ChannelType = C;
+    const KIND: PortKind = PortKind::In;
 }
 
 impl<T, C: Channel> Port for PortRefIn<T, C> {
@@ -153,6 +364,7 @@ impl<T, C: Channel> Port for PortRefIn<T, C> {
     type Seed = C::ReceiverSeed<T>;
     type ItemType = T;
     type ChannelType = C;
+    const KIND: PortKind = PortKind::RefIn;
 }
 
 impl<T, C> Port for PortSource<T, C>
@@ -164,11 +376,18 @@ where
     type Seed = C::ReceiverSeed<T>;
     type ItemType = T;
     type ChannelType = C;
+    const KIND: PortKind = PortKind::Source;
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct PortId(u32);
 
+impl PortId {
+    pub(crate) fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
 impl From<u32> for PortId {
     fn from(value: u32) -> PortId {
         PortId(value)
@@ -1,17 +1,50 @@
-use super::{buffer::Buffer, sheet::Sheet};
+use super::{
+    buffer::Buffer,
+    metadata::Metadata,
+    pod::{Pod, cast_slice, cast_slice_mut},
+    sheet::Sheet,
+};
+use anyhow::Result;
 
 #[derive(Debug)]
 pub struct Quantum<B: Buffer> {
     sheet: Sheet<B>,
+    // boxed so that quanta without metadata stay small
+    metadata: Option<Box<Metadata>>,
 }
 
 impl<B: Buffer> Quantum<B> {
     pub fn new(buffer: B) -> Quantum<B> {
         Quantum {
             sheet: Sheet::new(buffer),
+            metadata: None,
         }
     }
 
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_deref()
+    }
+
+    pub fn metadata_mut(&mut self) -> Option<&mut Metadata> {
+        self.metadata.as_deref_mut()
+    }
+
+    /// Sets or clears the metadata of the quantum.
+    ///
+    /// Quanta are reused, so a block that produces quanta should set their
+    /// metadata each time, to avoid passing stale metadata downstream.
+    pub fn set_metadata(&mut self, metadata: Option<Metadata>) {
+        match (&mut self.metadata, metadata) {
+            // reuse the allocation
+            (Some(current), Some(metadata)) => **current = metadata,
+            (current, metadata) => *current = metadata.map(Box::new),
+        }
+    }
+
+    pub fn take_metadata(&mut self) -> Option<Metadata> {
+        self.metadata.take().map(|metadata| *metadata)
+    }
+
     pub fn as_slice(&self) -> &[B::Item] {
         &self.sheet
     }
@@ -29,7 +62,12 @@ impl<B: Buffer> Quantum<B> {
     }
 
     pub fn set_margins(&mut self, left_margin_len: usize, right_margin_len: usize) {
+        let previous_left_margin_len = self.left_margin_len();
         self.sheet.set_margins(left_margin_len, right_margin_len);
+        if let Some(metadata) = &mut self.metadata {
+            metadata.advance(left_margin_len as isize - previous_left_margin_len as isize);
+            metadata.truncate(self.sheet.len());
+        }
     }
 
     pub fn len(&self) -> usize {
@@ -42,6 +80,9 @@ impl<B: Buffer> Quantum<B> {
 
     pub fn extend_left(&mut self, len: usize) {
         self.sheet.extend_left(len);
+        if let Some(metadata) = &mut self.metadata {
+            metadata.advance(-(len as isize));
+        }
     }
 
     pub fn extend_right(&mut self, len: usize) {
@@ -50,10 +91,43 @@ impl<B: Buffer> Quantum<B> {
 
     pub fn shrink_left(&mut self, len: usize) {
         self.sheet.shrink_left(len);
+        if let Some(metadata) = &mut self.metadata {
+            metadata.advance(len as isize);
+        }
     }
 
     pub fn shrink_right(&mut self, len: usize) {
         self.sheet.shrink_right(len);
+        if let Some(metadata) = &mut self.metadata {
+            metadata.truncate(self.sheet.len());
+        }
+    }
+
+    pub(crate) fn buffer(&self) -> &B {
+        self.sheet.buffer()
+    }
+}
+
+impl<B> Quantum<B>
+where
+    B: Buffer,
+    B::Item: Pod,
+{
+    /// Returns a view of the text as a slice of another plain-old-data type.
+    ///
+    /// For instance, a text of interleaved I/Q `f32` samples can be viewed as
+    /// `[f32; 2]` complex samples. This fails if the size of the text is not a
+    /// multiple of the size of `U`, or if the text is not aligned for `U`.
+    pub fn view<U: Pod>(&self) -> Result<&[U]> {
+        cast_slice(self.as_slice())
+    }
+
+    /// Returns a mutable view of the text as a slice of another plain-old-data
+    /// type.
+    ///
+    /// See [`Quantum::view`].
+    pub fn view_mut<U: Pod>(&mut self) -> Result<&mut [U]> {
+        cast_slice_mut(self.as_mut_slice())
     }
 }
 
@@ -65,6 +139,7 @@ where
     pub fn snapshot(&self) -> QuantumSnapshot<B::Item> {
         QuantumSnapshot {
             slice: Box::from(self.as_slice()),
+            metadata: self.metadata().cloned(),
         }
     }
 }
@@ -72,6 +147,7 @@ where
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct QuantumSnapshot<T> {
     slice: Box<[T]>,
+    metadata: Option<Metadata>,
 }
 
 impl<T> QuantumSnapshot<T> {
@@ -82,12 +158,34 @@ impl<T> QuantumSnapshot<T> {
     pub fn as_mut_slice(&mut self) -> &mut [T] {
         &mut self.slice
     }
+
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    pub fn metadata_mut(&mut self) -> Option<&mut Metadata> {
+        self.metadata.as_mut()
+    }
+
+    pub fn set_metadata(&mut self, metadata: Option<Metadata>) {
+        self.metadata = metadata;
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> QuantumSnapshot<T> {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn take_metadata(&mut self) -> Option<Metadata> {
+        self.metadata.take()
+    }
 }
 
 impl<T: Clone> From<&[T]> for QuantumSnapshot<T> {
     fn from(slice: &[T]) -> QuantumSnapshot<T> {
         QuantumSnapshot {
             slice: Box::from(slice),
+            metadata: None,
         }
     }
 }
@@ -96,6 +194,52 @@ impl<T> From<Vec<T>> for QuantumSnapshot<T> {
     fn from(vec: Vec<T>) -> QuantumSnapshot<T> {
         QuantumSnapshot {
             slice: vec.into_boxed_slice(),
+            metadata: None,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{buffers::CacheAlignedBuffer, runtime::metadata::TagValue};
+
+    #[test]
+    fn metadata_follows_margins() {
+        let mut quantum = Quantum::new(CacheAlignedBuffer::<u32>::new(64));
+        let mut metadata = Metadata::new(1000);
+        metadata.add_tag(2, "a", TagValue::Empty);
+        metadata.add_tag(40, "b", TagValue::Empty);
+        quantum.set_metadata(Some(metadata));
+
+        quantum.set_margins(4, 16);
+        let metadata = quantum.metadata().unwrap();
+        assert_eq!(metadata.sample_offset, 1004);
+        assert_eq!(metadata.tags.len(), 1);
+        assert_eq!(metadata.tags[0].offset, 36);
+
+        quantum.extend_left(4);
+        assert_eq!(quantum.metadata().unwrap().sample_offset, 1000);
+        assert_eq!(quantum.metadata().unwrap().tags[0].offset, 40);
+        quantum.shrink_right(10);
+        assert!(quantum.metadata().unwrap().tags.is_empty());
+
+        assert_eq!(quantum.snapshot().metadata(), quantum.metadata());
+        assert!(quantum.take_metadata().is_some());
+        assert!(quantum.snapshot().metadata().is_none());
+    }
+
+    #[test]
+    fn views() {
+        let mut quantum = Quantum::new(CacheAlignedBuffer::<f32>::from_fn(8, |n| n as f32));
+        let iq = quantum.view_mut::<[f32; 2]>().unwrap();
+        assert_eq!(iq[1], [2.0, 3.0]);
+        iq[1] = [0.0, 0.0];
+        assert_eq!(quantum.as_slice()[2..4], [0.0, 0.0]);
+        assert_eq!(quantum.view::<u8>().unwrap().len(), 32);
+
+        quantum.set_margins(1, 0);
+        assert!(quantum.view::<[f32; 2]>().is_err());
+        assert!(quantum.view::<[f32; 7]>().is_ok());
+    }
+}
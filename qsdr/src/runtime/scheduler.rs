@@ -1,12 +1,30 @@
 use anyhow::Result;
 use futures::{TryStream, TryStreamExt};
 
+mod block_threads;
+pub use block_threads::BlockThreads;
+
+mod cpu_groups;
+pub use cpu_groups::{CpuGroups, pin_current_thread};
+
+pub use crate::runtime::error::ErrorPolicy;
+
+mod executor;
+pub use executor::{Executor, FuturesExecutor};
+
 mod sequence;
 pub use sequence::{
     Sequence2, Sequence3, Sequence4, Sequence5, Sequence6, Sequence7, Sequence8, sequence2,
     sequence3, sequence4, sequence5, sequence6, sequence7, sequence8,
 };
 
+mod sequence_vec;
+pub use sequence_vec::{SequenceN, sequence_vec};
+
+mod stop;
+pub use stop::StopHandle;
+pub(crate) use stop::Stoppable;
+
 pub async fn run<S: TryStream<Ok = ()>>(stream: S) -> Result<(), <S as TryStream>::Error> {
     stream.try_collect::<()>().await
 }
@@ -0,0 +1,37 @@
+use crate::runtime::{
+    block::Block,
+    flowgraph::{AssignedNode, FlowgraphNode},
+};
+
+/// Blocks of a flowgraph that run on their own thread.
+///
+/// See
+/// [`ValidatedFlowgraph::run_blocking`](crate::ValidatedFlowgraph::run_blocking).
+#[derive(Debug, Clone, Default)]
+pub struct BlockThreads {
+    nodes: Vec<AssignedNode>,
+}
+
+impl BlockThreads {
+    pub fn new() -> BlockThreads {
+        BlockThreads::default()
+    }
+
+    /// Adds a block, which will run on its own thread.
+    ///
+    /// The block is sent to its thread, so it must be `Send`.
+    pub fn add<N>(&mut self, node: &N) -> &mut BlockThreads
+    where
+        N: FlowgraphNode,
+        N::B: Send + 'static,
+        <N::B as Block>::Channels: Send,
+        <N::B as Block>::Seeds: Send + Sync,
+    {
+        self.nodes.push(AssignedNode::new(node));
+        self
+    }
+
+    pub(crate) fn nodes(&self) -> &[AssignedNode] {
+        &self.nodes
+    }
+}
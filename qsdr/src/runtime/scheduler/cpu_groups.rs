@@ -0,0 +1,73 @@
+use crate::runtime::{
+    block::Block,
+    flowgraph::{AssignedNode, FlowgraphNode},
+};
+use anyhow::{Context, Result};
+
+/// Assignment of the blocks of a flowgraph to CPUs.
+///
+/// Each CPU runs in its own thread, pinned to that CPU, and the blocks assigned
+/// to it are run with a sequence scheduler in the order in which they were
+/// added. See
+/// [`ValidatedFlowgraph::run_multi_core`](crate::ValidatedFlowgraph::run_multi_core).
+#[derive(Debug, Clone, Default)]
+pub struct CpuGroups {
+    groups: Vec<CpuGroup>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CpuGroup {
+    pub(crate) cpu: usize,
+    pub(crate) nodes: Vec<AssignedNode>,
+}
+
+impl CpuGroups {
+    pub fn new() -> CpuGroups {
+        CpuGroups::default()
+    }
+
+    /// Assigns a block to a CPU.
+    ///
+    /// The block is sent to the thread of the CPU, so it must be `Send`.
+    pub fn add<N>(&mut self, cpu: usize, node: &N) -> &mut CpuGroups
+    where
+        N: FlowgraphNode,
+        N::B: Send + 'static,
+        <N::B as Block>::Channels: Send,
+        <N::B as Block>::Seeds: Send + Sync,
+    {
+        let node = AssignedNode::new(node);
+        match self.groups.iter_mut().find(|group| group.cpu == cpu) {
+            Some(group) => group.nodes.push(node),
+            None => self.groups.push(CpuGroup {
+                cpu,
+                nodes: vec![node],
+            }),
+        }
+        self
+    }
+
+    pub(crate) fn groups(&self) -> &[CpuGroup] {
+        &self.groups
+    }
+}
+
+/// Pins the current thread to a CPU.
+pub fn pin_current_thread(cpu: usize) -> Result<()> {
+    anyhow::ensure!(
+        cpu < libc::CPU_SETSIZE as usize,
+        "CPU {cpu} is out of range"
+    );
+    // SAFETY: cpu_set_t is a plain bitmask, for which all zeros is valid, and
+    // cpu has been checked to fit in the set.
+    let ret = unsafe {
+        let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("could not pin to CPU {cpu}"));
+    }
+    Ok(())
+}
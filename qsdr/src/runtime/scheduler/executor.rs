@@ -0,0 +1,16 @@
+use std::future::Future;
+
+/// Executor that runs a future to completion on the current thread.
+pub trait Executor {
+    fn block_on<F: Future>(&self, future: F) -> F::Output;
+}
+
+/// Executor that uses [`futures::executor::block_on`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct FuturesExecutor {}
+
+impl Executor for FuturesExecutor {
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        futures::executor::block_on(future)
+    }
+}
@@ -0,0 +1,142 @@
+use futures::stream::{FusedStream, Stream, StreamExt};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Dynamically sized version of [`Sequence2`](super::Sequence2), etc.
+///
+/// The streams are typically boxed as `Pin<Box<dyn FusedStream<Item =
+/// Result<()>>>>` so that streams of different types can be run together.
+///
+/// The termination rule is different from the fixed-arity sequences. These
+/// finish after a poll in which none of the streams yields an item and not all
+/// of them are pending, for instance when a stream finishes while the rest are
+/// pending. `SequenceN` only finishes when all its streams have finished, so
+/// that a stream that is waiting for items is run until it is done.
+#[derive(Debug)]
+pub struct SequenceN<S> {
+    streams: Vec<S>,
+    done: bool,
+}
+
+pub fn sequence_vec<S: FusedStream + Unpin>(streams: Vec<S>) -> SequenceN<S> {
+    let done = streams.iter().all(|stream| stream.is_terminated());
+    SequenceN { streams, done }
+}
+
+impl<E, S> Stream for SequenceN<S>
+where
+    S: Stream<Item = Result<(), E>> + FusedStream + Unpin,
+{
+    type Item = Result<(), E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        // Only return pending when all the streams return pending. Otherwise
+        // one of the streams might be able to be polled immediately again.
+        let mut all_pending = true;
+        for stream in self.streams.iter_mut() {
+            if !stream.is_terminated() {
+                match stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok(()))) | Poll::Ready(None) => all_pending = false,
+                    Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                    Poll::Pending => {}
+                }
+            }
+        }
+        // The sequence is done only when all the streams are done, since a
+        // stream that finishes while the others are pending does not mean that
+        // the others cannot make progress later.
+        if self.streams.iter().all(|stream| stream.is_terminated()) {
+            self.done = true;
+            return Poll::Ready(None);
+        }
+        if all_pending {
+            return Poll::Pending;
+        }
+        Poll::Ready(Some(Ok(())))
+    }
+}
+
+impl<E, S> FusedStream for SequenceN<S>
+where
+    S: Stream<Item = Result<(), E>> + FusedStream + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scheduler::run;
+    use futures::executor::block_on;
+    use std::{cell::Cell, rc::Rc};
+
+    type BoxedStream<E> = Pin<Box<dyn FusedStream<Item = Result<(), E>>>>;
+
+    #[test]
+    fn runs_all_streams() {
+        let count = Rc::new(Cell::new(0));
+        let streams = (0..12)
+            .map(|n| {
+                let count = Rc::clone(&count);
+                Box::pin(
+                    futures::stream::iter(0..n)
+                        .map(move |_| {
+                            count.set(count.get() + 1);
+                            Ok::<(), ()>(())
+                        })
+                        .fuse(),
+                ) as BoxedStream<()>
+            })
+            .collect();
+        block_on(run(sequence_vec(streams))).unwrap();
+        assert_eq!(count.get(), (0..12).sum::<usize>());
+    }
+
+    #[test]
+    fn returns_error() {
+        let streams: Vec<BoxedStream<i32>> = vec![
+            Box::pin(futures::stream::repeat(Ok(()))),
+            Box::pin(futures::stream::iter([Ok(()), Err(42)]).fuse()),
+        ];
+        assert_eq!(block_on(run(sequence_vec(streams))), Err(42));
+    }
+
+    #[test]
+    fn empty() {
+        let streams: Vec<BoxedStream<()>> = Vec::new();
+        assert_eq!(block_on(run(sequence_vec(streams))), Ok(()));
+    }
+
+    #[test]
+    fn waits_for_pending_streams() {
+        // stream that is pending on its first poll and then yields once
+        let polled = Rc::new(Cell::new(false));
+        let finished = Rc::new(Cell::new(false));
+        let pending = {
+            let finished = Rc::clone(&finished);
+            futures::stream::poll_fn(move |cx| {
+                if !polled.replace(true) {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                if finished.replace(true) {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Ready(Some(Ok(())))
+                }
+            })
+            .fuse()
+        };
+        let streams: Vec<BoxedStream<()>> =
+            vec![Box::pin(futures::stream::empty().fuse()), Box::pin(pending)];
+        assert_eq!(block_on(run(sequence_vec(streams))), Ok(()));
+        assert!(finished.get());
+    }
+}
@@ -0,0 +1,198 @@
+use futures::{
+    stream::{FusedStream, Stream},
+    task::AtomicWaker,
+};
+use std::{
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, Waker},
+};
+
+/// Handle to stop a running flowgraph.
+///
+/// Calling [`stop`](StopHandle::stop) makes the source blocks of the flowgraph
+/// (the blocks without input ports) stop producing. The quanta that they have
+/// already produced continue flowing through the flowgraph, and the remaining
+/// blocks finish once their inputs have been drained, so the flowgraph
+/// terminates cleanly.
+///
+/// The handle can be cloned and sent to other threads. Stopping only involves
+/// setting an atomic flag and waking up the source blocks, so it can also be
+/// done from a signal handler thread.
+#[derive(Debug, Clone)]
+pub struct StopHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    stopped: AtomicBool,
+    aborted: AtomicBool,
+    // number of times that the wakers have been woken up
+    wakeups: AtomicUsize,
+    // one per flowgraph node, indexed by NodeId
+    wakers: Box<[AtomicWaker]>,
+}
+
+impl StopHandle {
+    pub(crate) fn new(num_nodes: usize) -> StopHandle {
+        StopHandle {
+            inner: Arc::new(Inner {
+                stopped: AtomicBool::new(false),
+                aborted: AtomicBool::new(false),
+                wakeups: AtomicUsize::new(0),
+                wakers: std::iter::repeat_with(AtomicWaker::new)
+                    .take(num_nodes)
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Requests the flowgraph to stop.
+    pub fn stop(&self) {
+        self.inner.stopped.store(true, Ordering::SeqCst);
+        self.wake_all();
+    }
+
+    /// Returns `true` if the flowgraph has been requested to stop.
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::SeqCst)
+    }
+
+    // Makes all the blocks stop running immediately.
+    pub(crate) fn abort(&self) {
+        self.inner.aborted.store(true, Ordering::SeqCst);
+        self.wake_all();
+    }
+
+    pub(crate) fn is_aborted(&self) -> bool {
+        self.inner.aborted.load(Ordering::SeqCst)
+    }
+
+    fn wake_all(&self) {
+        self.inner.wakeups.fetch_add(1, Ordering::SeqCst);
+        for waker in &self.inner.wakers {
+            waker.wake();
+        }
+    }
+
+    // Wraps the stream of a block so that it terminates when the flowgraph is
+    // aborted, or when it is stopped if the block is a source. The stream is
+    // dropped when terminating, which drops the channels of the block and
+    // lets the downstream blocks finish.
+    pub(crate) fn stoppable<S>(&self, node: usize, is_source: bool, stream: S) -> Stoppable<S> {
+        Stoppable {
+            handle: self.clone(),
+            node,
+            is_source,
+            stream: Some(stream),
+            registered: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Stoppable<S> {
+    handle: StopHandle,
+    node: usize,
+    is_source: bool,
+    stream: Option<S>,
+    // waker registered in the StopHandle and number of wakeups when it was
+    // registered
+    registered: Option<(Waker, usize)>,
+}
+
+impl<S> Stoppable<S> {
+    // Terminates the stream, dropping the stream of the block.
+    pub(crate) fn terminate(&mut self) {
+        self.stream = None;
+    }
+
+    // Registers the waker of the task, so that it is woken up when stopping or
+    // aborting. Registering is skipped when the waker is the same as in the
+    // previous poll and it has not been consumed by a wakeup since then.
+    fn register(&mut self, waker: &Waker) {
+        let inner = &self.handle.inner;
+        let wakeups = inner.wakeups.load(Ordering::SeqCst);
+        if let Some((registered, registered_wakeups)) = &self.registered
+            && *registered_wakeups == wakeups
+            && registered.will_wake(waker)
+        {
+            return;
+        }
+        inner.wakers[self.node].register(waker);
+        self.registered = Some((waker.clone(), wakeups));
+    }
+}
+
+impl<S: Stream + Unpin> Stream for Stoppable<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let self_ = self.get_mut();
+        if self_.stream.is_none() {
+            return Poll::Ready(None);
+        }
+        // Register the waker before checking the flags so that a concurrent
+        // stop or abort cannot be missed.
+        self_.register(cx.waker());
+        let handle = &self_.handle;
+        if handle.is_aborted() || (self_.is_source && handle.is_stopped()) {
+            self_.terminate();
+            return Poll::Ready(None);
+        }
+        let stream = self_.stream.as_mut().unwrap();
+        let poll = Pin::new(stream).poll_next(cx);
+        if let Poll::Ready(None) = poll {
+            self_.terminate();
+        }
+        poll
+    }
+}
+
+impl<S: Stream + Unpin> FusedStream for Stoppable<S> {
+    fn is_terminated(&self) -> bool {
+        self.stream.is_none()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::task::{ArcWake, waker};
+
+    #[derive(Default)]
+    struct CountWakes(AtomicUsize);
+
+    impl ArcWake for CountWakes {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn wakes_after_previous_wakeup() {
+        let handle = StopHandle::new(1);
+        let count = Arc::new(CountWakes::default());
+        let waker = waker(Arc::clone(&count));
+        let mut cx = Context::from_waker(&waker);
+        let mut stream = handle.stoppable(0, false, futures::stream::pending::<()>());
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+
+        // stopping wakes up the block, but it only terminates sources
+        handle.stop();
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_pending());
+
+        // the waker has been registered again, since the previous wakeup
+        // consumed it
+        handle.abort();
+        assert_eq!(count.0.load(Ordering::SeqCst), 2);
+        assert!(Pin::new(&mut stream).poll_next(&mut cx).is_ready());
+        assert!(stream.is_terminated());
+    }
+}
@@ -0,0 +1,204 @@
+//! Selection over several input ports.
+//!
+//! A block with several inputs (for instance, a block that merges the quanta
+//! of two circuits, or a block with a data input and a control input) can
+//! use [`Select`] in its [`WorkCustom`](crate::WorkCustom) implementation to
+//! wait for whichever input is ready first, instead of awaiting the inputs
+//! one at a time.
+
+use super::channel::RefReceiver;
+use std::{
+    future::{Future, poll_fn},
+    pin::pin,
+    task::Poll,
+};
+
+/// Fair selection over several receivers.
+///
+/// The `select` methods wait until one of the receivers has an item and return
+/// this item, tagged with the index of the receiver. When several receivers
+/// have items, they are selected in round-robin order, so that a busy
+/// receiver cannot starve the others. For this, the `Select` remembers which
+/// receiver was selected last, so the same `Select` should be used each time
+/// that the block selects over the same receivers.
+///
+/// The receivers that are disconnected are skipped. The `select` methods
+/// return `None` only when all the receivers are disconnected.
+///
+/// The receivers are polled concurrently, and the futures of the receivers
+/// that are not selected are dropped. The channels in
+/// [`channels`](crate::channels) are designed so that this does not lose
+/// items, except the futex channels, which block the thread when they are
+/// polled. Selecting over a futex channel gives a compile-time error.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct Select {
+    next: usize,
+}
+
+impl Select {
+    pub fn new() -> Select {
+        Select::default()
+    }
+}
+
+macro_rules! generate {
+    ($Selected:ident, $select:ident, $num:expr,
+     $(($Input:ident, $n:expr, $A:ident, $T:ident, $R:ident, $rx:ident, $fut:ident, $done:ident)),*) => {
+        #[doc = concat!("Item selected by [`Select::", stringify!($select), "`].")]
+        #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+        pub enum $Selected<$($A),*> {
+            $($Input($A)),*
+        }
+
+        impl Select {
+            #[doc = concat!("Selects over ", stringify!($num), " receivers.")]
+            pub fn $select<'a, $($T, $R: RefReceiver<$T>),*>(
+                &'a mut self,
+                $($rx: &'a mut $R),*
+            ) -> impl Future<Output = Option<$Selected<$($R::Ref<'a>),*>>> {
+                $(
+                    const {
+                        assert!(
+                            !<$R as RefReceiver<$T>>::BLOCKING,
+                            "blocking receivers cannot be selected over"
+                        )
+                    };
+                )*
+                let start = self.next;
+                let next = &mut self.next;
+                async move {
+                    $(
+                        let mut $fut = pin!($rx.ref_recv());
+                        let mut $done = false;
+                    )*
+                    poll_fn(|cx| {
+                        let mut all_done = true;
+                        for k in 0..$num {
+                            let n = (start + k) % $num;
+                            $(
+                                if n == $n && !$done {
+                                    match $fut.as_mut().poll(cx) {
+                                        Poll::Ready(Some(value)) => {
+                                            *next = (n + 1) % $num;
+                                            return Poll::Ready(Some($Selected::$Input(value)));
+                                        }
+                                        Poll::Ready(None) => $done = true,
+                                        Poll::Pending => all_done = false,
+                                    }
+                                }
+                            )*
+                        }
+                        if all_done {
+                            Poll::Ready(None)
+                        } else {
+                            Poll::Pending
+                        }
+                    })
+                    .await
+                }
+            }
+        }
+    };
+}
+
+generate!(
+    Selected2,
+    select2,
+    2,
+    (Input0, 0, A0, T0, R0, rx0, fut0, done0),
+    (Input1, 1, A1, T1, R1, rx1, fut1, done1)
+);
+generate!(
+    Selected3,
+    select3,
+    3,
+    (Input0, 0, A0, T0, R0, rx0, fut0, done0),
+    (Input1, 1, A1, T1, R1, rx1, fut1, done1),
+    (Input2, 2, A2, T2, R2, rx2, fut2, done2)
+);
+generate!(
+    Selected4,
+    select4,
+    4,
+    (Input0, 0, A0, T0, R0, rx0, fut0, done0),
+    (Input1, 1, A1, T1, R1, rx1, fut1, done1),
+    (Input2, 2, A2, T2, R2, rx2, fut2, done2),
+    (Input3, 3, A3, T3, R3, rx3, fut3, done3)
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::channel::spsc::futures::channel;
+    use futures::executor::block_on;
+
+    #[test]
+    fn fairness_and_disconnection() {
+        let (mut tx0, mut rx0) = channel::<u32>(16);
+        let (mut tx1, mut rx1) = channel::<u64>(16);
+        for n in 0..4 {
+            tx0.send(n);
+            tx1.send(n.into());
+        }
+        drop(tx1);
+        let mut select = Select::new();
+        let mut received = Vec::new();
+        block_on(async {
+            while let Some(selected) = select.select2(&mut rx0, &mut rx1).await {
+                received.push(selected);
+                if received.len() == 8 {
+                    tx0.send(4);
+                    tx0.send(5);
+                }
+                if received.len() == 10 {
+                    break;
+                }
+            }
+        });
+        let expected = vec![
+            Selected2::Input0(0),
+            Selected2::Input1(0),
+            Selected2::Input0(1),
+            Selected2::Input1(1),
+            Selected2::Input0(2),
+            Selected2::Input1(2),
+            Selected2::Input0(3),
+            Selected2::Input1(3),
+            // input 1 is disconnected
+            Selected2::Input0(4),
+            Selected2::Input0(5),
+        ];
+        assert_eq!(received, expected);
+        drop(tx0);
+        assert_eq!(block_on(select.select2(&mut rx0, &mut rx1)), None);
+    }
+
+    #[test]
+    fn wakes_up() {
+        let (mut tx0, mut rx0) = channel::<u32>(16);
+        let (mut tx1, mut rx1) = channel::<u32>(16);
+        let (mut tx2, mut rx2) = channel::<u32>(16);
+        let sender_thread = std::thread::spawn(move || {
+            for n in 0..100 {
+                std::thread::sleep(std::time::Duration::from_micros(100));
+                match n % 3 {
+                    0 => tx2.send(n),
+                    1 => tx0.send(n),
+                    _ => tx1.send(n),
+                }
+            }
+        });
+        let mut select = Select::new();
+        let received = block_on(async {
+            let mut received = Vec::new();
+            while let Some(selected) = select.select3(&mut rx0, &mut rx1, &mut rx2).await {
+                received.push(match selected {
+                    Selected3::Input0(n) | Selected3::Input1(n) | Selected3::Input2(n) => n,
+                });
+            }
+            received
+        });
+        sender_thread.join().unwrap();
+        assert_eq!(received, (0..100).collect::<Vec<_>>());
+    }
+}
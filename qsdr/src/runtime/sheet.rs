@@ -48,6 +48,10 @@ impl<B: Buffer> Sheet<B> {
         }
     }
 
+    pub fn buffer(&self) -> &B {
+        &self.buffer
+    }
+
     pub fn left_margin_len(&self) -> usize {
         unsafe { self.text.as_ptr().offset_from(self.buffer.as_mut_ptr()) as usize }
     }
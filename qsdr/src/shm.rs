@@ -0,0 +1,303 @@
+//! Shared memory segments.
+//!
+//! A [`Segment`] is a region of memory that can be mapped by several
+//! processes. Channels (see [`spsc::shm`](crate::channel::spsc::shm) and
+//! [`mpsc::shm`](crate::channel::mpsc::shm)) and buffers (see
+//! [`ShmBuffer`](crate::buffers::ShmBuffer)) can be allocated in a segment, so
+//! that a circuit can cross a process boundary. The quanta stay in the
+//! segment, and only their indices (or offsets) are sent through the
+//! channels.
+//!
+//! Each process refers to the objects in the segment by their offset from the
+//! start of the segment, since the segment is mapped at a different address in
+//! each process.
+
+use anyhow::{Context, Result};
+use std::{
+    alloc::Layout,
+    ffi::CString,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+    ptr::NonNull,
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+};
+
+const MAGIC: u64 = u64::from_le_bytes(*b"qsdrshm1");
+
+// Header placed at the beginning of the segment.
+#[repr(C, align(64))]
+struct Header {
+    magic: u64,
+    len: u64,
+    // offset of the first byte not allocated yet
+    next: AtomicU64,
+}
+
+/// Shared memory segment.
+///
+/// The segment is backed by a memfd or by a named POSIX shared memory object,
+/// and it is mapped in the address space of the process. Other processes can
+/// map the same segment by obtaining its file descriptor (for instance, by
+/// inheriting it or by receiving it through a Unix socket) or by opening it by
+/// name.
+///
+/// Memory is allocated in the segment with a bump allocator that is shared by
+/// all the processes. Allocations are never freed, so the segment should be
+/// sized for all the objects that it will contain.
+#[derive(Debug)]
+pub struct Segment {
+    ptr: NonNull<u8>,
+    len: usize,
+    fd: OwnedFd,
+}
+
+unsafe impl Send for Segment {}
+unsafe impl Sync for Segment {}
+
+impl Segment {
+    /// Creates a segment of `len` bytes backed by a memfd.
+    ///
+    /// The name is only used for debugging purposes.
+    pub fn new(name: &str, len: usize) -> Result<Segment> {
+        let c_name = CString::new(name).context("invalid segment name")?;
+        let fd = unsafe { libc::memfd_create(c_name.as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("could not create memfd {name}"));
+        }
+        // SAFETY: fd is a file descriptor that we own
+        Segment::init(unsafe { OwnedFd::from_raw_fd(fd) }, len)
+    }
+
+    /// Creates a segment of `len` bytes backed by a named POSIX shared memory
+    /// object.
+    ///
+    /// The name should start with a `/`. It is an error if an object with
+    /// this name already exists. The object should be removed with
+    /// [`unlink`](Segment::unlink) once all the processes have opened it.
+    pub fn create_named(name: &str, len: usize) -> Result<Segment> {
+        let fd = shm_open(name, libc::O_CREAT | libc::O_EXCL | libc::O_RDWR)?;
+        Segment::init(fd, len)
+    }
+
+    /// Opens a segment that has been created with
+    /// [`create_named`](Segment::create_named).
+    pub fn open_named(name: &str) -> Result<Segment> {
+        Segment::from_fd(shm_open(name, libc::O_RDWR)?)
+    }
+
+    /// Removes the name of a segment created with
+    /// [`create_named`](Segment::create_named).
+    ///
+    /// The segment stays alive until all the processes unmap it.
+    pub fn unlink(name: &str) -> Result<()> {
+        let c_name = CString::new(name).context("invalid segment name")?;
+        if unsafe { libc::shm_unlink(c_name.as_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("could not unlink shared memory object {name}"));
+        }
+        Ok(())
+    }
+
+    /// Maps a segment given its file descriptor.
+    ///
+    /// The file descriptor should belong to a segment created by
+    /// [`new`](Segment::new) or [`create_named`](Segment::create_named),
+    /// possibly in another process.
+    pub fn from_fd(fd: OwnedFd) -> Result<Segment> {
+        let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+        if unsafe { libc::fstat(fd.as_raw_fd(), stat.as_mut_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("could not stat segment");
+        }
+        // SAFETY: fstat has succeeded, so it has initialized stat
+        let len = usize::try_from(unsafe { stat.assume_init() }.st_size)?;
+        anyhow::ensure!(
+            len >= size_of::<Header>(),
+            "segment is too small ({len} bytes)"
+        );
+        let segment = Segment {
+            ptr: map(&fd, len)?,
+            len,
+            fd,
+        };
+        let header = segment.header();
+        anyhow::ensure!(
+            header.magic == MAGIC && header.len == len as u64,
+            "file descriptor does not contain a qsdr segment"
+        );
+        Ok(segment)
+    }
+
+    fn init(fd: OwnedFd, len: usize) -> Result<Segment> {
+        let len = len
+            .checked_add(size_of::<Header>())
+            .context("segment is too large")?;
+        if unsafe { libc::ftruncate(fd.as_raw_fd(), libc::off_t::try_from(len)?) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("could not resize segment");
+        }
+        let ptr = map(&fd, len)?;
+        // SAFETY: the mapping is at least as large as the header, and it is
+        // page aligned
+        unsafe {
+            ptr.cast::<Header>().write(Header {
+                magic: MAGIC,
+                len: len as u64,
+                next: AtomicU64::new(size_of::<Header>() as u64),
+            })
+        };
+        Ok(Segment { ptr, len, fd })
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { self.ptr.cast::<Header>().as_ref() }
+    }
+
+    /// Returns the size of the segment in bytes.
+    ///
+    /// This includes a header used by qsdr.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the number of bytes that can still be allocated, disregarding
+    /// alignment.
+    pub fn available(&self) -> usize {
+        self.len - self.header().next.load(Relaxed) as usize
+    }
+
+    /// Allocates memory in the segment, returning its offset.
+    pub fn alloc(&self, layout: Layout) -> Result<usize> {
+        let mut next = self.header().next.load(Relaxed);
+        loop {
+            let offset = next.next_multiple_of(layout.align() as u64);
+            let end = offset + layout.size() as u64;
+            anyhow::ensure!(
+                end <= self.len as u64,
+                "segment is full (allocation of {} bytes, {} bytes available)",
+                layout.size(),
+                self.available()
+            );
+            match self
+                .header()
+                .next
+                .compare_exchange_weak(next, end, Relaxed, Relaxed)
+            {
+                Ok(_) => return Ok(offset as usize),
+                Err(current) => next = current,
+            }
+        }
+    }
+
+    /// Returns a pointer to the given offset of the segment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the offset is outside the segment.
+    pub fn ptr(&self, offset: usize) -> NonNull<u8> {
+        assert!(offset <= self.len, "offset {offset} is outside the segment");
+        unsafe { self.ptr.add(offset) }
+    }
+
+    /// Returns the offset of a pointer to the segment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pointer does not point to the segment.
+    pub fn offset(&self, ptr: NonNull<u8>) -> usize {
+        let offset = (ptr.as_ptr() as usize).wrapping_sub(self.ptr.as_ptr() as usize);
+        assert!(offset <= self.len, "pointer is outside the segment");
+        offset
+    }
+}
+
+/// Channel endpoint in a shared memory segment.
+///
+/// This is obtained with the `into_raw` method of an endpoint and it contains
+/// all the information needed to recreate the endpoint with `from_raw`,
+/// possibly in another process that has mapped the same segment. It is plain
+/// data that can be sent to the other process by any means.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct RawEndpoint {
+    /// Offset of the channel in the segment.
+    pub offset: usize,
+    /// Number of slots of the channel.
+    pub size: u32,
+    /// Index of the next slot that the endpoint accesses.
+    pub idx: u32,
+}
+
+impl AsFd for Segment {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Drop for Segment {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr.as_ptr().cast(), self.len) };
+    }
+}
+
+fn shm_open(name: &str, flags: libc::c_int) -> Result<OwnedFd> {
+    let c_name = CString::new(name).context("invalid segment name")?;
+    let fd = unsafe { libc::shm_open(c_name.as_ptr(), flags | libc::O_CLOEXEC, 0o600) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("could not open shared memory object {name}"));
+    }
+    // SAFETY: fd is a file descriptor that we own
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn map(fd: &OwnedFd, len: usize) -> Result<NonNull<u8>> {
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            fd.as_raw_fd(),
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(std::io::Error::last_os_error()).context("could not map segment");
+    }
+    Ok(NonNull::new(ptr.cast()).unwrap())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn alloc_and_map_again() {
+        let segment = Segment::new("test", 4096).unwrap();
+        let offset = segment.alloc(Layout::new::<u64>()).unwrap();
+        assert_eq!(offset % align_of::<u64>(), 0);
+        unsafe { segment.ptr(offset).cast::<u64>().write(0x1234) };
+        let other = Segment::from_fd(segment.as_fd().try_clone_to_owned().unwrap()).unwrap();
+        assert_ne!(segment.ptr(0), other.ptr(0));
+        assert_eq!(unsafe { other.ptr(offset).cast::<u64>().read() }, 0x1234);
+        // allocations made through any mapping are seen by the others
+        let available = segment.available();
+        other.alloc(Layout::new::<u8>()).unwrap();
+        assert_eq!(segment.available(), available - 1);
+        let err = segment
+            .alloc(Layout::array::<u8>(8192).unwrap())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("segment is full"), "{err}");
+    }
+
+    #[test]
+    fn named() {
+        let name = format!("/qsdr-test-{}", std::process::id());
+        let segment = Segment::create_named(&name, 4096).unwrap();
+        assert!(Segment::create_named(&name, 4096).is_err());
+        let other = Segment::open_named(&name).unwrap();
+        Segment::unlink(&name).unwrap();
+        assert_eq!(segment.len(), other.len());
+        assert!(Segment::open_named(&name).is_err());
+    }
+}
@@ -1,11 +1,23 @@
 use futures::executor::block_on;
 use qsdr::{
-    blocks::basic::{RefClone, SnapshotSink, SnapshotSource, SourceIterator},
-    buffers::CacheAlignedBuffer,
+    BlockError, FlowgraphError, QuantumSnapshot, Select, Selected2, ValidatedFlowgraph,
+    blocks::basic::{
+        NullSink, Passthrough, RefClone, SnapshotSink, SnapshotSource, SourceIterator,
+    },
+    buffers::{CacheAlignedBuffer, RingBuffer},
+    channel::{Adaptive, BusySpin, SpinYield},
+    channels::{
+        FutexMpsc, FutexSpBroadcast, FutexSpsc, FutexSpscRef, FutexSpscmrRef, FutexWorkQueue,
+        WorkQueue,
+    },
     prelude::*,
-    scheduler::{run, sequence2, sequence4},
+    scheduler::{BlockThreads, CpuGroups, ErrorPolicy, run, sequence2, sequence3, sequence4},
 };
 use rand::prelude::*;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU32, Ordering},
+};
 
 #[test]
 fn spbroadcast() {
@@ -104,3 +116,751 @@ fn spbroadcast() {
 
     check_elements!(rx0, rx1, rx2);
 }
+
+#[test]
+fn run_passthrough_chain() {
+    type B = CacheAlignedBuffer<u32>;
+    let buffer_size = 1024;
+    let num_buffers = 4;
+    let num_elements = 1000;
+    let num_passthroughs = 12;
+    let mut rng = rand::rng();
+
+    let elements = std::iter::repeat_with(|| {
+        std::iter::repeat_with(|| rng.random())
+            .take(buffer_size)
+            .collect::<Vec<_>>()
+            .into()
+    })
+    .take(num_elements)
+    .collect::<Vec<_>>();
+    let buffers =
+        std::iter::repeat_with(|| Quantum::new(B::from_fn(buffer_size, |_| rng.random())))
+            .take(num_buffers)
+            .collect::<Vec<_>>()
+            .into_iter();
+
+    let mut fg = Flowgraph::new();
+    let mut circ = fg.new_circuit(buffers);
+    let source = fg.add_block(SnapshotSource::<B, _>::new(SourceIterator(
+        elements.clone().into_iter(),
+    )));
+    let mut last = fg.add_block(Passthrough::<_, Spsc, Spsc>::new());
+    fg.connect(&mut circ, source.output(), last.input())
+        .unwrap();
+    for _ in 1..num_passthroughs - 1 {
+        let passthrough = fg.add_block(Passthrough::<_, Spsc, Spsc>::new());
+        fg.connect(&mut circ, last.output(), passthrough.input())
+            .unwrap();
+        last = passthrough;
+    }
+    let passthrough = fg.add_block(Passthrough::<_, Spsc, SpscRef>::new());
+    fg.connect(&mut circ, last.output(), passthrough.input())
+        .unwrap();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let sink = fg.add_block(SnapshotSink::<B, _>::new(tx));
+    fg.connect_with_return(
+        &mut circ,
+        passthrough.output(),
+        sink.input(),
+        source.input(),
+    )
+    .unwrap();
+
+    fg.validate().unwrap().run().unwrap();
+
+    let mut rx = rx.into_iter();
+    for (n, element) in elements.iter().enumerate() {
+        let out = rx.next().unwrap();
+        assert_eq!(*element, out, "element {n} mismatch");
+    }
+    assert!(rx.next().is_none());
+}
+
+#[test]
+fn run_with_wait_strategies() {
+    type B = CacheAlignedBuffer<u32>;
+    type Forward = Spsc<SpinYield<16>>;
+    type Backward = SpscRef<BusySpin>;
+    let buffer_size = 256;
+    let num_buffers = 4;
+    let num_elements = 100;
+    let mut rng = rand::rng();
+
+    let elements = std::iter::repeat_with(|| {
+        std::iter::repeat_with(|| rng.random())
+            .take(buffer_size)
+            .collect::<Vec<_>>()
+            .into()
+    })
+    .take(num_elements)
+    .collect::<Vec<_>>();
+    let buffers = std::iter::repeat_with(|| Quantum::new(B::new(buffer_size)))
+        .take(num_buffers)
+        .collect::<Vec<_>>()
+        .into_iter();
+
+    let mut fg = Flowgraph::new();
+    let mut circ = fg.new_circuit(buffers);
+    let source = fg.add_block(SnapshotSource::<B, _, Spsc<Adaptive<64>>, Forward>::new(
+        SourceIterator(elements.clone().into_iter()),
+    ));
+    let passthrough = fg.add_block(Passthrough::<_, Forward, Backward>::new());
+    let (tx, rx) = std::sync::mpsc::channel();
+    let sink = fg.add_block(SnapshotSink::<B, _, Backward>::new(tx));
+    fg.connect(&mut circ, source.output(), passthrough.input())
+        .unwrap();
+    fg.connect_with_return(
+        &mut circ,
+        passthrough.output(),
+        sink.input(),
+        source.input(),
+    )
+    .unwrap();
+
+    fg.validate().unwrap().run().unwrap();
+
+    assert_eq!(rx.into_iter().collect::<Vec<_>>(), elements);
+}
+
+#[test]
+fn run_blocking_futex_channels() {
+    type B = CacheAlignedBuffer<u32>;
+    let buffer_size = 256;
+    let num_buffers = 4;
+    let num_elements = 1000;
+    let mut rng = rand::rng();
+
+    let elements = std::iter::repeat_with(|| {
+        std::iter::repeat_with(|| rng.random())
+            .take(buffer_size)
+            .collect::<Vec<_>>()
+            .into()
+    })
+    .take(num_elements)
+    .collect::<Vec<_>>();
+    let mut make_buffers = || {
+        std::iter::repeat_with(|| Quantum::new(B::from_fn(buffer_size, |_| rng.random())))
+            .take(num_buffers)
+            .collect::<Vec<_>>()
+            .into_iter()
+    };
+
+    let mut fg = Flowgraph::new();
+    let mut threads = BlockThreads::new();
+    let source = fg.add_block(SnapshotSource::<B, _, FutexMpsc, FutexSpBroadcast>::new(
+        SourceIterator(elements.clone().into_iter()),
+    ));
+    threads.add(&source);
+    let mut circ0 = fg.new_circuit(make_buffers());
+    let receivers = [(); 2].map(|()| {
+        let ref_clone = fg.add_block(RefClone::<B, FutexSpBroadcast, FutexSpsc, FutexSpsc>::new());
+        fg.connect_with_return(
+            &mut circ0,
+            source.output(),
+            ref_clone.input(),
+            source.input(),
+        )
+        .unwrap();
+        let passthrough = fg.add_block(Passthrough::<_, FutexSpsc, FutexSpscRef>::new());
+        let (tx, rx) = std::sync::mpsc::channel();
+        let sink = fg.add_block(SnapshotSink::<B, _, FutexSpscRef>::new(tx));
+        let mut circ = fg.new_circuit(make_buffers());
+        fg.connect(&mut circ, ref_clone.output(), passthrough.input())
+            .unwrap();
+        fg.connect_with_return(
+            &mut circ,
+            passthrough.output(),
+            sink.input(),
+            ref_clone.source(),
+        )
+        .unwrap();
+        threads.add(&ref_clone).add(&passthrough).add(&sink);
+        rx
+    });
+
+    fg.validate().unwrap().run_blocking(&threads).unwrap();
+
+    for rx in receivers {
+        assert_eq!(rx.into_iter().collect::<Vec<_>>(), elements);
+    }
+}
+
+#[test]
+fn work_queue() {
+    type B = CacheAlignedBuffer<u32>;
+    let buffer_size = 256;
+    let num_buffers = 8;
+    let num_elements = 1000;
+    let num_workers = 3;
+    let mut rng = rand::rng();
+
+    let elements = std::iter::repeat_with(|| {
+        std::iter::repeat_with(|| rng.random())
+            .take(buffer_size)
+            .collect::<Vec<_>>()
+            .into()
+    })
+    .take(num_elements)
+    .collect::<Vec<QuantumSnapshot<u32>>>();
+
+    macro_rules! run_work_queue {
+        ($queue:ty, $return:ty, $ref:ty, $run:expr) => {{
+            let buffers = std::iter::repeat_with(|| Quantum::new(B::new(buffer_size)))
+                .take(num_buffers)
+                .collect::<Vec<_>>()
+                .into_iter();
+            let mut fg = Flowgraph::new();
+            let mut threads = BlockThreads::new();
+            let mut circ = fg.new_circuit(buffers);
+            let source = fg.add_block(SnapshotSource::<B, _, $return, $queue>::new(
+                SourceIterator(elements.clone().into_iter()),
+            ));
+            threads.add(&source);
+            let (tx, rx) = std::sync::mpsc::channel();
+            for _ in 0..num_workers {
+                let worker = fg.add_block(Passthrough::<_, $queue, $ref>::new());
+                let sink = fg.add_block(SnapshotSink::<B, _, $ref>::new(tx.clone()));
+                fg.connect(&mut circ, source.output(), worker.input())
+                    .unwrap();
+                fg.connect_with_return(&mut circ, worker.output(), sink.input(), source.input())
+                    .unwrap();
+                threads.add(&worker).add(&sink);
+            }
+            drop(tx);
+
+            ($run)(fg.validate().unwrap(), &threads).unwrap();
+
+            // the order of the elements is not preserved across the workers
+            let mut received = rx.into_iter().collect::<Vec<_>>();
+            assert_eq!(received.len(), elements.len());
+            let mut expected = elements.clone();
+            received.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+            expected.sort_by(|a, b| a.as_slice().cmp(b.as_slice()));
+            assert_eq!(received, expected);
+        }};
+    }
+
+    run_work_queue!(
+        WorkQueue,
+        Mpsc,
+        SpscmrRef,
+        |fg: ValidatedFlowgraph, _: &BlockThreads| fg.run()
+    );
+    run_work_queue!(
+        FutexWorkQueue,
+        FutexMpsc,
+        FutexSpscmrRef,
+        |fg: ValidatedFlowgraph, threads: &BlockThreads| fg.run_blocking(threads)
+    );
+}
+
+#[test]
+fn run_multi_core_passthrough_chain() {
+    type B = CacheAlignedBuffer<u32>;
+    let buffer_size = 1024;
+    let num_buffers = 4;
+    let num_elements = 1000;
+    let mut rng = rand::rng();
+    let num_cpus = std::thread::available_parallelism().unwrap().get();
+
+    let elements = std::iter::repeat_with(|| {
+        std::iter::repeat_with(|| rng.random())
+            .take(buffer_size)
+            .collect::<Vec<_>>()
+            .into()
+    })
+    .take(num_elements)
+    .collect::<Vec<_>>();
+    let buffers =
+        std::iter::repeat_with(|| Quantum::new(B::from_fn(buffer_size, |_| rng.random())))
+            .take(num_buffers)
+            .collect::<Vec<_>>()
+            .into_iter();
+
+    let mut fg = Flowgraph::new();
+    let mut circ = fg.new_circuit(buffers);
+    let source = fg.add_block(SnapshotSource::<B, _>::new(SourceIterator(
+        elements.clone().into_iter(),
+    )));
+    let passthrough0 = fg.add_block(Passthrough::<_, Spsc, Spsc>::new());
+    let passthrough1 = fg.add_block(Passthrough::<_, Spsc, SpscRef>::new());
+    let (tx, rx) = std::sync::mpsc::channel();
+    let sink = fg.add_block(SnapshotSink::<B, _>::new(tx));
+    fg.connect(&mut circ, source.output(), passthrough0.input())
+        .unwrap();
+    fg.connect(&mut circ, passthrough0.output(), passthrough1.input())
+        .unwrap();
+    fg.connect_with_return(
+        &mut circ,
+        passthrough1.output(),
+        sink.input(),
+        source.input(),
+    )
+    .unwrap();
+
+    let mut groups = CpuGroups::new();
+    groups
+        .add(0, &source)
+        .add(0, &passthrough0)
+        .add(1 % num_cpus, &passthrough1)
+        .add(1 % num_cpus, &sink);
+    fg.validate().unwrap().run_multi_core(&groups).unwrap();
+
+    let mut rx = rx.into_iter();
+    for (n, element) in elements.iter().enumerate() {
+        let out = rx.next().unwrap();
+        assert_eq!(*element, out, "element {n} mismatch");
+    }
+    assert!(rx.next().is_none());
+}
+
+// Runs an endless source, a passthrough and a sink, stops the flowgraph and
+// checks that all the quanta produced by the source reach the sink.
+fn check_stop_drains_flowgraph(multi_core: bool) {
+    type B = CacheAlignedBuffer<u32>;
+    let buffer_size = 1024;
+    let num_buffers = 4;
+    let min_elements = 100;
+
+    // endless source that counts the elements it produces
+    let produced = Arc::new(AtomicU32::new(0));
+    let elements = {
+        let produced = Arc::clone(&produced);
+        std::iter::repeat_with(move || {
+            let n = produced.fetch_add(1, Ordering::SeqCst);
+            vec![n; buffer_size].into()
+        })
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    // the flowgraph is not Send, so it is built in the thread that runs it,
+    // which sends back the stop handle
+    let (stop_tx, stop_rx) = std::sync::mpsc::channel();
+    let runner = std::thread::spawn(move || {
+        let buffers = std::iter::repeat_with(|| Quantum::new(B::new(buffer_size)))
+            .take(num_buffers)
+            .collect::<Vec<_>>()
+            .into_iter();
+        let mut fg = Flowgraph::new();
+        let mut circ = fg.new_circuit(buffers);
+        let source = fg.add_block(SnapshotSource::<B, _>::new(SourceIterator(elements)));
+        let passthrough = fg.add_block(Passthrough::<_, Spsc, SpscRef>::new());
+        let sink = fg.add_block(SnapshotSink::<B, _>::new(tx));
+        fg.connect(&mut circ, source.output(), passthrough.input())
+            .unwrap();
+        fg.connect_with_return(
+            &mut circ,
+            passthrough.output(),
+            sink.input(),
+            source.input(),
+        )
+        .unwrap();
+        // the source shares its CPU with the passthrough, which needs to keep
+        // running after the source has stopped
+        let num_cpus = std::thread::available_parallelism().unwrap().get();
+        let mut groups = CpuGroups::new();
+        groups
+            .add(0, &source)
+            .add(0, &passthrough)
+            .add(1 % num_cpus, &sink);
+        let fg = fg.validate().unwrap();
+        stop_tx.send(fg.stop_handle()).unwrap();
+        if multi_core {
+            fg.run_multi_core(&groups)
+        } else {
+            fg.run()
+        }
+    });
+    let stop = stop_rx.recv().unwrap();
+
+    let mut received = rx.iter().take(min_elements).collect::<Vec<_>>();
+    stop.stop();
+    runner.join().unwrap().unwrap();
+    received.extend(rx.iter());
+
+    // all the quanta produced by the source reach the sink
+    assert_eq!(received.len(), produced.load(Ordering::SeqCst) as usize);
+    for (n, element) in received.iter().enumerate() {
+        let expected: QuantumSnapshot<u32> = vec![n as u32; buffer_size].into();
+        assert_eq!(*element, expected, "element {n} mismatch");
+    }
+}
+
+#[test]
+fn stop_handle_drains_flowgraph() {
+    check_stop_drains_flowgraph(false);
+}
+
+#[test]
+fn stop_handle_drains_multi_core_flowgraph() {
+    check_stop_drains_flowgraph(true);
+}
+
+// Sends the snapshots of the quanta of two circuits, tagged with the index of
+// their input.
+#[derive(Block, Debug)]
+#[work(WorkCustom)]
+struct MergeSink {
+    #[port]
+    input0: PortRefInQ<CacheAlignedBuffer<u32>, SpscRef>,
+    #[port]
+    input1: PortRefInQ<CacheAlignedBuffer<u32>, SpscRef>,
+    select: Select,
+    sink: std::sync::mpsc::Sender<(usize, QuantumSnapshot<u32>)>,
+}
+
+impl MergeSink {
+    fn new(sink: std::sync::mpsc::Sender<(usize, QuantumSnapshot<u32>)>) -> Self {
+        Self {
+            input0: Default::default(),
+            input1: Default::default(),
+            select: Select::new(),
+            sink,
+        }
+    }
+}
+
+impl WorkCustom for MergeSink {
+    async fn work_custom(&mut self, channels: &mut Self::Channels) -> Result<BlockWorkStatus> {
+        let item = match self
+            .select
+            .select2(&mut channels.input0, &mut channels.input1)
+            .await
+        {
+            Some(Selected2::Input0(quantum)) => (0, quantum.as_ref().snapshot()),
+            Some(Selected2::Input1(quantum)) => (1, quantum.as_ref().snapshot()),
+            None => return Ok(BlockWorkStatus::Done),
+        };
+        self.sink.send(item)?;
+        Ok(BlockWorkStatus::Run)
+    }
+}
+
+#[test]
+fn select_two_circuits() {
+    type B = CacheAlignedBuffer<u32>;
+    let buffer_size = 64;
+    let num_buffers = 4;
+    let num_elements = [1000, 300];
+
+    let mut fg = Flowgraph::new();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let merge = fg.add_block(MergeSink::new(tx));
+    for (input, &num_elements) in num_elements.iter().enumerate() {
+        let buffers = std::iter::repeat_with(|| Quantum::new(B::new(buffer_size)))
+            .take(num_buffers)
+            .collect::<Vec<_>>()
+            .into_iter();
+        let elements = (0..num_elements).map(move |n| vec![n; buffer_size].into());
+        let mut circ = fg.new_circuit(buffers);
+        let source = fg.add_block(SnapshotSource::<B, _, Spsc, SpscRef>::new(SourceIterator(
+            elements,
+        )));
+        let merge_input = if input == 0 {
+            merge.input0()
+        } else {
+            merge.input1()
+        };
+        fg.connect_with_return(&mut circ, source.output(), merge_input, source.input())
+            .unwrap();
+    }
+
+    fg.validate().unwrap().run().unwrap();
+
+    let received = rx.into_iter().collect::<Vec<_>>();
+    assert_eq!(received.len(), num_elements.iter().sum::<u32>() as usize);
+    for (input, &num_elements) in num_elements.iter().enumerate() {
+        // the quanta of each circuit are received in order
+        let expected = (0..num_elements)
+            .map(|n| vec![n; buffer_size].into())
+            .collect::<Vec<QuantumSnapshot<u32>>>();
+        let received = received
+            .iter()
+            .filter(|(n, _)| *n == input)
+            .map(|(_, snapshot)| snapshot.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(received, expected);
+    }
+}
+
+// Sends the window formed by the history from the previous quantum and each
+// quantum. Each quantum is kept until the next one has been processed.
+#[derive(Block, Debug)]
+#[work(WorkCustom)]
+struct HistorySink {
+    #[port]
+    input: PortInQ<RingBuffer<u32>, Spsc>,
+    #[port]
+    output: PortOutQ<RingBuffer<u32>, SpscRef>,
+    history_len: usize,
+    previous: Option<Quantum<RingBuffer<u32>>>,
+    sink: std::sync::mpsc::Sender<Vec<u32>>,
+}
+
+impl HistorySink {
+    fn new(history_len: usize, sink: std::sync::mpsc::Sender<Vec<u32>>) -> Self {
+        Self {
+            input: Default::default(),
+            output: Default::default(),
+            history_len,
+            previous: None,
+            sink,
+        }
+    }
+}
+
+impl WorkCustom for HistorySink {
+    async fn work_custom(&mut self, channels: &mut Self::Channels) -> Result<BlockWorkStatus> {
+        let Some(quantum) = channels.input.recv().await else {
+            if let Some(previous) = self.previous.take() {
+                channels.output.send(previous);
+            }
+            return Ok(BlockWorkStatus::Done);
+        };
+        if let Some(previous) = self.previous.take() {
+            self.sink
+                .send(quantum.window(&previous, self.history_len).to_vec())?;
+            channels.output.send(previous);
+        }
+        self.previous = Some(quantum);
+        Ok(BlockWorkStatus::Run)
+    }
+}
+
+#[test]
+fn ring_buffer_history() {
+    type B = RingBuffer<u32>;
+    let buffer_size = 1024;
+    let num_buffers = 4;
+    let num_elements = 100;
+    let history_len = 31;
+    let buffers = B::ring(num_buffers, buffer_size, 0)
+        .unwrap()
+        .into_iter()
+        .map(Quantum::new)
+        .collect::<Vec<_>>()
+        .into_iter();
+    let elements = (0..num_elements).map(move |n| {
+        (n * buffer_size..(n + 1) * buffer_size)
+            .map(|x| x as u32)
+            .collect::<Vec<_>>()
+            .into()
+    });
+
+    let mut fg = Flowgraph::new();
+    let mut circ = fg.new_circuit(buffers);
+    let source = fg.add_block(SnapshotSource::<B, _>::new(SourceIterator(elements)));
+    let (tx, rx) = std::sync::mpsc::channel();
+    let history = fg.add_block(HistorySink::new(history_len, tx));
+    fg.connect(&mut circ, source.output(), history.input())
+        .unwrap();
+    let sink = fg.add_block(NullSink::new());
+    fg.connect_with_return(&mut circ, history.output(), sink.input(), source.input())
+        .unwrap();
+    fg.validate().unwrap().run().unwrap();
+
+    let received = rx.into_iter().collect::<Vec<_>>();
+    assert_eq!(received.len(), num_elements - 1);
+    for (n, window) in received.iter().enumerate() {
+        let start = (n + 1) * buffer_size - history_len;
+        let expected = (start..(n + 2) * buffer_size)
+            .map(|x| x as u32)
+            .collect::<Vec<_>>();
+        assert_eq!(*window, expected, "window {n} mismatch");
+    }
+}
+
+// Passes through a number of items and then fails.
+#[derive(Block, Debug)]
+#[work(WorkInPlace)]
+struct FailAfter<T> {
+    #[port]
+    input: PortIn<T, Spsc>,
+    #[port]
+    output: PortOut<T, SpscRef>,
+    remaining: usize,
+}
+
+impl<T> FailAfter<T> {
+    fn new(count: usize) -> Self {
+        Self {
+            input: Default::default(),
+            output: Default::default(),
+            remaining: count,
+        }
+    }
+}
+
+impl<T> WorkInPlace<T> for FailAfter<T> {
+    async fn work_in_place(&mut self, _: &mut T) -> Result<WorkStatus> {
+        anyhow::ensure!(self.remaining > 0, "failing on purpose");
+        self.remaining -= 1;
+        Ok(Run)
+    }
+}
+
+fn run_failing_flowgraph(policy: ErrorPolicy) -> (anyhow::Error, Vec<QuantumSnapshot<u32>>) {
+    type B = CacheAlignedBuffer<u32>;
+    let buffer_size = 1024;
+    let num_buffers = 4;
+    let buffers = std::iter::repeat_with(|| Quantum::new(B::new(buffer_size)))
+        .take(num_buffers)
+        .collect::<Vec<_>>()
+        .into_iter();
+    let elements = (0..).map(move |n| vec![n; buffer_size].into());
+
+    let mut fg = Flowgraph::new();
+    let mut circ = fg.new_circuit(buffers);
+    let source = fg.add_block(SnapshotSource::<B, _>::new(SourceIterator(elements)));
+    let fail = fg.add_block_named("fail", FailAfter::new(10));
+    let (tx, rx) = std::sync::mpsc::channel();
+    let sink = fg.add_block(SnapshotSink::<B, _>::new(tx));
+    fg.connect(&mut circ, source.output(), fail.input())
+        .unwrap();
+    fg.connect_with_return(&mut circ, fail.output(), sink.input(), source.input())
+        .unwrap();
+    let mut fg = fg.validate().unwrap();
+    fg.set_error_policy(policy);
+    let err = fg.run().unwrap_err();
+    (err, rx.into_iter().collect())
+}
+
+#[test]
+fn error_policy_abort() {
+    let (err, _) = run_failing_flowgraph(ErrorPolicy::Abort);
+    let fg_err = err.downcast_ref::<FlowgraphError>().unwrap();
+    assert_eq!(fg_err.policy(), ErrorPolicy::Abort);
+    assert_eq!(fg_err.errors().len(), 1);
+    let block_err = &fg_err.errors()[0];
+    assert_eq!(block_err.block().name(), "fail");
+    assert!(block_err.block().type_name().contains("FailAfter<"));
+    assert_eq!(block_err.error().to_string(), "failing on purpose");
+    assert!(format!("{err:#}").ends_with(" failed: failing on purpose"));
+}
+
+#[test]
+fn error_policy_drain() {
+    let (err, received) = run_failing_flowgraph(ErrorPolicy::Drain);
+    let fg_err = err.downcast_ref::<FlowgraphError>().unwrap();
+    assert_eq!(fg_err.policy(), ErrorPolicy::Drain);
+    assert_eq!(fg_err.errors().len(), 1);
+    assert_eq!(fg_err.errors()[0].block().name(), "fail");
+    // the quanta that went through the failing block reach the sink
+    assert_eq!(received.len(), 10);
+    for (n, element) in received.iter().enumerate() {
+        let expected: QuantumSnapshot<u32> = vec![n as u32; 1024].into();
+        assert_eq!(*element, expected, "element {n} mismatch");
+    }
+}
+
+#[test]
+fn extracted_block_error() {
+    type B = CacheAlignedBuffer<u32>;
+    let buffers = std::iter::repeat_with(|| Quantum::new(B::new(16)))
+        .take(4)
+        .collect::<Vec<_>>()
+        .into_iter();
+    let elements = (0..).map(|n| vec![n; 16].into());
+
+    let mut fg = Flowgraph::new();
+    let mut circ = fg.new_circuit(buffers);
+    let source = fg.add_block(SnapshotSource::<B, _>::new(SourceIterator(elements)));
+    let fail = fg.add_block(FailAfter::new(3));
+    let (tx, _rx) = std::sync::mpsc::channel();
+    let sink = fg.add_block(SnapshotSink::<B, _>::new(tx));
+    fg.connect(&mut circ, source.output(), fail.input())
+        .unwrap();
+    fg.connect_with_return(&mut circ, fail.output(), sink.input(), source.input())
+        .unwrap();
+    let mut fg = fg.validate().unwrap();
+    let source = fg.extract_block(source).unwrap();
+    let fail = fg.extract_block(fail).unwrap();
+    let sink = fg.extract_block(sink).unwrap();
+
+    let err = block_on(run(sequence3(
+        source.into_stream(),
+        fail.into_stream(),
+        sink.into_stream(),
+    )))
+    .unwrap_err();
+    let block_err = err.downcast_ref::<BlockError>().unwrap();
+    assert_eq!(
+        block_err.block().name(),
+        "FailAfter<Quantum<CacheAlignedBuffer<u32>>>"
+    );
+}
+
+// Counts the quanta that it receives. The counter is not Send, so the block
+// can only be run on the thread where the flowgraph is built.
+#[derive(Block, Debug)]
+#[work(WorkSink)]
+struct CountSink<T> {
+    #[port]
+    input: PortRefIn<T, SpscRef>,
+    count: std::rc::Rc<std::cell::Cell<usize>>,
+}
+
+impl<T> CountSink<T> {
+    fn new() -> Self {
+        Self {
+            input: Default::default(),
+            count: Default::default(),
+        }
+    }
+}
+
+impl<T> WorkSink<T> for CountSink<T> {
+    async fn work_sink(&mut self, _: &T) -> Result<BlockWorkStatus> {
+        self.count.set(self.count.get() + 1);
+        Ok(BlockWorkStatus::Run)
+    }
+}
+
+#[test]
+fn non_send_block() {
+    type B = CacheAlignedBuffer<u32>;
+    let num_elements = 100;
+    let buffers = std::iter::repeat_with(|| Quantum::new(B::new(16)))
+        .take(4)
+        .collect::<Vec<_>>()
+        .into_iter();
+    let elements = (0..num_elements).map(|n| vec![n; 16].into());
+
+    let mut fg = Flowgraph::new();
+    let mut circ = fg.new_circuit(buffers);
+    let source = fg.add_block(SnapshotSource::<B, _, Spsc, SpscRef>::new(SourceIterator(
+        elements,
+    )));
+    let sink = fg.add_block(CountSink::new());
+    fg.connect_with_return(&mut circ, source.output(), sink.input(), source.input())
+        .unwrap();
+    let count = sink.with_block(|sink| std::rc::Rc::clone(&sink.count));
+    fg.validate().unwrap().run().unwrap();
+
+    assert_eq!(count.get(), num_elements as usize);
+}
+
+#[test]
+fn run_rejects_blocking_channels() {
+    type B = CacheAlignedBuffer<u32>;
+    let buffers = std::iter::repeat_with(|| Quantum::new(B::new(16)))
+        .take(4)
+        .collect::<Vec<_>>()
+        .into_iter();
+    let elements = (0..).map(|n| vec![n; 16].into());
+
+    let mut fg = Flowgraph::new();
+    let mut circ = fg.new_circuit(buffers);
+    let source = fg.add_block(SnapshotSource::<B, _, FutexSpsc, FutexSpscRef>::new(
+        SourceIterator(elements),
+    ));
+    let sink = fg.add_block(NullSink::<_, FutexSpscRef>::new());
+    fg.connect_with_return(&mut circ, source.output(), sink.input(), source.input())
+        .unwrap();
+    let err = fg.validate().unwrap().run().unwrap_err().to_string();
+    assert!(
+        err.contains("has the blocking input input, so it can only be run with run_blocking"),
+        "unexpected error: {err}"
+    );
+}